@@ -1,112 +0,0 @@
-/*
- Navicat Premium Data Transfer
-
- Source Server         : 101.35.230.139
- Source Server Type    : MySQL
- Source Server Version : 100612
- Source Host           : 101.35.230.139:3306
- Source Schema         : blog
-
- Target Server Type    : MySQL
- Target Server Version : 100612
- File Encoding         : 65001
-
- Date: 05/02/2026 11:46:41
-*/
-
-SET NAMES utf8mb4;
-SET FOREIGN_KEY_CHECKS = 0;
-
--- ----------------------------
--- Table structure for article_tb
--- ----------------------------
-DROP TABLE IF EXISTS `article_tb`;
-CREATE TABLE `article_tb`  (
-  `id` int(11) NOT NULL AUTO_INCREMENT,
-  `user_id` int(11) NULL DEFAULT NULL,
-  `create_time` datetime NULL DEFAULT NULL,
-  `update_time` datetime NULL DEFAULT NULL,
-  `content` longtext CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL,
-  `title` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL DEFAULT NULL,
-  `level` int(255) NULL DEFAULT NULL,
-  `tag_id` int(11) NULL DEFAULT NULL,
-  `article_state` int(255) NULL DEFAULT 1,
-  PRIMARY KEY (`id`) USING BTREE,
-  INDEX `idx_article_state_level`(`article_state`, `level`) USING BTREE,
-  INDEX `idx_article_update_time`(`update_time`) USING BTREE,
-  INDEX `idx_article_user_id`(`user_id`) USING BTREE
-) ENGINE = InnoDB AUTO_INCREMENT = 404 CHARACTER SET = utf8mb4 COLLATE = utf8mb4_general_ci ROW_FORMAT = Dynamic;
-
--- ----------------------------
--- Table structure for comment_tb
--- ----------------------------
-DROP TABLE IF EXISTS `comment_tb`;
-CREATE TABLE `comment_tb`  (
-  `id` int(11) NOT NULL AUTO_INCREMENT,
-  `user_id` int(11) NULL DEFAULT NULL,
-  `article_id` int(11) NULL DEFAULT NULL,
-  `create_time` datetime NULL DEFAULT NULL,
-  `update_time` datetime NULL DEFAULT NULL,
-  `comment` longtext CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL,
-  `md_content` longtext CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL,
-  PRIMARY KEY (`id`) USING BTREE,
-  INDEX `idx_comment_article_id`(`article_id`) USING BTREE,
-  INDEX `idx_comment_user_id`(`user_id`) USING BTREE
-) ENGINE = InnoDB AUTO_INCREMENT = 52 CHARACTER SET = utf8mb4 COLLATE = utf8mb4_general_ci ROW_FORMAT = Dynamic;
-
--- ----------------------------
--- Table structure for level_tb
--- ----------------------------
-DROP TABLE IF EXISTS `level_tb`;
-CREATE TABLE `level_tb`  (
-  `id` int(11) NOT NULL AUTO_INCREMENT,
-  `level` int(255) NULL DEFAULT NULL,
-  `create_time` datetime NULL DEFAULT NULL,
-  `name` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL DEFAULT NULL,
-  PRIMARY KEY (`id`) USING BTREE
-) ENGINE = InnoDB AUTO_INCREMENT = 4 CHARACTER SET = utf8mb4 COLLATE = utf8mb4_general_ci ROW_FORMAT = Dynamic;
-
--- ----------------------------
--- Table structure for tag_tb
--- ----------------------------
-DROP TABLE IF EXISTS `tag_tb`;
-CREATE TABLE `tag_tb`  (
-  `id` int(11) NOT NULL AUTO_INCREMENT,
-  `name` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL DEFAULT NULL,
-  `create_time` datetime NULL DEFAULT NULL,
-  PRIMARY KEY (`id`) USING BTREE,
-  INDEX `idx_tag_name`(`name`(50)) USING BTREE
-) ENGINE = InnoDB AUTO_INCREMENT = 8 CHARACTER SET = utf8mb4 COLLATE = utf8mb4_general_ci ROW_FORMAT = Dynamic;
-
--- ----------------------------
--- Table structure for user_tb
--- ----------------------------
-DROP TABLE IF EXISTS `user_tb`;
-CREATE TABLE `user_tb`  (
-  `id` int(11) NOT NULL AUTO_INCREMENT,
-  `name` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL DEFAULT NULL,
-  `create_time` datetime NULL DEFAULT NULL,
-  `update_time` datetime NULL DEFAULT NULL,
-  `email` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL DEFAULT NULL,
-  `password` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL DEFAULT NULL,
-  `privilege` smallint(255) NULL DEFAULT NULL,
-  `avatar` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL DEFAULT '',
-  PRIMARY KEY (`id`) USING BTREE,
-  INDEX `idx_user_name`(`name`(50)) USING BTREE,
-  INDEX `idx_user_email`(`email`(100)) USING BTREE
-) ENGINE = InnoDB AUTO_INCREMENT = 26 CHARACTER SET = utf8mb4 COLLATE = utf8mb4_general_ci ROW_FORMAT = Dynamic;
-
--- ----------------------------
--- Table structure for view_tb
--- ----------------------------
-DROP TABLE IF EXISTS `view_tb`;
-CREATE TABLE `view_tb`  (
-  `id` int(11) NOT NULL AUTO_INCREMENT,
-  `article_id` int(11) NULL DEFAULT NULL,
-  `create_time` datetime NULL DEFAULT NULL,
-  `ip` varchar(255) CHARACTER SET utf8mb4 COLLATE utf8mb4_general_ci NULL DEFAULT NULL,
-  PRIMARY KEY (`id`) USING BTREE,
-  INDEX `idx_view_article_id`(`article_id`) USING BTREE
-) ENGINE = InnoDB AUTO_INCREMENT = 1818 CHARACTER SET = utf8mb4 COLLATE = utf8mb4_general_ci ROW_FORMAT = Dynamic;
-
-SET FOREIGN_KEY_CHECKS = 1;
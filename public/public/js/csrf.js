@@ -0,0 +1,21 @@
+// Send the page's CSRF token back with every jQuery request, both with the
+// global jQuery and the copy bundled in layui. See src/home/csrf.rs.
+(function () {
+	var meta = document.querySelector('meta[name="csrf-token"]');
+	if (!meta) {
+		return;
+	}
+	var token = meta.getAttribute('content');
+	var setup = function (jq) {
+		jq.ajaxSetup({ headers: { 'x-csrf-token': token } });
+	};
+	window.csrfToken = token;
+	if (window.jQuery) {
+		setup(window.jQuery);
+	}
+	if (window.layui) {
+		layui.use('jquery', function () {
+			setup(layui.jquery);
+		});
+	}
+})();
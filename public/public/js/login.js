@@ -24,25 +24,60 @@ layui.use(['jquery', 'layer', 'form'], function () {
 		layer.load(2);
 		console.log(obj.field);
 		$.post({url:`${window.baseUrl}login`,headers:{"Content-type":"application/x-www-form-urlencoded"}}, obj.field, function (res) {
-			if (200 === res.code) {
+			if (200 === res.code && res.totp) {
+				layer.closeAll('loading');
+				askTotpCode(res.ticket, obj.field.rememberMe);
+			} else if (200 === res.code) {
 				console.log(res);
-				layer.msg('登录成功', { icon: 1, time: 1500 }, function () {
-					if (obj.field.rememberMe === true) {
-						document.cookie = `token=${res.token};expires=${new Date(new Date().setDate(new Date().getDate() + 30)).toUTCString()};Path=${res.baseUrl}`;
-					} else {
-						document.cookie = `token=${res.token};Path=${res.baseUrl}`;
-					}
-					location.replace(`${window.baseUrl}home/1`);
-				});
+				loginSucceeded(res, obj.field.rememberMe);
 			} else {
 				layer.closeAll('loading');
 				layer.msg(res.msg, { icon: 5 });
 				$('img.login-captcha').trigger('click');
 			}
-		}, 'JSON');
+		}, 'JSON').fail(function(res){
+			layer.closeAll('loading');
+			layer.msg(res.responseJSON.msg, { icon: 5 });
+		});
 		return false;
 	});
 
+	function loginSucceeded(res, rememberMe) {
+		layer.msg('登录成功', { icon: 1, time: 1500 }, function () {
+			if (rememberMe === true) {
+				document.cookie = `token=${res.token};expires=${new Date(new Date().setDate(new Date().getDate() + 30)).toUTCString()};Path=${res.baseUrl}`;
+			} else {
+				document.cookie = `token=${res.token};Path=${res.baseUrl}`;
+			}
+			location.replace(`${window.baseUrl}home/1`);
+		});
+	}
+
+	// 两步验证: 密码正确后输入验证器中的验证码或恢复码
+	function askTotpCode(ticket, rememberMe) {
+		layer.prompt({ title: '请输入两步验证码或恢复码', formType: 0 }, function (code, index) {
+			layer.load(2);
+			$.post({url:`${window.baseUrl}login/totp`,headers:{"Content-type":"application/x-www-form-urlencoded"}}, { ticket: ticket, code: code }, function (res) {
+				layer.closeAll('loading');
+				if (200 === res.code) {
+					layer.close(index);
+					loginSucceeded(res, rememberMe);
+				} else {
+					layer.msg(res.msg, { icon: 5 });
+				}
+			}, 'JSON').fail(function(res){
+				layer.closeAll('loading');
+				layer.msg(res.responseJSON.msg, { icon: 5 });
+			});
+		});
+	}
+
+	// 第三方登录后开启了两步验证的账号带着 ticket 回到登录页
+	const pendingTicket = new URLSearchParams(location.search).get('ticket');
+	if (pendingTicket) {
+		askTotpCode(pendingTicket, false);
+	}
+
 	// 注册表单提交
 	form.on('submit(regSubmit)', function (obj) {
 		layer.load(2);
@@ -114,7 +149,7 @@ layui.use(['jquery', 'layer', 'form'], function () {
 			url: `${window.baseUrl}sendcode`,
 			headers:{"Content-type":"application/x-www-form-urlencoded"},
 			type: 'POST', 
-			data:`email=${email}`,  //970252187@qq.com
+			data:{ email: email, purpose: $("#sendCheckCode").data("purpose") || "register" },
 			success: function (res) {
 				console.log(res);
 				if (res.code==200) {
@@ -122,8 +157,13 @@ layui.use(['jquery', 'layer', 'form'], function () {
 					layer.msg("已发送");
 					countDown();
 				} else {
-					layer.alert("获取失败，请重新获取")
+					layer.close(index);
+					layer.alert(res.msg || "获取失败，请重新获取")
 				}
+			},
+			error: function (res) {
+				layer.close(index);
+				layer.alert((res.responseJSON && res.responseJSON.msg) || "获取失败，请重新获取")
 			}
 		});
 	});
@@ -0,0 +1,677 @@
+//! Application configuration.
+//!
+//! Values are read from a JSON file (`./config.json` unless `--config <path>`
+//! is given; `config.example.json` is a starting point, the real file is not
+//! committed) and can then be overridden by `BLOG_*` environment variables:
+//! every scalar setting by `BLOG_` and its upper-cased name, e.g.
+//! `BLOG_CODE_TTL_SECS`, and each of `rate_limits` by
+//! `BLOG_RATE_LIMIT_<NAME>=<max>/<window_secs>`. The lists `oauth_providers`
+//! and `html_policy` can only be set in the file.
+//! Secrets may be supplied inline, or through a `*_file` key / `BLOG_*_FILE`
+//! variable pointing at a file that holds only the secret, so the committed
+//! config does not need to carry live credentials. A secret set in the
+//! environment, either way, wins over both forms in the file.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use serde::Deserialize;
+
+use crate::home::access::Role;
+use crate::home::highlight;
+
+const DEFAULT_CONFIG_PATH: &str = "./config.json";
+const MIN_SECRET_KEY_LEN: usize = 32;
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AppConfig {
+    pub base_url: String,
+    pub database_url: String,
+    pub database_url_file: Option<PathBuf>,
+    pub bind_addr: String,
+    pub secret_key: String,
+    pub secret_key_file: Option<PathBuf>,
+    /// Where email verification codes are kept
+    pub code_store: CodeStoreKind,
+    pub redis_url: String,
+    /// How long to wait for Redis to accept a connection, in milliseconds
+    pub redis_connect_timeout_ms: u64,
+    /// How long to wait for Redis to answer a command, in milliseconds
+    pub redis_response_timeout_ms: u64,
+    /// How outgoing email is delivered
+    pub mail_transport: MailTransport,
+    /// Sender of every outgoing email, e.g. `Blog <blog@example.com>`
+    pub mail_from: String,
+    pub resend_key: String,
+    pub resend_key_file: Option<PathBuf>,
+    /// Base URL of the Resend API
+    pub resend_url: String,
+    /// `smtp://` or `smtps://` URL, credentials included, for the SMTP transport
+    pub smtp_url: String,
+    pub smtp_url_file: Option<PathBuf>,
+    /// Where the file transport writes messages; stdout when unset
+    pub mail_dir: Option<PathBuf>,
+    /// Apply pending schema migrations when the server starts
+    pub auto_migrate: bool,
+    /// Trust `X-Forwarded-For` / `X-Real-IP` for the client address
+    pub behind_proxy: bool,
+    /// Digits in an emailed verification code, 6 to 8
+    pub code_length: usize,
+    /// How long an emailed verification code stays valid, in seconds
+    pub code_ttl_secs: u64,
+    /// Wrong guesses after which a verification code is thrown away
+    pub code_max_attempts: u32,
+    /// Name authenticator apps show next to the account for two-factor codes
+    pub totp_issuer: String,
+    /// Scheme and host the blog is reached at, e.g. `https://blog.example.com`,
+    /// for the redirect URIs registered with OAuth providers
+    pub public_url: String,
+    /// "Sign in with …" providers shown on the login page
+    pub oauth_providers: Vec<OAuthProviderConfig>,
+    pub rate_limits: RateLimits,
+    /// What user-supplied HTML in comments and articles may keep
+    pub html_policy: HtmlPolicy,
+    /// Stylesheet under `public/public/css/highlight` code blocks are coloured
+    /// with, e.g. `solarized-dark`
+    pub highlight_theme: String,
+}
+
+/// An OAuth 2.0 / OpenID Connect provider users can sign in with.
+///
+/// The redirect URI to register with the provider is
+/// `<public_url><base_url>oauth/<id>/callback`.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct OAuthProviderConfig {
+    /// Used in the login URLs and to tell identities apart, e.g. `github`
+    pub id: String,
+    /// Shown on the login button; `id` when empty
+    #[serde(default)]
+    pub name: String,
+    pub kind: OAuthKind,
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: String,
+    #[serde(default)]
+    pub client_secret_file: Option<PathBuf>,
+    /// Server of a Gitea or GitHub Enterprise instance, or the OIDC issuer
+    #[serde(default)]
+    pub url: String,
+    /// Scopes to ask for instead of the defaults of `kind`
+    #[serde(default)]
+    pub scopes: Vec<String>,
+}
+
+impl OAuthProviderConfig {
+    pub fn display_name(&self) -> &str {
+        if self.name.is_empty() {
+            &self.id
+        } else {
+            &self.name
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OAuthKind {
+    /// github.com, or GitHub Enterprise at `url`
+    Github,
+    /// The Gitea server at `url`
+    Gitea,
+    /// Any OpenID Connect provider, found through the discovery document of `url`
+    Oidc,
+}
+
+/// At most `max` requests per `window_secs`; a `max` of 0 disables the limit.
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    pub max: u32,
+    pub window_secs: u64,
+}
+
+impl Quota {
+    const fn new(max: u32, window_secs: u64) -> Self {
+        Quota { max, window_secs }
+    }
+}
+
+impl std::str::FromStr for Quota {
+    type Err = anyhow::Error;
+
+    /// `<max>/<window_secs>`, e.g. `5/900`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (max, window_secs) = s
+            .trim()
+            .split_once('/')
+            .context("expected `<max>/<window_secs>`")?;
+        Ok(Quota {
+            max: max.trim().parse().context("invalid max")?,
+            window_secs: window_secs.trim().parse().context("invalid window")?,
+        })
+    }
+}
+
+/// Per-client and per-target limits for the account endpoints.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimits {
+    pub login_per_ip: Quota,
+    /// Failed logins per account name; a successful login clears the count
+    pub login_per_account: Quota,
+    pub sendcode_per_ip: Quota,
+    pub sendcode_per_email: Quota,
+    pub register_per_ip: Quota,
+    pub register_per_email: Quota,
+    pub forget_per_ip: Quota,
+    pub forget_per_account: Quota,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        RateLimits {
+            login_per_ip: Quota::new(30, 5 * 60),
+            login_per_account: Quota::new(5, 15 * 60),
+            sendcode_per_ip: Quota::new(20, 60 * 60),
+            sendcode_per_email: Quota::new(5, 60 * 60),
+            register_per_ip: Quota::new(10, 60 * 60),
+            register_per_email: Quota::new(10, 60 * 60),
+            forget_per_ip: Quota::new(10, 60 * 60),
+            forget_per_account: Quota::new(5, 60 * 60),
+        }
+    }
+}
+
+/// The allow-list comment and article HTML is sanitized with, on top of
+/// ammonia's defaults.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct HtmlPolicy {
+    /// Tags to allow besides the defaults, e.g. `details`
+    pub extra_tags: Vec<String>,
+    /// Default tags to strip, e.g. `img` to keep pictures out
+    pub removed_tags: Vec<String>,
+    /// Attributes to allow on every tag besides `class`, `lang` and `title`
+    pub extra_attributes: Vec<String>,
+    /// Schemes links and images may use; relative URLs are always fine
+    pub url_schemes: Vec<String>,
+}
+
+impl Default for HtmlPolicy {
+    fn default() -> Self {
+        HtmlPolicy {
+            extra_tags: Vec::new(),
+            removed_tags: Vec::new(),
+            extra_attributes: Vec::new(),
+            url_schemes: ["http", "https", "mailto"].map(String::from).to_vec(),
+        }
+    }
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        AppConfig {
+            base_url: String::from("/"),
+            database_url: String::new(),
+            database_url_file: None,
+            bind_addr: String::from("0.0.0.0:8080"),
+            secret_key: String::new(),
+            secret_key_file: None,
+            code_store: CodeStoreKind::Redis,
+            redis_url: String::from("redis://127.0.0.1/"),
+            redis_connect_timeout_ms: 2000,
+            redis_response_timeout_ms: 1000,
+            mail_transport: MailTransport::Resend,
+            mail_from: String::from("Blog <blog@resend.dev>"),
+            resend_key: String::new(),
+            resend_key_file: None,
+            resend_url: String::from("https://api.resend.com"),
+            smtp_url: String::new(),
+            smtp_url_file: None,
+            mail_dir: None,
+            auto_migrate: true,
+            behind_proxy: false,
+            code_length: 6,
+            code_ttl_secs: 5 * 60,
+            code_max_attempts: 5,
+            totp_issuer: String::from("Blog"),
+            public_url: String::new(),
+            oauth_providers: Vec::new(),
+            rate_limits: RateLimits::default(),
+            html_policy: HtmlPolicy::default(),
+            highlight_theme: String::from("inspired-github"),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CodeStoreKind {
+    /// Redis at `redis_url`, shared by every node
+    Redis,
+    /// A map inside the process, for single-node deployments without Redis
+    Memory,
+}
+
+impl std::str::FromStr for CodeStoreKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "redis" => Ok(CodeStoreKind::Redis),
+            "memory" => Ok(CodeStoreKind::Memory),
+            other => bail!("unknown code store `{other}`, expected `redis` or `memory`"),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MailTransport {
+    /// The Resend HTTP API, authenticated with `resend_key`
+    Resend,
+    /// The SMTP server at `smtp_url`
+    Smtp,
+    /// Write messages to `mail_dir` or stdout instead of sending them
+    File,
+}
+
+impl std::str::FromStr for MailTransport {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "resend" => Ok(MailTransport::Resend),
+            "smtp" => Ok(MailTransport::Smtp),
+            "file" => Ok(MailTransport::File),
+            other => bail!("unknown mail transport `{other}`, expected `resend`, `smtp` or `file`"),
+        }
+    }
+}
+
+/// Command line arguments understood by the binary.
+///
+/// `blog-rust [--config <path>] [serve | migrate [up|down|status|fresh] | role <user> <role> | sanitize]`
+#[derive(Debug, Default)]
+pub struct Cli {
+    pub config_path: Option<PathBuf>,
+    pub command: Command,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum Command {
+    #[default]
+    Serve,
+    Migrate(MigrateAction),
+    /// Change a user's role, see [`Role`]
+    SetRole {
+        user: String,
+        role: Role,
+    },
+    /// Run every stored comment and article through the current `html_policy`
+    Sanitize,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum MigrateAction {
+    /// Apply every pending migration
+    #[default]
+    Up,
+    /// Roll back the most recently applied migration
+    Down,
+    /// List applied and pending migrations
+    Status,
+    /// Drop every table and apply all migrations again
+    Fresh,
+}
+
+impl Cli {
+    pub fn parse() -> anyhow::Result<Cli> {
+        Self::parse_from(std::env::args().skip(1))
+    }
+
+    pub fn parse_from(args: impl IntoIterator<Item = String>) -> anyhow::Result<Cli> {
+        let mut cli = Cli::default();
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            if arg == "--config" {
+                let path = args.next().context("`--config` requires a path")?;
+                cli.config_path = Some(PathBuf::from(path));
+            } else if let Some(path) = arg.strip_prefix("--config=") {
+                cli.config_path = Some(PathBuf::from(path));
+            } else if arg == "serve" {
+                cli.command = Command::Serve;
+            } else if arg == "migrate" {
+                let action = match args.next_if(|a| !a.starts_with("--")).as_deref() {
+                    None | Some("up") => MigrateAction::Up,
+                    Some("down") => MigrateAction::Down,
+                    Some("status") => MigrateAction::Status,
+                    Some("fresh") => MigrateAction::Fresh,
+                    Some(other) => bail!("unknown migrate action `{other}`"),
+                };
+                cli.command = Command::Migrate(action);
+            } else if arg == "role" {
+                let user = args.next().context("`role` requires a user name")?;
+                let role = args.next().context("`role` requires a role")?;
+                let role = Role::from_name(&role).with_context(|| {
+                    format!("unknown role `{role}`, expected reader, author, editor or admin")
+                })?;
+                cli.command = Command::SetRole { user, role };
+            } else if arg == "sanitize" {
+                cli.command = Command::Sanitize;
+            } else {
+                bail!("unknown argument `{arg}`");
+            }
+        }
+        Ok(cli)
+    }
+}
+
+impl AppConfig {
+    /// Load the config file, apply environment overrides, resolve secret files and validate.
+    pub fn load(cli: &Cli) -> anyhow::Result<AppConfig> {
+        Self::load_with(cli, |key| std::env::var(key).ok())
+    }
+
+    /// [`AppConfig::load`] with the environment read through `var`.
+    pub(crate) fn load_with(
+        cli: &Cli,
+        var: impl Fn(&str) -> Option<String>,
+    ) -> anyhow::Result<AppConfig> {
+        let mut config = match &cli.config_path {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            None => AppConfig::default(),
+        };
+        config.apply_env(var)?;
+        config.resolve_secret_files()?;
+        config.validate()?;
+        if cli.command == Command::Serve {
+            config.validate_server()?;
+        }
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> anyhow::Result<AppConfig> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read config file {}", path.display()))?;
+        serde_json::from_str(&content)
+            .with_context(|| format!("failed to parse config file {}", path.display()))
+    }
+
+    pub(crate) fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> anyhow::Result<()> {
+        let strings = [
+            ("BLOG_BASE_URL", &mut self.base_url),
+            ("BLOG_BIND_ADDR", &mut self.bind_addr),
+            ("BLOG_REDIS_URL", &mut self.redis_url),
+            ("BLOG_MAIL_FROM", &mut self.mail_from),
+            ("BLOG_RESEND_URL", &mut self.resend_url),
+            ("BLOG_TOTP_ISSUER", &mut self.totp_issuer),
+            ("BLOG_PUBLIC_URL", &mut self.public_url),
+            ("BLOG_HIGHLIGHT_THEME", &mut self.highlight_theme),
+        ];
+        for (key, field) in strings {
+            if let Some(v) = var(key) {
+                *field = v;
+            }
+        }
+        let secrets = [
+            (
+                "BLOG_DATABASE_URL",
+                &mut self.database_url,
+                &mut self.database_url_file,
+            ),
+            (
+                "BLOG_SECRET_KEY",
+                &mut self.secret_key,
+                &mut self.secret_key_file,
+            ),
+            (
+                "BLOG_RESEND_KEY",
+                &mut self.resend_key,
+                &mut self.resend_key_file,
+            ),
+            ("BLOG_SMTP_URL", &mut self.smtp_url, &mut self.smtp_url_file),
+        ];
+        for (key, value, file) in secrets {
+            let file_key = format!("{key}_FILE");
+            match (var(key), var(&file_key)) {
+                (Some(_), Some(_)) => bail!("set only one of `{key}` and `{file_key}`"),
+                // The variable beats a `*_file` from the config file
+                (Some(v), None) => {
+                    *value = v;
+                    *file = None;
+                }
+                (None, Some(path)) => *file = Some(PathBuf::from(path)),
+                (None, None) => {}
+            }
+        }
+        if let Some(v) = var("BLOG_MAIL_DIR") {
+            self.mail_dir = Some(PathBuf::from(v));
+        }
+        if let Some(v) = var("BLOG_AUTO_MIGRATE") {
+            self.auto_migrate = matches!(v.trim(), "1" | "true" | "yes");
+        }
+        if let Some(v) = var("BLOG_BEHIND_PROXY") {
+            self.behind_proxy = matches!(v.trim(), "1" | "true" | "yes");
+        }
+        parse_env(&var, "BLOG_CODE_STORE", &mut self.code_store)?;
+        parse_env(&var, "BLOG_MAIL_TRANSPORT", &mut self.mail_transport)?;
+        parse_env(&var, "BLOG_CODE_LENGTH", &mut self.code_length)?;
+        parse_env(&var, "BLOG_CODE_TTL_SECS", &mut self.code_ttl_secs)?;
+        parse_env(&var, "BLOG_CODE_MAX_ATTEMPTS", &mut self.code_max_attempts)?;
+        parse_env(
+            &var,
+            "BLOG_REDIS_CONNECT_TIMEOUT_MS",
+            &mut self.redis_connect_timeout_ms,
+        )?;
+        parse_env(
+            &var,
+            "BLOG_REDIS_RESPONSE_TIMEOUT_MS",
+            &mut self.redis_response_timeout_ms,
+        )?;
+        let limits = &mut self.rate_limits;
+        let quotas = [
+            ("BLOG_RATE_LIMIT_LOGIN_PER_IP", &mut limits.login_per_ip),
+            (
+                "BLOG_RATE_LIMIT_LOGIN_PER_ACCOUNT",
+                &mut limits.login_per_account,
+            ),
+            (
+                "BLOG_RATE_LIMIT_SENDCODE_PER_IP",
+                &mut limits.sendcode_per_ip,
+            ),
+            (
+                "BLOG_RATE_LIMIT_SENDCODE_PER_EMAIL",
+                &mut limits.sendcode_per_email,
+            ),
+            (
+                "BLOG_RATE_LIMIT_REGISTER_PER_IP",
+                &mut limits.register_per_ip,
+            ),
+            (
+                "BLOG_RATE_LIMIT_REGISTER_PER_EMAIL",
+                &mut limits.register_per_email,
+            ),
+            ("BLOG_RATE_LIMIT_FORGET_PER_IP", &mut limits.forget_per_ip),
+            (
+                "BLOG_RATE_LIMIT_FORGET_PER_ACCOUNT",
+                &mut limits.forget_per_account,
+            ),
+        ];
+        for (key, field) in quotas {
+            parse_env(&var, key, field)?;
+        }
+        Ok(())
+    }
+
+    fn resolve_secret_files(&mut self) -> anyhow::Result<()> {
+        let secrets = [
+            (&self.database_url_file, &mut self.database_url),
+            (&self.secret_key_file, &mut self.secret_key),
+            (&self.resend_key_file, &mut self.resend_key),
+            (&self.smtp_url_file, &mut self.smtp_url),
+        ];
+        let providers = self
+            .oauth_providers
+            .iter_mut()
+            .map(|p| (&p.client_secret_file, &mut p.client_secret));
+        for (file, value) in secrets.into_iter().chain(providers) {
+            if let Some(path) = file {
+                let content = std::fs::read_to_string(path)
+                    .with_context(|| format!("failed to read secret file {}", path.display()))?;
+                *value = content.trim().to_owned();
+            }
+        }
+        Ok(())
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if !self.base_url.starts_with('/') || !self.base_url.ends_with('/') {
+            bail!(
+                "`base_url` must start and end with `/`, got `{}`",
+                self.base_url
+            );
+        }
+        if self.database_url.is_empty() {
+            bail!("`database_url` is required");
+        }
+        if !["mysql://", "postgres://", "postgresql://", "sqlite:"]
+            .iter()
+            .any(|scheme| self.database_url.starts_with(scheme))
+        {
+            bail!("`database_url` must be a mysql://, postgres:// or sqlite: URL");
+        }
+        self.bind_addr.parse::<SocketAddr>().with_context(|| {
+            format!("`bind_addr` is not a socket address: `{}`", self.bind_addr)
+        })?;
+        if !(6..=8).contains(&self.code_length) {
+            bail!("`code_length` must be between 6 and 8");
+        }
+        if self.code_ttl_secs == 0 {
+            bail!("`code_ttl_secs` must be greater than zero");
+        }
+        if self.code_max_attempts == 0 {
+            bail!("`code_max_attempts` must be at least 1");
+        }
+        // The issuer is part of the `otpauth://` label, where `:` is the separator
+        if self.totp_issuer.is_empty() || self.totp_issuer.contains(':') {
+            bail!("`totp_issuer` must be non-empty and must not contain `:`");
+        }
+        if !self.oauth_providers.is_empty()
+            && !self.public_url.starts_with("http://")
+            && !self.public_url.starts_with("https://")
+        {
+            bail!(
+                "`public_url` must be an http:// or https:// URL when OAuth providers are configured"
+            );
+        }
+        let mut ids = std::collections::HashSet::new();
+        for provider in &self.oauth_providers {
+            let id = &provider.id;
+            if id.is_empty()
+                || !id
+                    .chars()
+                    .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+            {
+                bail!("OAuth provider id `{id}` may only contain a-z, 0-9, `-` and `_`");
+            }
+            if !ids.insert(id) {
+                bail!("OAuth provider id `{id}` is used twice");
+            }
+            if provider.client_id.is_empty() {
+                bail!("OAuth provider `{id}` needs a `client_id`");
+            }
+            if provider.kind != OAuthKind::Github && provider.url.is_empty() {
+                bail!("OAuth provider `{id}` needs a `url`");
+            }
+        }
+        if !highlight::THEMES.contains(&self.highlight_theme.as_str()) {
+            bail!(
+                "unknown `highlight_theme` `{}`, expected one of {}",
+                self.highlight_theme,
+                highlight::THEMES.join(", ")
+            );
+        }
+        let policy = &self.html_policy;
+        if let Some(scheme) = policy.url_schemes.iter().find(|s| {
+            matches!(
+                s.to_ascii_lowercase().as_str(),
+                "javascript" | "vbscript" | "data"
+            )
+        }) {
+            bail!("`html_policy.url_schemes` must not allow `{scheme}`");
+        }
+        if let Some(attribute) = policy
+            .extra_attributes
+            .iter()
+            .find(|a| a.to_ascii_lowercase().starts_with("on"))
+        {
+            bail!("`html_policy.extra_attributes` must not allow the event handler `{attribute}`");
+        }
+        if self.code_store == CodeStoreKind::Redis
+            && !self.redis_url.starts_with("redis://")
+            && !self.redis_url.starts_with("rediss://")
+        {
+            bail!("`redis_url` must be a redis:// or rediss:// URL");
+        }
+        if self.redis_connect_timeout_ms == 0 || self.redis_response_timeout_ms == 0 {
+            bail!("redis timeouts must be greater than zero");
+        }
+        Ok(())
+    }
+
+    /// The secrets only the server needs, so `migrate`, `role` and `sanitize`
+    /// run without them.
+    fn validate_server(&self) -> anyhow::Result<()> {
+        if self.secret_key.len() < MIN_SECRET_KEY_LEN {
+            bail!("`secret_key` must be at least {MIN_SECRET_KEY_LEN} bytes long");
+        }
+        match self.mail_transport {
+            MailTransport::Resend if self.resend_key.is_empty() => {
+                bail!("`resend_key` is required when `mail_transport` is `resend`");
+            }
+            MailTransport::Resend
+                if !self.resend_url.starts_with("https://")
+                    && !self.resend_url.starts_with("http://") =>
+            {
+                bail!("`resend_url` must be an http:// or https:// URL");
+            }
+            MailTransport::Smtp
+                if !self.smtp_url.starts_with("smtp://")
+                    && !self.smtp_url.starts_with("smtps://") =>
+            {
+                bail!(
+                    "`smtp_url` must be a smtp:// or smtps:// URL when `mail_transport` is `smtp`"
+                );
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Parse the variable `key` into `field` if it is set.
+fn parse_env<T>(
+    var: &impl Fn(&str) -> Option<String>,
+    key: &str,
+    field: &mut T,
+) -> anyhow::Result<()>
+where
+    T: std::str::FromStr,
+    T::Err: Into<anyhow::Error>,
+{
+    if let Some(v) = var(key) {
+        *field = v
+            .trim()
+            .parse()
+            .map_err(Into::into)
+            .with_context(|| format!("invalid `{key}`"))?;
+    }
+    Ok(())
+}
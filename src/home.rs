@@ -1,65 +1,54 @@
+pub mod access;
+pub mod admin;
+pub mod code_store;
+pub mod csrf;
 mod database;
-
+pub mod highlight;
+pub mod mailer;
+mod markdown;
+pub mod math;
+pub mod oauth;
+mod password;
+mod profile;
+pub mod sanitize;
+pub mod session;
+mod state;
+mod throttle;
+pub mod two_factor;
+pub mod verification;
+
+pub use state::{AppState, InjectState};
+
+use access::{Permission, Role, Standing, Viewer};
+use code_store::CodeStore;
 use database::prelude::*;
+use mailer::{Email, Mailer};
+use markdown::{Source, Target};
+use profile::{Profile, ProfileForm};
+use throttle::client_ip;
+use verification::{Check, Purpose};
 
-use redis::TypedCommands;
-use resend_rs::{Resend, types::CreateEmailBaseOptions};
 use salvo::prelude::*;
-use sea_orm::{DatabaseBackend, DatabaseConnection, EntityTrait, JsonValue, Statement, prelude::*};
+use sea_orm::sea_query::{Alias, Expr, Func, Order, Query};
+use sea_orm::{DatabaseConnection, EntityTrait, JsonValue, prelude::*};
 
 use serde_json::json;
 
 use url_encor::Encoder;
 
-use self::database::{article_tb, comment_tb, tag_tb, user_tb, view_tb};
+use self::database::{article_tb, comment_tb, level_tb, tag_tb, user_tb, view_tb};
+use crate::config::Quota;
 
 use sea_orm::{entity::*, query::*};
 
 use salvo::http::StatusCode;
 
 use tera::{Context, Tera};
-use time::{Duration, OffsetDateTime};
 
 use chrono::prelude::*;
-use jsonwebtoken::{self, EncodingKey};
 
 use ::serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::sync::OnceLock;
-
-// Static global variables for application-lifetime resources
-static DB: OnceLock<DatabaseConnection> = OnceLock::new();
-static TERA: OnceLock<Tera> = OnceLock::new();
-static BASE_URL: OnceLock<String> = OnceLock::new();
-static SECRET_KEY: OnceLock<String> = OnceLock::new();
-static REDIS_URL: OnceLock<String> = OnceLock::new();
-static RESEND_KEY: OnceLock<String> = OnceLock::new();
-
-// Public initialization functions (called from main.rs)
-pub fn init_db(db: DatabaseConnection) {
-    DB.set(db).expect("DB already initialized");
-}
-
-pub fn init_tera(tera: Tera) {
-    TERA.set(tera).expect("TERA already initialized");
-}
-
-pub fn init_base_url(url: String) {
-    BASE_URL.set(url).expect("BASE_URL already initialized");
-}
-
-pub fn init_secret_key(key: String) {
-    SECRET_KEY.set(key).expect("SECRET_KEY already initialized");
-}
-
-pub fn init_redis_url(url: String) {
-    REDIS_URL.set(url).expect("REDIS_URL already initialized");
-}
-
-pub fn init_resend_key(key: String) {
-    RESEND_KEY.set(key).expect("RESEND_KEY already initialized");
-}
-
 macro_rules! construct_context {
     ($($k:expr => $v:expr),+) => {
         {
@@ -74,6 +63,12 @@ macro_rules! construct_context {
 pub struct JwtClaims {
     pub user_id: String,
     pub exp: i64,
+    /// Names the row in `session_tb` backing this token
+    #[serde(default)]
+    pub jti: String,
+    /// `user_tb.token_version` when the token was issued
+    #[serde(default)]
+    pub ver: i32,
 }
 
 const RESPONSE_TEXT_FOR_ERROR: u8 = 1;
@@ -90,11 +85,14 @@ impl<const ERRORCODE: u8, T: Into<anyhow::Error>> From<T> for UniformError<ERROR
 
 #[async_trait]
 impl<const ERRORCODE: u8> Writer for UniformError<ERRORCODE> {
-    async fn write(mut self, _req: &mut Request, _depot: &mut Depot, res: &mut Response) {
+    async fn write(mut self, _req: &mut Request, depot: &mut Depot, res: &mut Response) {
         let err = self.0.to_string();
         if ERRORCODE == 1 {
             // Try to get tera and base_url, fall back to plain text if not available
-            match (get_tera::<ERRORCODE>(), get_base_url::<ERRORCODE>()) {
+            match (
+                get_tera::<ERRORCODE>(depot),
+                get_base_url::<ERRORCODE>(depot),
+            ) {
                 (Ok(tera), Ok(base_url)) => {
                     let context = construct_context!["code"=>404,"msg"=>err,"baseUrl"=>base_url];
                     let r = tera.render("404.html", &context).unwrap_or(err);
@@ -102,7 +100,7 @@ impl<const ERRORCODE: u8> Writer for UniformError<ERRORCODE> {
                         .render(Text::Html(r));
                 }
                 _ => {
-                    // If globals not initialized, return plain text error
+                    // If the state was not injected, return plain text error
                     res.status_code(StatusCode::BAD_REQUEST)
                         .render(Text::Plain(err));
                 }
@@ -131,44 +129,88 @@ impl<T> ConverOptionToResult<T> for Option<T> {
     }
 }
 
-// Helper functions to access static global resources
+// Typed accessors for the AppState injected by `InjectState`
 // These return Result to allow graceful error handling instead of panic
-fn get_db<const E: u8>() -> Result<&'static DatabaseConnection, UniformError<E>> {
-    DB.get()
-        .ok_or_else(|| anyhow::anyhow!("Database connection not initialized").into())
+fn get_state<const E: u8>(depot: &Depot) -> Result<&AppState, UniformError<E>> {
+    depot
+        .obtain::<AppState>()
+        .map_err(|_| anyhow::anyhow!("Application state not injected").into())
+}
+
+fn get_db<const E: u8>(depot: &Depot) -> Result<&DatabaseConnection, UniformError<E>> {
+    Ok(get_state(depot)?.db())
+}
+
+fn get_base_url<const E: u8>(depot: &Depot) -> Result<&str, UniformError<E>> {
+    Ok(get_state(depot)?.base_url())
+}
+
+fn get_tera<const E: u8>(depot: &Depot) -> Result<&Tera, UniformError<E>> {
+    Ok(get_state(depot)?.tera())
 }
 
-fn get_base_url<const E: u8>() -> Result<&'static str, UniformError<E>> {
-    BASE_URL
-        .get()
-        .map(|s| s.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Base URL not initialized").into())
+/// Render a page, adding the CSRF token its requests have to send back.
+fn render_page<const E: u8>(
+    depot: &Depot,
+    template: &str,
+    context: &mut Context,
+) -> Result<String, UniformError<E>> {
+    context.insert("csrfToken", csrf::token(depot));
+    Ok(get_tera(depot)?.render(template, context)?)
 }
 
-fn get_tera<const E: u8>() -> Result<&'static Tera, UniformError<E>> {
-    TERA.get()
-        .ok_or_else(|| anyhow::anyhow!("Template engine not initialized").into())
+fn get_secret_key<const E: u8>(depot: &Depot) -> Result<&str, UniformError<E>> {
+    Ok(get_state(depot)?.secret_key())
 }
 
-fn get_secret_key<const E: u8>() -> Result<&'static str, UniformError<E>> {
-    SECRET_KEY
-        .get()
-        .map(|s| s.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Secret key not initialized").into())
+fn get_mailer<const E: u8>(depot: &Depot) -> Result<&dyn Mailer, UniformError<E>> {
+    Ok(get_state(depot)?.mailer())
 }
 
-fn get_redis_url<const E: u8>() -> Result<&'static str, UniformError<E>> {
-    REDIS_URL
-        .get()
-        .map(|s| s.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Redis URL not initialized").into())
+/// Count the request against every `(key, quota)` pair. When one of them is used
+/// up, answer 429 with the usual `{code,msg}` body and return `false`.
+fn allow_request<const E: u8>(
+    depot: &Depot,
+    res: &mut Response,
+    checks: &[(String, Quota)],
+) -> Result<bool, UniformError<E>> {
+    let Err(wait) = get_state(depot)?.throttle().hit(checks) else {
+        return Ok(true);
+    };
+    let seconds = wait.as_secs().max(1);
+    res.status_code(StatusCode::TOO_MANY_REQUESTS);
+    res.add_header("retry-after", seconds.to_string(), true)?;
+    let r = json!({
+        "code":429,
+        "msg":format!("请求过于频繁, 请{seconds}秒后再试")
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(false)
 }
 
-fn get_resend_key<const E: u8>() -> Result<&'static str, UniformError<E>> {
-    RESEND_KEY
-        .get()
-        .map(|s| s.as_str())
-        .ok_or_else(|| anyhow::anyhow!("Resend key not initialized").into())
+/// Compare `guess` with the code emailed to `email` for `purpose`, and return
+/// the message to show when it is not accepted. An accepted code is used up.
+async fn check_email_code<const E: u8>(
+    depot: &Depot,
+    purpose: Purpose,
+    email: &str,
+    guess: &str,
+) -> Result<Option<&'static str>, UniformError<E>> {
+    let state = get_state(depot)?;
+    let check = verification::check(
+        state.codes(),
+        purpose,
+        email,
+        guess,
+        state.code_max_attempts(),
+    )
+    .await?;
+    Ok(match check {
+        Check::Accepted => None,
+        Check::Missing => Some("未通过邮箱验证, 未找到邮箱的验证码"),
+        Check::Wrong => Some("邮箱验证码错误"),
+        Check::Exhausted => Some("验证码错误次数过多, 请重新获取"),
+    })
 }
 
 fn get_current_time() -> chrono::NaiveDateTime {
@@ -209,7 +251,7 @@ async fn enrich_articles_with_metadata(
         .await?;
     let user_map: HashMap<i32, String> = users
         .into_iter()
-        .map(|u| (u.id, u.name.unwrap_or_default()))
+        .map(|u| (u.id, profile::display_name(&u)))
         .collect();
 
     // Batch fetch tags
@@ -222,58 +264,9 @@ async fn enrich_articles_with_metadata(
         .map(|t| (t.id, t.name.unwrap_or_default()))
         .collect();
 
-    // Batch fetch view and comment counts with optimized SQL
-    let article_ids_str = article_ids
-        .iter()
-        .map(|id| id.to_string())
-        .collect::<Vec<_>>()
-        .join(",");
-
-    if !article_ids_str.is_empty() {
-        let counts_sql = format!(
-            r#"
-            SELECT
-                article_id,
-                COUNT(CASE WHEN source = 'view' THEN 1 END) as view_count,
-                COUNT(CASE WHEN source = 'comment' THEN 1 END) as comment_count
-            FROM (
-                SELECT article_id, 'view' as source FROM view_tb WHERE article_id IN ({})
-                UNION ALL
-                SELECT article_id, 'comment' as source FROM comment_tb WHERE article_id IN ({})
-            ) combined
-            GROUP BY article_id
-            "#,
-            article_ids_str, article_ids_str
-        );
-
-        let counts_stmt = Statement::from_string(DatabaseBackend::MySql, counts_sql);
-        let counts_results = ViewTb::find()
-            .from_raw_sql(counts_stmt)
-            .into_json()
-            .all(db)
-            .await?;
-
-        let mut counts_map: HashMap<i32, (u64, u64)> = counts_results
-            .into_iter()
-            .filter_map(|v| {
-                let article_id = v.get("article_id")?.as_i64()? as i32;
-                let view_count = v
-                    .get("view_count")
-                    .and_then(|val| {
-                        val.as_u64()
-                            .or_else(|| val.as_str().and_then(|s| s.parse().ok()))
-                    })
-                    .unwrap_or(0);
-                let comment_count = v
-                    .get("comment_count")
-                    .and_then(|val| {
-                        val.as_u64()
-                            .or_else(|| val.as_str().and_then(|s| s.parse().ok()))
-                    })
-                    .unwrap_or(0);
-                Some((article_id, (view_count, comment_count)))
-            })
-            .collect();
+    // Batch fetch view and comment counts
+    if !article_ids.is_empty() {
+        let mut counts_map = count_views_and_comments(&article_ids, db).await?;
 
         // Enrich articles with fetched data
         for article in articles.iter_mut() {
@@ -281,17 +274,17 @@ async fn enrich_articles_with_metadata(
                 let article_id = article_id as i32;
 
                 // Add user name
-                if let Some(user_id) = article.get("user_id").and_then(|v| v.as_i64()) {
-                    if let Some(user_name) = user_map.get(&(user_id as i32)) {
-                        article["userName"] = json!(user_name);
-                    }
+                if let Some(user_id) = article.get("user_id").and_then(|v| v.as_i64())
+                    && let Some(user_name) = user_map.get(&(user_id as i32))
+                {
+                    article["userName"] = json!(user_name);
                 }
 
                 // Add tag name
-                if let Some(tag_id) = article.get("tag_id").and_then(|v| v.as_i64()) {
-                    if let Some(tag_name) = tag_map.get(&(tag_id as i32)) {
-                        article["tagName"] = json!(tag_name);
-                    }
+                if let Some(tag_id) = article.get("tag_id").and_then(|v| v.as_i64())
+                    && let Some(tag_name) = tag_map.get(&(tag_id as i32))
+                {
+                    article["tagName"] = json!(tag_name);
                 }
 
                 // Add counts
@@ -305,8 +298,46 @@ async fn enrich_articles_with_metadata(
     Ok(())
 }
 
-/// Return UserTb::Model, TagTb::Model, view_count, comment_count
-/// Note: This function is kept for backward compatibility but should be replaced with batch version
+/// Count views and comments for every article in `article_ids` with two grouped queries
+async fn count_views_and_comments(
+    article_ids: &[i32],
+    db: &DatabaseConnection,
+) -> Result<HashMap<i32, (u64, u64)>, UniformError> {
+    let view_counts: Vec<(Option<i32>, i64)> = ViewTb::find()
+        .select_only()
+        .column(view_tb::Column::ArticleId)
+        .column_as(view_tb::Column::Id.count(), "view_count")
+        .filter(view_tb::Column::ArticleId.is_in(article_ids.iter().copied()))
+        .group_by(view_tb::Column::ArticleId)
+        .into_tuple()
+        .all(db)
+        .await?;
+    let comment_counts: Vec<(Option<i32>, i64)> = CommentTb::find()
+        .select_only()
+        .column(comment_tb::Column::ArticleId)
+        .column_as(comment_tb::Column::Id.count(), "comment_count")
+        .filter(comment_tb::Column::ArticleId.is_in(article_ids.iter().copied()))
+        .group_by(comment_tb::Column::ArticleId)
+        .into_tuple()
+        .all(db)
+        .await?;
+
+    let mut counts_map: HashMap<i32, (u64, u64)> = HashMap::new();
+    for (article_id, count) in view_counts {
+        if let Some(article_id) = article_id {
+            counts_map.entry(article_id).or_default().0 = count as u64;
+        }
+    }
+    for (article_id, count) in comment_counts {
+        if let Some(article_id) = article_id {
+            counts_map.entry(article_id).or_default().1 = count as u64;
+        }
+    }
+    Ok(counts_map)
+}
+
+// Return UserTb::Model, TagTb::Model, view_count, comment_count
+// Note: This function is kept for backward compatibility but should be replaced with batch version
 // async fn get_relative_information_from_article(
 //     article_id: u64,
 //     user_id: u64,
@@ -333,25 +364,34 @@ async fn enrich_articles_with_metadata(
 // }
 
 async fn get_hot_article_list(db: &DatabaseConnection) -> Result<Vec<JsonValue>, UniformError> {
-    // Optimized: Simplified SQL query without nested subquery
-    let sql = r#"
-        SELECT
-            a.id,
-            a.title,
-            COUNT(v.id) AS Counts
-        FROM article_tb a
-        LEFT JOIN view_tb v ON a.id = v.article_id
-        WHERE a.level <> 999 AND a.article_state = 1
-        GROUP BY a.id, a.title
-        ORDER BY Counts DESC
-        LIMIT 8
-    "#;
+    let query = Query::select()
+        .column((article_tb::Entity, article_tb::Column::Id))
+        .column((article_tb::Entity, article_tb::Column::Title))
+        .expr_as(
+            Expr::col((view_tb::Entity, view_tb::Column::Id)).count(),
+            Alias::new("Counts"),
+        )
+        .from(article_tb::Entity)
+        .left_join(
+            view_tb::Entity,
+            Expr::col((article_tb::Entity, article_tb::Column::Id))
+                .equals((view_tb::Entity, view_tb::Column::ArticleId)),
+        )
+        .and_where(
+            Expr::col((article_tb::Entity, article_tb::Column::Level)).ne(access::PRIVATE_LEVEL),
+        )
+        .and_where(Expr::col((article_tb::Entity, article_tb::Column::ArticleState)).eq(1))
+        .and_where(Expr::col((article_tb::Entity, article_tb::Column::Hidden)).eq(false))
+        .group_by_columns([
+            (article_tb::Entity, article_tb::Column::Id),
+            (article_tb::Entity, article_tb::Column::Title),
+        ])
+        .order_by(Alias::new("Counts"), Order::Desc)
+        .limit(8)
+        .to_owned();
 
     let r = ViewTb::find()
-        .from_raw_sql(Statement::from_string(
-            DatabaseBackend::MySql,
-            sql.to_string(),
-        ))
+        .from_raw_sql(db.get_database_backend().build(&query))
         .into_json()
         .all(db)
         .await?;
@@ -370,31 +410,13 @@ async fn get_person_right_state<const I: u8>(
     Ok((info, post_count))
 }
 
-async fn generate_token_by_user_id<const I: u8>(
-    secret_key: &str,
-    user_id: i32,
-    remember: bool,
-) -> Result<String, UniformError<I>> {
-    let exp = OffsetDateTime::now_utc() + Duration::days(if remember { 30 } else { 1 });
-    let claim = JwtClaims {
-        user_id: user_id.to_string(),
-        exp: exp.unix_timestamp(),
-    };
-    let token = jsonwebtoken::encode(
-        &jsonwebtoken::Header::default(),
-        &claim,
-        &EncodingKey::from_secret(secret_key.as_bytes()),
-    )?;
-    Ok(token)
-}
-
 #[handler]
 pub async fn home(
     req: &mut Request,
     res: &mut Response,
     depot: &mut Depot,
 ) -> Result<(), UniformError> {
-    let base_url = get_base_url()?;
+    let base_url = get_base_url(depot)?;
     let page = match req.param::<u64>("page") {
         Some(x) if x >= 1 => x - 1,
         _ => {
@@ -403,17 +425,17 @@ pub async fn home(
             return Ok(());
         }
     };
-    let db = get_db()?;
+    let db = get_db(depot)?;
     let pagination = ArticleTb::find()
         .order_by_desc(article_tb::Column::UpdateTime)
         .filter(article_tb::Column::ArticleState.eq(1))
-        .filter(article_tb::Column::Level.ne(999))
+        .filter(article_tb::Column::Hidden.eq(false))
+        .filter(article_tb::Column::Level.ne(access::PRIVATE_LEVEL))
         .into_json()
         .paginate(db, 10);
-    let tera = get_tera()?;
     let total_count = pagination.num_items().await?;
     // Optimized: Calculate total_pages locally to avoid redundant COUNT query
-    let total_pages = (total_count + 9) / 10;
+    let total_pages = total_count.div_ceil(10);
     if page != 0 && (page + 1) > total_pages {
         return Err(UniformError(anyhow::anyhow!("请求的资源不存在")));
     }
@@ -439,15 +461,20 @@ pub async fn home(
                         "avatar":""
                     });
                 };
+                let notice = Standing::of(&info.0).notice();
                 let avatar = info.0.avatar.unwrap_or_default();
                 let username = info.0.name.unwrap_or_default();
-                let level = info.0.privilege.unwrap_or_default();
+                let role = Role::from_privilege(info.0.privilege);
+                let level = access::clearance(role, info.0.clearance);
+                let role = role.title();
                 let post_count = info.1;
                 json!({
                     "login":true,
                     "avatar":avatar,
                     "name":username,
                     "level":level,
+                    "role":role,
+                    "notice":notice,
                     "post_count":post_count
                 })
             }
@@ -466,17 +493,26 @@ pub async fn home(
     context.insert("commentCount", &10);
     context.insert("page", &(page + 1));
     context.insert("hotArticles", &hot_list);
-    let r = tera.render("home.html", &context)?;
+    let r = render_page(depot, "home.html", &mut context)?;
     res.render(Text::Html(r));
     Ok(())
 }
 
 #[handler]
-pub async fn render_login_view(_req: &mut Request, res: &mut Response) -> Result<(), UniformError> {
-    let base_url = get_base_url()?;
-    let tera = get_tera()?;
-    let context = construct_context!["baseUrl"=>base_url];
-    let r = tera.render("login.html", &context)?;
+pub async fn render_login_view(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let base_url = get_base_url(depot)?;
+    let providers: Vec<JsonValue> = get_state(depot)?
+        .oauth()
+        .providers()
+        .into_iter()
+        .map(|(id, name)| json!({"id":id,"name":name}))
+        .collect();
+    let mut context = construct_context!["baseUrl"=>base_url,"oauthProviders"=>providers];
+    let r = render_page(depot, "login.html", &mut context)?;
     res.render(Text::Html(r));
     Ok(())
 }
@@ -485,20 +521,33 @@ pub async fn render_login_view(_req: &mut Request, res: &mut Response) -> Result
 pub async fn login(
     req: &mut Request,
     res: &mut Response,
+    depot: &mut Depot,
 ) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
     let name = req.form::<String>("nickName").await.to_result()?;
     let pass = req.form::<String>("password").await.to_result()?;
     let remember_me = req.form::<String>("rememberMe").await.to_result()?;
-    let pass = md5::compute(pass);
-    let pass = format!("{:?}", pass);
-    let db = get_db()?;
-    let base_url = get_base_url()?;
-    let Some(r) = UserTb::find()
+    let state = get_state(depot)?;
+    let limits = state.rate_limits();
+    let ip = client_ip(req, state.behind_proxy());
+    let account_key = format!("login:account:{name}");
+    let checks = [
+        (format!("login:ip:{ip}"), limits.login_per_ip),
+        (account_key.clone(), limits.login_per_account),
+    ];
+    if !allow_request(depot, res, &checks)? {
+        return Ok(());
+    }
+    let db = get_db(depot)?;
+    let base_url = get_base_url(depot)?;
+    let user = UserTb::find()
         .filter(user_tb::Column::Name.eq(name.clone()))
-        .filter(user_tb::Column::Password.eq(pass))
         .one(db)
-        .await?
-    else {
+        .await?;
+    let verification = match user.as_ref().and_then(|u| u.password.clone()) {
+        Some(stored) => password::verify(pass.clone(), stored).await?,
+        None => password::verify_absent(pass.clone()).await?,
+    };
+    let (Some(r), true) = (user, verification.is_valid()) else {
         let r = json!({
            "code":400,
            "msg": "用户名或密码错误",
@@ -507,9 +556,100 @@ pub async fn login(
         res.render(Text::Json(r.to_string()));
         return Ok(());
     };
+    if verification == password::Verification::NeedsRehash {
+        // Upgrade legacy MD5 hashes transparently now that we know the plain password
+        let mut upgrade = user_tb::ActiveModel::from(r.clone());
+        upgrade.password = ActiveValue::set(Some(password::hash(pass).await?));
+        upgrade.update(db).await?;
+    }
     let remember = remember_me.trim() == "true";
-    let secret_key = get_secret_key()?;
-    let token = generate_token_by_user_id(secret_key, r.id, remember).await?;
+    if r.totp_enabled {
+        // The account stays throttled until the second step is through as well
+        let ticket = two_factor::issue_ticket(state.codes(), r.id, remember).await?;
+        let r = json!({
+           "code":200,
+           "msg": "请输入两步验证码",
+           "totp": true,
+           "ticket": ticket,
+           "baseUrl":base_url
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    state.throttle().reset(&account_key);
+    let secret_key = get_secret_key(depot)?;
+    let origin = session::Origin::from_request(req, state.behind_proxy());
+    let token = session::issue(db, secret_key, r.id, r.token_version, remember, &origin).await?;
+    let r = json!({
+       "code":200,
+       "msg": "登录成功",
+       "token": token,
+       "baseUrl":base_url
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+/// Second step of the login for users with two-factor authentication: trade
+/// the ticket from the password step and a TOTP or recovery code for a token.
+#[handler]
+pub async fn login_totp(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let ticket = req.form::<String>("ticket").await.to_result()?;
+    let code = req.form::<String>("code").await.to_result()?;
+    let state = get_state(depot)?;
+    let db = state.db();
+    let base_url = state.base_url();
+    let expired = json!({
+        "code":400,
+        "msg":"两步验证已过期, 请重新登录",
+        "baseUrl":base_url
+    });
+    let Some(pending) = two_factor::find_ticket(state.codes(), &ticket).await? else {
+        res.render(Text::Json(expired.to_string()));
+        return Ok(());
+    };
+    let Some(user) = UserTb::find_by_id(pending.user_id).one(db).await? else {
+        res.render(Text::Json(expired.to_string()));
+        return Ok(());
+    };
+    // Counted under the same keys as passwords, so codes can't be guessed any faster
+    let limits = state.rate_limits();
+    let ip = client_ip(req, state.behind_proxy());
+    let account_key = format!("login:account:{}", user.name.clone().unwrap_or_default());
+    let checks = [
+        (format!("login:ip:{ip}"), limits.login_per_ip),
+        (account_key.clone(), limits.login_per_account),
+    ];
+    if !allow_request(depot, res, &checks)? {
+        return Ok(());
+    }
+    if !two_factor::verify(db, &user, &code).await? {
+        let discarded =
+            two_factor::fail_ticket(state.codes(), &ticket, state.code_max_attempts()).await?;
+        let r = json!({
+            "code":400,
+            "msg":if discarded { "验证码错误次数过多, 请重新登录" } else { "两步验证码错误" },
+            "baseUrl":base_url
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    two_factor::consume_ticket(state.codes(), &ticket).await?;
+    state.throttle().reset(&account_key);
+    let origin = session::Origin::from_request(req, state.behind_proxy());
+    let token = session::issue(
+        db,
+        state.secret_key(),
+        user.id,
+        user.token_version,
+        pending.remember,
+        &origin,
+    )
+    .await?;
     let r = json!({
        "code":200,
        "msg": "登录成功",
@@ -520,13 +660,178 @@ pub async fn login(
     Ok(())
 }
 
+/// Expire the `token` cookie set by the login page.
+fn clear_token_cookie(res: &mut Response, base_url: &str) {
+    let cookie = salvo::http::cookie::Cookie::build(("token", ""))
+        .path(base_url.to_owned())
+        .removal()
+        .build();
+    res.add_cookie(cookie);
+}
+
+#[handler]
+pub async fn oauth_start(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let provider = req.param::<String>("provider").to_result()?;
+    let reauth = req.query::<String>("reauth").is_some_and(|v| v == "1");
+    let state = get_state(depot)?;
+    // Signed in users come here to link another account to their own, or
+    // with `reauth` to stand in for the password they don't have
+    let link_user = match depot.jwt_auth_state() {
+        JwtAuthState::Authorized => Some(
+            depot
+                .jwt_auth_data::<JwtClaims>()
+                .to_result()?
+                .claims
+                .user_id
+                .parse()?,
+        ),
+        _ => None,
+    };
+    let authorization = state
+        .oauth()
+        .authorize_url(state.codes(), &provider, link_user, reauth)
+        .await?;
+    let cookie = salvo::http::cookie::Cookie::build((oauth::BINDING_COOKIE, authorization.binding))
+        .path(format!("{}oauth/", state.base_url()))
+        .http_only(true)
+        // Lax still sends it on the provider's top-level redirect back to us
+        .same_site(salvo::http::cookie::SameSite::Lax)
+        .max_age(salvo::http::cookie::time::Duration::seconds(
+            oauth::STATE_TTL.as_secs() as i64,
+        ))
+        .build();
+    res.add_cookie(cookie);
+    res.render(Redirect::found(authorization.url));
+    Ok(())
+}
+
+#[handler]
+pub async fn oauth_callback(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let provider = req.param::<String>("provider").to_result()?;
+    if req.query::<String>("error").is_some() {
+        return Err(UniformError(anyhow::anyhow!("第三方登录已取消")));
+    }
+    let code = req.query::<String>("code").to_result()?;
+    let oauth_state = req.query::<String>("state").to_result()?;
+    let state = get_state(depot)?;
+    let base_url = state.base_url();
+    let db = state.db();
+    let binding = req
+        .cookie(oauth::BINDING_COOKIE)
+        .map(|c| c.value().to_owned());
+    let removal = salvo::http::cookie::Cookie::build((oauth::BINDING_COOKIE, ""))
+        .path(format!("{base_url}oauth/"))
+        .removal()
+        .build();
+    res.add_cookie(removal);
+    let completed = state
+        .oauth()
+        .finish(
+            state.codes(),
+            &provider,
+            &code,
+            &oauth_state,
+            binding.as_deref(),
+        )
+        .await?;
+    if completed.reauth {
+        oauth::confirm_reauth(db, state.codes(), &completed).await?;
+        res.render(Redirect::other(format!("{base_url}profile")));
+        return Ok(());
+    }
+    let user = oauth::sign_in(db, &completed).await?;
+    if completed.link_user.is_some() {
+        res.render(Redirect::other(format!("{base_url}home/1")));
+        return Ok(());
+    }
+    if user.totp_enabled {
+        // The login page picks the ticket up and asks for the code
+        let ticket = two_factor::issue_ticket(state.codes(), user.id, false).await?;
+        res.render(Redirect::other(format!("{base_url}login?ticket={ticket}")));
+        return Ok(());
+    }
+    let origin = session::Origin::from_request(req, state.behind_proxy());
+    let token = session::issue(
+        db,
+        state.secret_key(),
+        user.id,
+        user.token_version,
+        false,
+        &origin,
+    )
+    .await?;
+    let cookie = salvo::http::cookie::Cookie::build(("token", token))
+        .path(base_url.to_owned())
+        .same_site(salvo::http::cookie::SameSite::Lax)
+        .build();
+    res.add_cookie(cookie);
+    res.render(Redirect::other(format!("{base_url}home/1")));
+    Ok(())
+}
+
+#[handler]
+pub async fn logout(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let db = get_db(depot)?;
+    let base_url = get_base_url(depot)?;
+    // Signing out without a valid session is not an error, there is just nothing to revoke
+    if depot.jwt_auth_state() == JwtAuthState::Authorized {
+        let data = depot.jwt_auth_data::<JwtClaims>().to_result()?;
+        session::revoke(db, &data.claims.jti).await?;
+    }
+    clear_token_cookie(res, base_url);
+    let r = json!({
+       "code":200,
+       "msg": "已退出登录",
+       "baseUrl":base_url
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+#[handler]
+pub async fn logout_everywhere(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let db = get_db(depot)?;
+    let base_url = get_base_url(depot)?;
+    let user_id = depot
+        .jwt_auth_data::<JwtClaims>()
+        .to_result()?
+        .claims
+        .user_id
+        .parse()?;
+    session::revoke_all(db, user_id).await?;
+    clear_token_cookie(res, base_url);
+    let r = json!({
+       "code":200,
+       "msg": "已退出所有设备",
+       "baseUrl":base_url
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
 #[handler]
 pub async fn person_list(
     req: &mut Request,
     res: &mut Response,
     depot: &mut Depot,
 ) -> Result<(), UniformError> {
-    let base_url = get_base_url()?;
+    let base_url = get_base_url(depot)?;
     let page = match req.param::<u64>("page") {
         Some(x) if x >= 1 => x - 1,
         _ => {
@@ -536,46 +841,11 @@ pub async fn person_list(
         }
     };
     let data = depot.jwt_auth_data::<JwtClaims>().to_result()?;
-    let user_id = data.claims.user_id.clone();
-    let db = get_db()?;
+    let user_id: i32 = data.claims.user_id.parse()?;
+    let db = get_db(depot)?;
     let offset = page * 10;
-    let sql = r#"SELECT
-	R.AID,
-	R.`name`,
-	R.title,
-	R.view_count,
-	R.update_time,
-	R.article_state,
-    R.level,
-	COUNT( comment_tb.id ) AS comment_count
-FROM
-	(
-	SELECT
-		tag_tb.`name`,
-		article_tb.title,
-		article_tb.id AS AID,
-		article_tb.update_time,
-		article_tb.article_state,
-        article_tb.level,
-		COUNT( view_tb.article_id ) AS view_count
-	FROM
-		article_tb
-		LEFT JOIN tag_tb ON tag_tb.id = article_tb.tag_id
-		LEFT JOIN view_tb ON view_tb.article_id = article_tb.id
-	WHERE
-		article_tb.user_id = ?
-	GROUP BY
-		AID
-	) AS R
-	LEFT JOIN comment_tb ON comment_tb.article_id = R.AID
-GROUP BY
-	AID
-ORDER BY
-	R.update_time DESC
-	LIMIT ?, 10"#;
-    let tera = get_tera()?;
     let total_count = ArticleTb::find()
-        .filter(article_tb::Column::UserId.eq(user_id.as_str()))
+        .filter(article_tb::Column::UserId.eq(user_id))
         .count(db)
         .await?;
     let total_page = if total_count >= 10 {
@@ -591,29 +861,65 @@ ORDER BY
     if page != 0 && page + 1 > total_page {
         return Err(UniformError(anyhow::anyhow!("请求的资源不存在")));
     }
-    let sql_statement = Statement::from_sql_and_values(
-        DatabaseBackend::MySql,
-        sql,
-        [
-            Value::BigInt(Some(user_id.parse()?)),
-            Value::BigUnsigned(Some(offset)),
-        ],
-    );
-    let r = ArticleTb::find()
-        .from_raw_sql(sql_statement)
+    let query = Query::select()
+        .expr_as(
+            Expr::col((article_tb::Entity, article_tb::Column::Id)),
+            Alias::new("AID"),
+        )
+        .column((tag_tb::Entity, tag_tb::Column::Name))
+        .columns([
+            (article_tb::Entity, article_tb::Column::Title),
+            (article_tb::Entity, article_tb::Column::UpdateTime),
+            (article_tb::Entity, article_tb::Column::ArticleState),
+            (article_tb::Entity, article_tb::Column::Hidden),
+            (article_tb::Entity, article_tb::Column::Level),
+        ])
+        .from(article_tb::Entity)
+        .left_join(
+            tag_tb::Entity,
+            Expr::col((tag_tb::Entity, tag_tb::Column::Id))
+                .equals((article_tb::Entity, article_tb::Column::TagId)),
+        )
+        .and_where(Expr::col((article_tb::Entity, article_tb::Column::UserId)).eq(user_id))
+        .order_by(
+            (article_tb::Entity, article_tb::Column::UpdateTime),
+            Order::Desc,
+        )
+        .limit(10)
+        .offset(offset)
+        .to_owned();
+    let mut r = ArticleTb::find()
+        .from_raw_sql(db.get_database_backend().build(&query))
         .into_json()
         .all(db)
         .await?;
-    let info = get_person_right_state(user_id.parse()?, db).await?;
+    let article_ids: Vec<i32> = r
+        .iter()
+        .filter_map(|v| v.get("AID")?.as_i64())
+        .map(|id| id as i32)
+        .collect();
+    let mut counts_map = count_views_and_comments(&article_ids, db).await?;
+    for article in r.iter_mut() {
+        let article_id = article.get("AID").and_then(|v| v.as_i64()).unwrap_or(0) as i32;
+        let (view_count, comment_count) = counts_map.remove(&article_id).unwrap_or((0, 0));
+        article["view_count"] = json!(view_count);
+        article["comment_count"] = json!(comment_count);
+    }
+    let info = get_person_right_state(user_id, db).await?;
+    let notice = Standing::of(&info.0).notice();
     let avatar = info.0.avatar.unwrap_or_default();
     let username = info.0.name.unwrap_or_default();
-    let level = info.0.privilege.unwrap_or_default();
+    let role = Role::from_privilege(info.0.privilege);
+    let level = access::clearance(role, info.0.clearance);
+    let role = role.title();
     let post_count = info.1;
     let login_v = json!({
         "login":true,
         "avatar":avatar,
         "name":username,
         "level":level,
+        "role":role,
+        "notice":notice,
         "post_count":post_count
     });
 
@@ -625,18 +931,89 @@ ORDER BY
     context.insert("total", &total_count);
     context.insert("baseUrl", &base_url);
     context.insert("hotArticles", &hot_list);
-    let r = tera.render("list.html", &context)?;
+    let r = render_page(depot, "list.html", &mut context)?;
+    res.render(Text::Html(r));
+    Ok(())
+}
+
+/// The public page of an author: their profile and the articles anyone can
+/// see on the home page, plus their private and hidden ones when they look
+/// themselves.
+#[handler]
+pub async fn author_page(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let base_url = get_base_url(depot)?;
+    let author_id = req.param::<i32>("id").to_result()?;
+    let page = match req.param::<u64>("page") {
+        Some(x) if x >= 1 => x - 1,
+        _ => {
+            let uri = format!("{base_url}user/{author_id}/1");
+            res.render(Redirect::other(uri));
+            return Ok(());
+        }
+    };
+    let db = get_db(depot)?;
+    let Some(author) = UserTb::find_by_id(author_id).one(db).await? else {
+        return Err(UniformError(anyhow::anyhow!("用户不存在")));
+    };
+    let is_self = access::current(depot, db)
+        .await?
+        .is_some_and(|viewer| viewer.id == author_id);
+    let mut find = ArticleTb::find()
+        .order_by_desc(article_tb::Column::UpdateTime)
+        .filter(article_tb::Column::UserId.eq(author_id))
+        .filter(article_tb::Column::ArticleState.eq(1));
+    if !is_self {
+        find = find
+            .filter(article_tb::Column::Level.ne(access::PRIVATE_LEVEL))
+            .filter(article_tb::Column::Hidden.eq(false));
+    }
+    let pagination = find.into_json().paginate(db, 10);
+    let total_count = pagination.num_items().await?;
+    let total_pages = total_count.div_ceil(10);
+    if page != 0 && (page + 1) > total_pages {
+        return Err(UniformError(anyhow::anyhow!("请求的资源不存在")));
+    }
+    let mut data = pagination.fetch_page(page).await?;
+    enrich_articles_with_metadata(&mut data, db).await?;
+
+    let role = Role::from_privilege(author.privilege).title();
+    let author_v = json!({
+        "id":author.id,
+        "name":author.name.clone().unwrap_or_default(),
+        "displayName":profile::display_name(&author),
+        "avatar":author.avatar.clone().unwrap_or_default(),
+        "role":role,
+        "since":author.create_time.map(|t| t.format("%Y-%m-%d").to_string()),
+        "profile":Profile::of(&author).to_json(),
+        "post_count":total_count,
+    });
+    let hot_list = get_hot_article_list(db).await?;
+    let mut context = Context::new();
+    context.insert("baseUrl", base_url);
+    context.insert("author", &author_v);
+    context.insert("articles", &data);
+    context.insert("total", &total_count);
+    context.insert("page", &(page + 1));
+    context.insert("hotArticles", &hot_list);
+    let r = render_page(depot, "user.html", &mut context)?;
     res.render(Text::Html(r));
     Ok(())
 }
 
 #[handler]
-pub async fn register(_req: &mut Request, res: &mut Response) -> Result<(), UniformError> {
-    let base_url = get_base_url()?;
-    let tera = get_tera()?;
+pub async fn register(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let base_url = get_base_url(depot)?;
     let mut context = Context::new();
     context.insert("baseUrl", base_url);
-    let r = tera.render("reg.html", &context)?;
+    let r = render_page(depot, "reg.html", &mut context)?;
     res.render(Text::Html(r));
     Ok(())
 }
@@ -645,6 +1022,7 @@ pub async fn register(_req: &mut Request, res: &mut Response) -> Result<(), Unif
 pub async fn post_register(
     req: &mut Request,
     res: &mut Response,
+    depot: &mut Depot,
 ) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
     let name = req.form::<String>("nickName").await.to_result()?;
     let pass = req.form::<String>("password").await.to_result()?;
@@ -658,18 +1036,17 @@ pub async fn post_register(
         .await
         .ok_or(anyhow::anyhow!("code is required"))?;
 
-    let redis_url = get_redis_url()?;
-    let client = redis::Client::open(redis_url)?;
-    let mut con = client.get_connection()?;
-    let code = con
-        .get(&email)?
-        .ok_or(anyhow::anyhow!("未通过邮箱验证, 未找到邮箱的验证码"))?;
-    if code != email_code {
-        let r = json!({
-            "code":400,
-            "msg":"邮箱验证码错误"
-        });
-        res.render(Text::Json(r.to_string()));
+    let state = get_state(depot)?;
+    let ip = client_ip(req, state.behind_proxy());
+    let limits = state.rate_limits();
+    let checks = [
+        (format!("register:ip:{ip}"), limits.register_per_ip),
+        (
+            format!("register:email:{}", email.to_lowercase()),
+            limits.register_per_email,
+        ),
+    ];
+    if !allow_request(depot, res, &checks)? {
         return Ok(());
     }
 
@@ -690,12 +1067,8 @@ pub async fn post_register(
         return Ok(());
     }
 
-    let db = get_db()?;
-    let count = UserTb::find()
-        .filter(user_tb::Column::Name.eq(name.clone()))
-        .count(db)
-        .await?;
-    if count != 0 {
+    let db = get_db(depot)?;
+    if name_taken(db, &name, None).await? {
         let r = json!({
             "code":400,
             "msg":"用户名已存在"
@@ -703,20 +1076,30 @@ pub async fn post_register(
         res.render(Text::Json(r.to_string()));
         return Ok(());
     } else {
+        // Checked last, as an accepted code is used up
+        if let Some(msg) = check_email_code(depot, Purpose::Register, &email, &email_code).await? {
+            let r = json!({
+                "code":400,
+                "msg":msg
+            });
+            res.render(Text::Json(r.to_string()));
+            return Ok(());
+        }
         let mut add_user = user_tb::ActiveModel::new();
         add_user.avatar = ActiveValue::set(None);
         let time_now = Local::now();
         add_user.create_time = ActiveValue::set(Some(time_now.naive_local()));
-        add_user.email = ActiveValue::set(Some(email));
+        add_user.email = ActiveValue::set(Some(email.clone()));
         add_user.name = ActiveValue::set(Some(name));
-        let pass = format!("{:?}", md5::compute(pass));
+        let pass = password::hash(pass).await?;
         add_user.password = ActiveValue::set(Some(pass));
         add_user.update_time = ActiveValue::set(Some(time_now.naive_local()));
-        add_user.privilege = ActiveValue::set(Some(2));
+        add_user.privilege = ActiveValue::set(Some(access::Role::Author.privilege()));
         let r = UserTb::insert(add_user).exec(db).await?.last_insert_id;
-        let secret_key = get_secret_key()?;
-        let token = generate_token_by_user_id(secret_key, r, false).await?;
-        let base_url = get_base_url()?;
+        let secret_key = get_secret_key(depot)?;
+        let origin = session::Origin::from_request(req, state.behind_proxy());
+        let token = session::issue(db, secret_key, r, 0, false, &origin).await?;
+        let base_url = get_base_url(depot)?;
         let r = json!({
            "code":200,
            "token":token,
@@ -729,12 +1112,15 @@ pub async fn post_register(
 }
 
 #[handler]
-pub async fn forgetpass(_req: &mut Request, res: &mut Response) -> Result<(), UniformError> {
-    let base_url = get_base_url()?;
-    let tera = get_tera()?;
+pub async fn forgetpass(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let base_url = get_base_url(depot)?;
     let mut context = Context::new();
     context.insert("baseUrl", base_url);
-    let r = tera.render("forget.html", &context)?;
+    let r = render_page(depot, "forget.html", &mut context)?;
     res.render(Text::Html(r));
     Ok(())
 }
@@ -743,6 +1129,7 @@ pub async fn forgetpass(_req: &mut Request, res: &mut Response) -> Result<(), Un
 pub async fn post_forget(
     req: &mut Request,
     res: &mut Response,
+    depot: &mut Depot,
 ) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
     let name = req.form::<String>("nickName").await.to_result()?;
     let pass = req.form::<String>("password").await.to_result()?;
@@ -756,18 +1143,14 @@ pub async fn post_forget(
         .await
         .ok_or(anyhow::anyhow!("code is required"))?;
 
-    let redis_url = get_redis_url()?;
-    let client = redis::Client::open(redis_url)?;
-    let mut con = client.get_connection()?;
-    let code = con
-        .get(&email)?
-        .ok_or(anyhow::anyhow!("未通过邮箱验证, 未找到邮箱的验证码"))?;
-    if code != email_code {
-        let r = json!({
-            "code":400,
-            "msg":"邮箱验证码错误"
-        });
-        res.render(Text::Json(r.to_string()));
+    let state = get_state(depot)?;
+    let limits = state.rate_limits();
+    let ip = client_ip(req, state.behind_proxy());
+    let checks = [
+        (format!("forget:ip:{ip}"), limits.forget_per_ip),
+        (format!("forget:account:{name}"), limits.forget_per_account),
+    ];
+    if !allow_request(depot, res, &checks)? {
         return Ok(());
     }
 
@@ -788,20 +1171,33 @@ pub async fn post_forget(
         return Ok(());
     }
 
-    let db = get_db()?;
+    // After the password checks, as an accepted code is used up
+    if let Some(msg) = check_email_code(depot, Purpose::ResetPassword, &email, &email_code).await? {
+        let r = json!({
+            "code":400,
+            "msg":msg
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+
+    let db = get_db(depot)?;
     let user = UserTb::find()
         .filter(user_tb::Column::Name.eq(name.clone()))
         .filter(user_tb::Column::Email.eq(&email))
         .one(db)
         .await?;
     if let Some(user) = user {
+        let user_id = user.id;
         let mut user = user_tb::ActiveModel::from(user);
-        let pass = format!("{:?}", md5::compute(pass));
+        let pass = password::hash(pass).await?;
         user.password = ActiveValue::set(Some(pass));
         let time_now = Local::now();
         user.update_time = ActiveValue::set(Some(time_now.naive_local()));
         user.update(db).await?;
-        let base_url = get_base_url()?;
+        // Whoever knew the old password must not stay signed in
+        session::revoke_all(db, user_id).await?;
+        let base_url = get_base_url(depot)?;
         let r = json!({
            "code":200,
            "msg":"重置账号成功",
@@ -822,27 +1218,44 @@ async fn get_comments_from_article_id(
     article_id: i32,
     db: &DatabaseConnection,
 ) -> Result<Vec<JsonValue>, UniformError> {
-    let sql = r#"SELECT
-	comment_tb.id,
-	comment_tb.`comment`,
-	comment_tb.md_content,
-    comment_tb.create_time,
-	comment_tb.update_time,
-	user_tb.id AS user_id,
-	user_tb.avatar,
-	user_tb.`name` AS userName,
-	user_tb.privilege AS level
-FROM
-	comment_tb
-	LEFT JOIN user_tb ON comment_tb.user_id = user_tb.id
-WHERE
-	comment_tb.article_id = ?
-ORDER BY
-	comment_tb.create_time"#;
-    let sql_statement =
-        Statement::from_sql_and_values(DatabaseBackend::MySql, sql, [Value::Int(Some(article_id))]);
+    let query = Query::select()
+        .columns([
+            (comment_tb::Entity, comment_tb::Column::Id),
+            (comment_tb::Entity, comment_tb::Column::Comment),
+            (comment_tb::Entity, comment_tb::Column::MdContent),
+            (comment_tb::Entity, comment_tb::Column::CreateTime),
+            (comment_tb::Entity, comment_tb::Column::UpdateTime),
+        ])
+        .expr_as(
+            Expr::col((user_tb::Entity, user_tb::Column::Id)),
+            Alias::new("user_id"),
+        )
+        .column((user_tb::Entity, user_tb::Column::Avatar))
+        .expr_as(
+            Func::coalesce([
+                Expr::col((user_tb::Entity, user_tb::Column::DisplayName)).into(),
+                Expr::col((user_tb::Entity, user_tb::Column::Name)).into(),
+            ]),
+            Alias::new("userName"),
+        )
+        .expr_as(
+            Expr::col((user_tb::Entity, user_tb::Column::Privilege)),
+            Alias::new("level"),
+        )
+        .from(comment_tb::Entity)
+        .left_join(
+            user_tb::Entity,
+            Expr::col((comment_tb::Entity, comment_tb::Column::UserId))
+                .equals((user_tb::Entity, user_tb::Column::Id)),
+        )
+        .and_where(Expr::col((comment_tb::Entity, comment_tb::Column::ArticleId)).eq(article_id))
+        .order_by(
+            (comment_tb::Entity, comment_tb::Column::CreateTime),
+            Order::Asc,
+        )
+        .to_owned();
     let r = CommentTb::find()
-        .from_raw_sql(sql_statement)
+        .from_raw_sql(db.get_database_backend().build(&query))
         .into_json()
         .all(db)
         .await?;
@@ -853,24 +1266,34 @@ async fn get_article_and_author_by_article_id(
     article_id: i32,
     db: &DatabaseConnection,
 ) -> Result<JsonValue, UniformError> {
-    let sql = r#"SELECT
-	article_tb.id,
-	article_tb.title,
-	article_tb.create_time,
-	article_tb.update_time,
-	article_tb.`level`,
-	article_tb.content,
-	user_tb.`name` as userName,
-    article_tb.user_id as user_id
-FROM
-	article_tb
-	LEFT JOIN user_tb ON user_tb.id = article_tb.user_id
-WHERE
-	article_tb.id = ?"#;
-    let sql_statement =
-        Statement::from_sql_and_values(DatabaseBackend::MySql, sql, [Value::Int(Some(article_id))]);
+    let query = Query::select()
+        .columns([
+            (article_tb::Entity, article_tb::Column::Id),
+            (article_tb::Entity, article_tb::Column::Title),
+            (article_tb::Entity, article_tb::Column::CreateTime),
+            (article_tb::Entity, article_tb::Column::UpdateTime),
+            (article_tb::Entity, article_tb::Column::Level),
+            (article_tb::Entity, article_tb::Column::Content),
+            (article_tb::Entity, article_tb::Column::Hidden),
+        ])
+        .expr_as(
+            Func::coalesce([
+                Expr::col((user_tb::Entity, user_tb::Column::DisplayName)).into(),
+                Expr::col((user_tb::Entity, user_tb::Column::Name)).into(),
+            ]),
+            Alias::new("userName"),
+        )
+        .column((article_tb::Entity, article_tb::Column::UserId))
+        .from(article_tb::Entity)
+        .left_join(
+            user_tb::Entity,
+            Expr::col((user_tb::Entity, user_tb::Column::Id))
+                .equals((article_tb::Entity, article_tb::Column::UserId)),
+        )
+        .and_where(Expr::col((article_tb::Entity, article_tb::Column::Id)).eq(article_id))
+        .to_owned();
     let r = ArticleTb::find()
-        .from_raw_sql(sql_statement)
+        .from_raw_sql(db.get_database_backend().build(&query))
         .into_json()
         .one(db)
         .await?
@@ -887,6 +1310,69 @@ async fn increase_view_count(article_id: i32, db: &DatabaseConnection) -> Result
     Ok(())
 }
 
+/// Put the rendered HTML of the article and its comments under `html`.
+///
+/// Comments stored before their Markdown was kept fall back to their HTML.
+fn render_markdown<const E: u8>(
+    depot: &Depot,
+    article_id: i32,
+    article: &mut JsonValue,
+    comments: &mut [JsonValue],
+) -> Result<(), UniformError<E>> {
+    let state = get_state(depot)?;
+    let markdown = state.markdown();
+    let page = format!("article/{article_id}");
+    let stamp = |item: &JsonValue| item.get("update_time").map(JsonValue::to_string);
+
+    let content = article
+        .get("content")
+        .and_then(JsonValue::as_str)
+        .unwrap_or_default();
+    let html = markdown.cached(
+        Source::Article,
+        article_id,
+        &stamp(article).unwrap_or_default(),
+        content,
+        Target {
+            page: &page,
+            id_prefix: "",
+        },
+    );
+    article["html"] = json!(&*html);
+
+    for comment in comments {
+        let Some(id) = comment
+            .get("id")
+            .and_then(JsonValue::as_i64)
+            .and_then(|id| i32::try_from(id).ok())
+        else {
+            continue;
+        };
+        let html = match comment.get("md_content").and_then(JsonValue::as_str) {
+            Some(md) => markdown
+                .cached(
+                    Source::Comment,
+                    id,
+                    &stamp(comment).unwrap_or_default(),
+                    md,
+                    Target {
+                        page: &page,
+                        id_prefix: &format!("comment-{id}-"),
+                    },
+                )
+                .to_string(),
+            None => state.sanitizer().clean(
+                comment
+                    .get("comment")
+                    .and_then(JsonValue::as_str)
+                    .unwrap_or_default(),
+            ),
+        };
+        comment["html"] = json!(html);
+    }
+    Ok(())
+}
+
 #[handler]
 pub async fn read_article(
     req: &mut Request,
@@ -895,96 +1381,68 @@ pub async fn read_article(
 ) -> Result<(), UniformError> {
     let article_id: i32 = req.param("id").to_result()?;
 
-    let db = get_db()?;
+    let db = get_db(depot)?;
 
-    let article_model = get_article_and_author_by_article_id(article_id, db).await?;
+    let mut article_model = get_article_and_author_by_article_id(article_id, db).await?;
 
     let need_level = article_model
         .get("level")
         .to_result()?
-        .as_u64()
+        .as_i64()
         .to_result()?;
-
-    let base_url = get_base_url()?;
-
-    let tera = get_tera()?;
-    match depot.jwt_auth_state() {
-        JwtAuthState::Authorized => {
-            let data = depot.jwt_auth_data::<JwtClaims>().to_result()?;
-            let user_id = &data.claims.user_id;
-            let person = UserTb::find_by_id(user_id.parse::<i32>()?)
-                .one(db)
-                .await?
-                .to_result()?;
-            let currend_id = data.claims.user_id.parse::<u64>()?;
-            if need_level == 999 {
-                if currend_id
-                    == article_model
-                        .get("user_id")
-                        .to_result()?
-                        .as_u64()
-                        .to_result()?
-                {
-                    let comments = get_comments_from_article_id(article_id, db).await?;
-                    let context = construct_context!["info"=>article_model,"comments"=>comments,"baseUrl"=>base_url,"currentId"=>currend_id];
-                    let r = tera.render("article.html", &context)?;
-                    res.render(Text::Html(r));
-                } else {
-                    let context = construct_context!["code"=>404, "msg"=>"没有该文章的阅读权限","baseUrl"=>base_url];
-                    let r = tera.render("404.html", &context)?;
-                    res.render(Text::Html(r));
-                }
-            } else if need_level <= person.privilege.unwrap_or(1) as u64 {
-                increase_view_count(article_id, db).await?;
-                let comments = get_comments_from_article_id(article_id, db).await?;
-                let context = construct_context!["info"=>article_model,"comments"=>comments,"baseUrl"=>base_url,"currentId"=>currend_id];
-                let r = tera.render("article.html", &context)?;
-                res.render(Text::Html(r));
-            } else {
-                let context = construct_context!["code"=>404, "msg"=>"没有该文章的阅读权限","baseUrl"=>base_url];
-                let r = tera.render("404.html", &context)?;
-                res.render(Text::Html(r));
-            }
-        }
-        JwtAuthState::Unauthorized => {
-            if need_level == 999 {
-                let context = construct_context!["code"=>404, "msg"=>"没有该文章的阅读权限","baseUrl"=>base_url];
-                let r = tera.render("404.html", &context)?;
-                res.render(Text::Html(r));
-            } else if need_level <= 1 {
-                increase_view_count(article_id, db).await?;
-                let comments = get_comments_from_article_id(article_id, db).await?;
-                let context = construct_context!["info"=>article_model,"comments"=>comments,"baseUrl"=>base_url,"currentId"=>Option::<i32>::None];
-                let r = tera.render("article.html", &context)?;
-                res.render(Text::Html(r));
-            } else {
-                let context = construct_context!["code"=>404, "msg"=>"没有该文章的阅读权限","baseUrl"=>base_url];
-                let r = tera.render("404.html", &context)?;
-                res.render(Text::Html(r));
-            }
-        }
-        JwtAuthState::Forbidden => {
-            if need_level == 999 {
-                let context = construct_context!["code"=>404, "msg"=>"没有该文章的阅读权限","baseUrl"=>base_url];
-                let r = tera.render("404.html", &context)?;
-                res.render(Text::Html(r));
-            } else if need_level <= 1 {
-                increase_view_count(article_id, db).await?;
-                let comments = get_comments_from_article_id(article_id, db).await?;
-                let context = construct_context!["info"=>article_model,"comments"=>comments,"baseUrl"=>base_url,"currentId"=>Option::<i32>::None];
-                let r = tera.render("article.html", &context)?;
-                res.render(Text::Html(r));
-            } else {
-                let context = construct_context!["code"=>404, "msg"=>"没有该文章的阅读权限","baseUrl"=>base_url];
-                let r = tera.render("404.html", &context)?;
-                res.render(Text::Html(r));
-            }
-        }
+    let author_id = article_model
+        .get("user_id")
+        .and_then(JsonValue::as_i64)
+        .unwrap_or_default();
+
+    let base_url = get_base_url(depot)?;
+
+    let tera = get_tera(depot)?;
+    let reader = access::current(depot, db).await?;
+    if !access::can_read(
+        need_level,
+        author_id,
+        reader.as_ref().map(|v| (i64::from(v.id), v.clearance)),
+    ) {
+        let context =
+            construct_context!["code"=>404, "msg"=>"没有该文章的阅读权限","baseUrl"=>base_url];
+        let r = tera.render("404.html", &context)?;
+        res.render(Text::Html(r));
+        return Ok(());
+    }
+    // Articles a moderator hid stay readable to the author and to moderators
+    // SQLite hands booleans back as integers
+    let hidden = match article_model.get("hidden") {
+        Some(JsonValue::Bool(b)) => *b,
+        Some(v) => v.as_i64().is_some_and(|n| n != 0),
+        None => false,
     };
-    Ok(())
-}
-
-#[handler]
+    let sees_hidden = reader
+        .as_ref()
+        .is_some_and(|v| i64::from(v.id) == author_id || v.can(Permission::ArticleModerate));
+    if hidden && !sees_hidden {
+        let context =
+            construct_context!["code"=>404, "msg"=>"该文章已被管理员隐藏","baseUrl"=>base_url];
+        let r = tera.render("404.html", &context)?;
+        res.render(Text::Html(r));
+        return Ok(());
+    }
+    // Authors looking at their private drafts don't count as views
+    if need_level != access::PRIVATE_LEVEL {
+        increase_view_count(article_id, db).await?;
+    }
+    let current_id = reader.as_ref().map(|v| v.id);
+    let can_moderate = reader.is_some_and(|v| v.can(Permission::CommentModerate));
+    let mut comments = get_comments_from_article_id(article_id, db).await?;
+    render_markdown(depot, article_id, &mut article_model, &mut comments)?;
+    let highlight_theme = get_state(depot)?.highlight_theme();
+    let mut context = construct_context!["info"=>article_model,"comments"=>comments,"baseUrl"=>base_url,"currentId"=>current_id,"canModerate"=>can_moderate,"highlightTheme"=>highlight_theme];
+    let r = render_page(depot, "article.html", &mut context)?;
+    res.render(Text::Html(r));
+    Ok(())
+}
+
+#[handler]
 pub async fn delete_comment(
     req: &mut Request,
     res: &mut Response,
@@ -997,12 +1455,14 @@ pub async fn delete_comment(
         .claims
         .user_id;
     let identifier = identifier.as_str();
-    let db = get_db()?;
-    let r = CommentTb::find_by_id(comment_id)
-        .filter(comment_tb::Column::UserId.eq(identifier))
-        .count(db)
-        .await?;
-    let base_url = get_base_url()?;
+    let db = get_db(depot)?;
+    let mut comment = CommentTb::find_by_id(comment_id);
+    // Moderators may remove anybody's comment, everyone else only their own
+    if !access::allowed(depot, db, Permission::CommentModerate).await? {
+        comment = comment.filter(comment_tb::Column::UserId.eq(identifier.parse::<i32>()?));
+    }
+    let r = comment.count(db).await?;
+    let base_url = get_base_url(depot)?;
     if r == 1 {
         let _ = CommentTb::delete_by_id(comment_id).exec(db).await?;
         let r = json!({
@@ -1034,17 +1494,17 @@ pub async fn edit_comment(
         .claims
         .user_id;
     let identifier = identifier.as_str();
-    let db = get_db()?;
+    let db = get_db(depot)?;
     let r = CommentTb::find_by_id(comment_id)
-        .filter(comment_tb::Column::UserId.eq(identifier))
+        .filter(comment_tb::Column::UserId.eq(identifier.parse::<i32>()?))
         .into_json()
         .one(db)
         .await?;
-    let tera = get_tera()?;
-    let base_url = get_base_url()?;
+    let tera = get_tera(depot)?;
+    let base_url = get_base_url(depot)?;
     if let Some(x) = r {
-        let context = construct_context!["info"=>x,"baseUrl"=>base_url];
-        let r = tera.render("editcomment.html", &context)?;
+        let mut context = construct_context!["info"=>x,"baseUrl"=>base_url];
+        let r = render_page(depot, "editcomment.html", &mut context)?;
         res.render(Text::Html(r));
     } else {
         let context =
@@ -1064,7 +1524,10 @@ pub async fn save_edit_comment(
     let comment_id = req.param::<i32>("id").to_result()?;
     let comment: String = req.form("comment").await.to_result()?;
     let md_content: String = req.form("md_content").await.to_result()?;
-    let db = get_db()?;
+    let sanitizer = get_state(depot)?.sanitizer();
+    let comment = sanitizer.clean(&comment);
+    let md_content = sanitizer.clean_markdown(&md_content);
+    let db = get_db(depot)?;
     let identifier = &depot
         .jwt_auth_data::<JwtClaims>()
         .to_result()?
@@ -1072,9 +1535,9 @@ pub async fn save_edit_comment(
         .user_id;
     let identifier = identifier.as_str();
     //let tera = depot.get::<Tera>("tera").to_result()?;
-    let base_url = get_base_url()?;
+    let base_url = get_base_url(depot)?;
     let model = CommentTb::find_by_id(comment_id)
-        .filter(comment_tb::Column::UserId.eq(identifier))
+        .filter(comment_tb::Column::UserId.eq(identifier.parse::<i32>()?))
         .one(db)
         .await?;
     if let Some(x) = model {
@@ -1083,6 +1546,9 @@ pub async fn save_edit_comment(
         update.md_content = ActiveValue::set(Some(md_content));
         update.update_time = ActiveValue::set(Some(get_current_time()));
         let _ = update.update(db).await?;
+        get_state(depot)?
+            .markdown()
+            .forget(Source::Comment, comment_id);
         let r = json!({
             "code":200,
         });
@@ -1113,6 +1579,9 @@ pub async fn add_comment(
     let article_id = req.param::<i32>("id").to_result()?;
     let comment: String = req.form("comment").await.to_result()?;
     let md_comment: String = req.form("md_content").await.to_result()?;
+    let sanitizer = get_state(depot)?.sanitizer();
+    let comment = sanitizer.clean(&comment);
+    let md_comment = sanitizer.clean_markdown(&md_comment);
     let mut model = comment_tb::ActiveModel::new();
     model.article_id = ActiveValue::set(Some(article_id));
     model.comment = ActiveValue::set(Some(comment));
@@ -1121,7 +1590,7 @@ pub async fn add_comment(
     model.md_content = ActiveValue::set(Some(md_comment));
     model.update_time = now;
     model.user_id = ActiveValue::set(Some(identifier));
-    let db = get_db()?;
+    let db = get_db(depot)?;
     let _ = model.insert(db).await?;
     let r = json!({
         "code":200
@@ -1163,18 +1632,27 @@ pub async fn upload(
 pub async fn render_add_article_view(
     _req: &mut Request,
     res: &mut Response,
+    depot: &mut Depot,
 ) -> Result<(), UniformError> {
-    let base_url = get_base_url()?;
-    let db = get_db()?;
+    let base_url = get_base_url(depot)?;
+    let db = get_db(depot)?;
     let tags = TagTb::find().into_json().all(db).await?;
     let levels = LevelTb::find().into_json().all(db).await?;
-    let context = construct_context!["tags"=>tags,"levels"=>levels,"baseUrl"=>base_url];
-    let tera = get_tera()?;
-    let r = tera.render("add.html", &context)?;
+    let mut context = construct_context!["tags"=>tags,"levels"=>levels,"baseUrl"=>base_url];
+    let r = render_page(depot, "add.html", &mut context)?;
     res.render(Text::Html(r));
     Ok(())
 }
 
+/// Whether `level` is one of the reading levels in `level_tb`.
+async fn level_exists(db: &DatabaseConnection, level: i32) -> anyhow::Result<bool> {
+    let found = LevelTb::find()
+        .filter(level_tb::Column::Level.eq(level))
+        .count(db)
+        .await?;
+    Ok(found > 0)
+}
+
 #[handler]
 pub async fn add_article(
     req: &mut Request,
@@ -1198,8 +1676,17 @@ pub async fn add_article(
             .claims
             .user_id;
         let identifier = identifier.as_str();
-        let base_url = get_base_url()?;
-        let db = get_db()?;
+        let base_url = get_base_url(depot)?;
+        let db = get_db(depot)?;
+        if !level_exists(db, level).await? {
+            let r = json!({
+                "code":400,
+                "msg":"阅读等级不存在"
+            });
+            res.render(Text::Json(r.to_string()));
+            return Ok(());
+        }
+        let content = get_state(depot)?.sanitizer().clean_markdown(&content);
         let mut model = article_tb::ActiveModel::new();
         model.article_state = ActiveValue::set(Some(1));
         model.content = ActiveValue::set(Some(content));
@@ -1236,12 +1723,12 @@ pub async fn render_article_edit_view(
 
     let identifier = identifier.as_str();
 
-    let base_url = get_base_url()?;
+    let base_url = get_base_url(depot)?;
 
-    let db = get_db()?;
+    let db = get_db(depot)?;
 
     let model = ArticleTb::find_by_id(article_id)
-        .filter(article_tb::Column::UserId.eq(identifier))
+        .filter(article_tb::Column::UserId.eq(identifier.parse::<i32>()?))
         .into_json()
         .one(db)
         .await?
@@ -1249,10 +1736,9 @@ pub async fn render_article_edit_view(
 
     let tags = TagTb::find().into_json().all(db).await?;
     let levels = LevelTb::find().into_json().all(db).await?;
-    let context =
+    let mut context =
         construct_context!["tags"=>tags,"levels"=>levels,"baseUrl"=>base_url,"article"=>model];
-    let tera = get_tera()?;
-    let r = tera.render("edit.html", &context)?;
+    let r = render_page(depot, "edit.html", &mut context)?;
     res.render(Text::Html(r));
     Ok(())
 }
@@ -1270,11 +1756,11 @@ pub async fn edit_article(
         .to_result()?
         .claims
         .user_id;
-    let base_url = get_base_url()?;
+    let base_url = get_base_url(depot)?;
 
-    let db = get_db()?;
+    let db = get_db(depot)?;
     let model = ArticleTb::find_by_id(article_id)
-        .filter(article_tb::Column::UserId.eq(identifier.as_str()))
+        .filter(article_tb::Column::UserId.eq(identifier.parse::<i32>()?))
         .one(db)
         .await?
         .to_result()?;
@@ -1282,13 +1768,25 @@ pub async fn edit_article(
     let tag = req.form::<i32>("tag").await.to_result()?;
     let title = req.form::<String>("title").await.to_result()?;
     let content = req.form::<String>("content").await.to_result()?;
+    let content = get_state(depot)?.sanitizer().clean_markdown(&content);
     let level = req.form::<i32>("level").await.to_result()?;
+    if !level_exists(db, level).await? {
+        let r = json!({
+            "code":400,
+            "msg":"阅读等级不存在"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
     model.tag_id = ActiveValue::set(Some(tag));
     model.title = ActiveValue::set(Some(title));
     model.content = ActiveValue::set(Some(content));
     model.level = ActiveValue::set(Some(level));
     model.update_time = ActiveValue::set(Some(get_current_time()));
     model.update(db).await?;
+    get_state(depot)?
+        .markdown()
+        .forget(Source::Article, article_id);
     let r = json!({
         "code":200,
         "baseUrl":base_url
@@ -1310,10 +1808,10 @@ pub async fn shadow_article(
         .to_result()?
         .claims
         .user_id;
-    let base_url = get_base_url()?;
-    let db = get_db()?;
+    let base_url = get_base_url(depot)?;
+    let db = get_db(depot)?;
     let model = ArticleTb::find_by_id(article_id)
-        .filter(article_tb::Column::UserId.eq(identifier.as_str()))
+        .filter(article_tb::Column::UserId.eq(identifier.parse::<i32>()?))
         .one(db)
         .await?
         .to_result()?;
@@ -1323,6 +1821,9 @@ pub async fn shadow_article(
     model.article_state = ActiveValue::set(Some(if state == 1 { 0 } else { 1 }));
     model.update_time = ActiveValue::set(Some(get_current_time()));
     model.update(db).await?;
+    get_state(depot)?
+        .markdown()
+        .forget(Source::Article, article_id);
     let r = json!({
         "code":200,
         "baseUrl":base_url
@@ -1342,16 +1843,24 @@ pub async fn render_profile_view(
         .to_result()?
         .claims
         .user_id;
-    let base_url = get_base_url()?;
-    let db = get_db()?;
+    let base_url = get_base_url(depot)?;
+    let db = get_db(depot)?;
     let model = UserTb::find_by_id(identifier.parse::<i32>()?)
-        .into_json()
         .one(db)
         .await?
         .to_result()?;
-    let context = construct_context!["info"=>model,"baseUrl"=>base_url];
-    let tera = get_tera()?;
-    let r = tera.render("person.html", &context)?;
+    let viewer = Viewer::of(&model);
+    let permissions: Vec<_> = viewer
+        .role
+        .permissions()
+        .filter(|p| viewer.can(*p))
+        .map(Permission::as_str)
+        .collect();
+    let info = json!({ "id": model.id, "avatar": model.avatar });
+    let profile = Profile::of(&model).to_json();
+    let reauth = reauth_providers(depot, &model).await?;
+    let mut context = construct_context!["info"=>info,"profile"=>profile,"role"=>viewer.role.title(),"permissions"=>permissions,"notice"=>viewer.standing.notice(),"hasPassword"=>model.password.is_some(),"reauthProviders"=>reauth,"baseUrl"=>base_url];
+    let r = render_page(depot, "person.html", &mut context)?;
     res.render(Text::Html(r));
     Ok(())
 }
@@ -1368,7 +1877,7 @@ pub async fn edit_profile(
         .claims
         .user_id;
     let avatar = req.form::<String>("path").await.to_result()?;
-    let base_url = get_base_url()?;
+    let base_url = get_base_url(depot)?;
     if avatar.is_empty() {
         let r = json!({
             "code":404,
@@ -1377,7 +1886,7 @@ pub async fn edit_profile(
         });
         res.render(Text::Json(r.to_string()));
     } else {
-        let db = get_db()?;
+        let db = get_db(depot)?;
         let model = UserTb::find_by_id(identifier.parse::<i32>()?)
             .one(db)
             .await?
@@ -1395,13 +1904,621 @@ pub async fn edit_profile(
     Ok(())
 }
 
+#[handler]
+pub async fn edit_profile_info(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let mut form = ProfileForm::default();
+    for (field, value) in [
+        ("displayName", &mut form.display_name),
+        ("bio", &mut form.bio),
+        ("website", &mut form.website),
+        ("location", &mut form.location),
+        ("socialLinks", &mut form.social_links),
+    ] {
+        *value = req.form::<String>(field).await.unwrap_or_default();
+    }
+    let profile = match Profile::parse(&form) {
+        Ok(profile) => profile,
+        Err(msg) => {
+            let r = json!({
+                "code":400,
+                "msg":msg
+            });
+            res.render(Text::Json(r.to_string()));
+            return Ok(());
+        }
+    };
+    let user = current_user(depot).await?;
+    let db = get_db(depot)?;
+    // Nobody may pass for someone else, by their user name or display name
+    if let Some(display_name) = &profile.display_name
+        && name_taken(db, display_name, Some(user.id)).await?
+    {
+        let r = json!({
+            "code":400,
+            "msg":"显示名称已被使用"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    let mut model = user_tb::ActiveModel::from(user);
+    profile.apply(&mut model);
+    model.update_time = ActiveValue::set(Some(get_current_time()));
+    model.update(db).await?;
+    let r = json!({
+        "code":200,
+        "msg":"资料已保存"
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+/// Tell the address the account had before a change what happened. Failing
+/// to send is only logged: the change itself has already been made.
+async fn notify_account_change<const E: u8>(
+    req: &Request,
+    depot: &Depot,
+    user: &user_tb::Model,
+    change: &str,
+) -> Result<(), UniformError<E>> {
+    let Some(to) = user.email.clone().filter(|email| !email.is_empty()) else {
+        return Ok(());
+    };
+    let state = get_state(depot)?;
+    let name = user.name.clone().unwrap_or_default();
+    let time = Local::now().format("%Y-%m-%d %H:%M").to_string();
+    let ip = client_ip(req, state.behind_proxy());
+    let context = construct_context!["name"=>name,"time"=>time,"ip"=>ip,"change"=>change];
+    let html = get_tera(depot)?.render("email/account_changed.html", &context)?;
+    let message = Email {
+        to,
+        subject: String::from("账号安全提醒"),
+        html,
+    };
+    if let Err(e) = get_mailer(depot)?.send(&message).await {
+        tracing::error!("failed to send account change notice: {e:#}");
+    }
+    Ok(())
+}
+
+#[handler]
+pub async fn change_password(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    // Accounts made through a provider have none, see `confirm_identity`
+    let old_pass = req.form::<String>("oldPassword").await.unwrap_or_default();
+    let pass = req.form::<String>("password").await.to_result()?;
+    let confirm_pass = req.form::<String>("password2").await.to_result()?;
+    let user = current_user(depot).await?;
+
+    let state = get_state(depot)?;
+    let checks = [(
+        format!("password:account:{}", user.id),
+        state.rate_limits().login_per_account,
+    )];
+    if !allow_request(depot, res, &checks)? {
+        return Ok(());
+    }
+    if !confirm_identity(state.codes(), &user, old_pass).await? {
+        let r = json!({
+            "code":400,
+            "msg":identity_error(&user, "当前密码错误")
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    if pass.chars().count() < 6 {
+        let r = json!({
+            "code":400,
+            "msg":"密码长度少于6位"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    if pass != confirm_pass {
+        let r = json!({
+            "code":400,
+            "msg":"密码不一致"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+
+    let db = get_db(depot)?;
+    let mut model = user_tb::ActiveModel::from(user.clone());
+    model.password = ActiveValue::set(Some(password::hash(pass).await?));
+    model.update_time = ActiveValue::set(Some(get_current_time()));
+    model.update(db).await?;
+    let jti = &depot.jwt_auth_data::<JwtClaims>().to_result()?.claims.jti;
+    session::revoke_others(db, user.id, jti).await?;
+    notify_account_change(req, depot, &user, "修改了密码").await?;
+    let r = json!({
+        "code":200,
+        "msg":"密码已修改, 其他设备已退出登录"
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+/// Send a code to the address the signed in user wants to switch to.
+#[handler]
+pub async fn send_email_change_code(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let email = req.form::<String>("email").await.to_result()?;
+    let email = email.trim().to_owned();
+    if !checkmail::validate_email(&email) {
+        let r = json!({
+            "code":400,
+            "msg":"无效的邮箱"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    let user = current_user(depot).await?;
+    if let Some(msg) = email_unavailable(depot, &user, &email).await? {
+        let r = json!({
+            "code":400,
+            "msg":msg
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    let state = get_state(depot)?;
+    let limits = state.rate_limits();
+    let checks = [
+        (
+            format!("sendcode:account:{}", user.id),
+            limits.sendcode_per_email,
+        ),
+        (
+            format!("sendcode:email:{}", email.to_lowercase()),
+            limits.sendcode_per_email,
+        ),
+    ];
+    if !allow_request(depot, res, &checks)? {
+        return Ok(());
+    }
+    let purpose = Purpose::ChangeEmail;
+    let code = verification::issue(
+        state.codes(),
+        purpose,
+        &email,
+        state.code_length(),
+        state.code_ttl(),
+    )
+    .await?;
+    let minutes = state.code_ttl().as_secs().div_ceil(60);
+    let context = construct_context!["code"=>code,"minutes"=>minutes,"purpose"=>purpose.as_str()];
+    let html = get_tera(depot)?.render("email/verification_code.html", &context)?;
+    let message = Email {
+        to: email,
+        subject: String::from(purpose.email_subject()),
+        html,
+    };
+    get_mailer(depot)?.send(&message).await.map_err(|e| {
+        tracing::error!("failed to send verification email: {e:#}");
+        anyhow::anyhow!("验证码邮件发送失败, 请稍后再试")
+    })?;
+    let r = json!({
+        "code":200,
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+/// Why `user` can't switch to `email`, or `None` when they can.
+async fn email_unavailable<const E: u8>(
+    depot: &Depot,
+    user: &user_tb::Model,
+    email: &str,
+) -> Result<Option<&'static str>, UniformError<E>> {
+    if user
+        .email
+        .as_deref()
+        .is_some_and(|current| current.eq_ignore_ascii_case(email))
+    {
+        return Ok(Some("新邮箱与当前邮箱相同"));
+    }
+    let taken = email_taken(get_db(depot)?, email, Some(user.id)).await?;
+    Ok(taken.then_some("该邮箱已被其他账号使用"))
+}
+
+/// Whether a user other than `except` goes by `name`, as their user name or
+/// display name, whatever its case.
+async fn name_taken(
+    db: &impl sea_orm::ConnectionTrait,
+    name: &str,
+    except: Option<i32>,
+) -> anyhow::Result<bool> {
+    let name = name.to_lowercase();
+    let mut find = UserTb::find().filter(
+        Condition::any()
+            .add(Expr::expr(Func::lower(Expr::col(user_tb::Column::Name))).eq(name.as_str()))
+            .add(
+                Expr::expr(Func::lower(Expr::col(user_tb::Column::DisplayName))).eq(name.as_str()),
+            ),
+    );
+    if let Some(id) = except {
+        find = find.filter(user_tb::Column::Id.ne(id));
+    }
+    Ok(find.count(db).await? != 0)
+}
+
+/// Whether a user other than `except` has `email`, whatever its case.
+async fn email_taken(
+    db: &impl sea_orm::ConnectionTrait,
+    email: &str,
+    except: Option<i32>,
+) -> anyhow::Result<bool> {
+    let mut find = UserTb::find().filter(
+        Expr::expr(Func::lower(Expr::col(user_tb::Column::Email))).eq(email.to_lowercase()),
+    );
+    if let Some(id) = except {
+        find = find.filter(user_tb::Column::Id.ne(id));
+    }
+    Ok(find.count(db).await? != 0)
+}
+
+#[handler]
+pub async fn change_email(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let email = req.form::<String>("email").await.to_result()?;
+    let email = email.trim().to_owned();
+    let email_code = req.form::<String>("code").await.to_result()?;
+    let user = current_user(depot).await?;
+    if let Some(msg) = email_unavailable(depot, &user, &email).await? {
+        let r = json!({
+            "code":400,
+            "msg":msg
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    if let Some(msg) = check_email_code(depot, Purpose::ChangeEmail, &email, &email_code).await? {
+        let r = json!({
+            "code":400,
+            "msg":msg
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+
+    let db = get_db(depot)?;
+    let mut model = user_tb::ActiveModel::from(user.clone());
+    model.email = ActiveValue::set(Some(email.clone()));
+    model.update_time = ActiveValue::set(Some(get_current_time()));
+    model.update(db).await?;
+    let jti = &depot.jwt_auth_data::<JwtClaims>().to_result()?.claims.jti;
+    session::revoke_others(db, user.id, jti).await?;
+    notify_account_change(req, depot, &user, &format!("将邮箱修改为 {email}")).await?;
+    let r = json!({
+        "code":200,
+        "msg":"邮箱已修改, 其他设备已退出登录"
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+#[handler]
+pub async fn render_sessions_view(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let claims = &depot.jwt_auth_data::<JwtClaims>().to_result()?.claims;
+    let base_url = get_base_url(depot)?;
+    let db = get_db(depot)?;
+    let format_time = |time: Option<chrono::NaiveDateTime>| {
+        time.map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default()
+    };
+    let sessions: Vec<JsonValue> = session::list_active(db, claims.user_id.parse()?)
+        .await?
+        .into_iter()
+        .map(|s| {
+            json!({
+                "id":s.id,
+                "current":s.jti == claims.jti,
+                "ip":s.ip.unwrap_or_default(),
+                "user_agent":s.user_agent.unwrap_or_default(),
+                "create_time":format_time(Some(s.create_time)),
+                "last_seen_time":format_time(s.last_seen_time),
+                "expire_time":format_time(Some(s.expire_time)),
+            })
+        })
+        .collect();
+    let mut context = construct_context!["sessions"=>sessions,"baseUrl"=>base_url];
+    let r = render_page(depot, "sessions.html", &mut context)?;
+    res.render(Text::Html(r));
+    Ok(())
+}
+
+#[handler]
+pub async fn revoke_session(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let id = req.param::<i32>("id").to_result()?;
+    let claims = &depot.jwt_auth_data::<JwtClaims>().to_result()?.claims;
+    let db = get_db(depot)?;
+    let base_url = get_base_url(depot)?;
+    let user_id = claims.user_id.parse()?;
+    // Ending the session of this very token also clears its cookie
+    let current = session::revoke_by_id(db, user_id, id, Some(&claims.jti)).await?;
+    if !current && !session::revoke_by_id(db, user_id, id, None).await? {
+        let r = json!({
+            "code":404,
+            "msg":"登录会话不存在或已失效",
+            "baseUrl":base_url
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    if current {
+        clear_token_cookie(res, base_url);
+    }
+    let r = json!({
+        "code":200,
+        "msg":"已移除该登录会话",
+        "current":current,
+        "baseUrl":base_url
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+/// The signed in user, for the profile handlers.
+async fn current_user<const E: u8>(depot: &Depot) -> Result<user_tb::Model, UniformError<E>> {
+    let user_id = depot
+        .jwt_auth_data::<JwtClaims>()
+        .to_result()?
+        .claims
+        .user_id
+        .parse::<i32>()?;
+    UserTb::find_by_id(user_id)
+        .one(get_db(depot)?)
+        .await?
+        .to_result()
+}
+
+/// Whether `user` has just proven who they are, for actions that ask again.
+/// That is `pass` for users with a password, and a fresh trip through a
+/// linked provider for those without one.
+async fn confirm_identity(
+    codes: &dyn CodeStore,
+    user: &user_tb::Model,
+    pass: String,
+) -> anyhow::Result<bool> {
+    match user.password.clone() {
+        Some(stored) => Ok(password::verify(pass, stored).await?.is_valid()),
+        None => oauth::take_reauth(codes, user.id).await,
+    }
+}
+
+/// The providers a user without a password can confirm who they are through.
+async fn reauth_providers(
+    depot: &Depot,
+    user: &user_tb::Model,
+) -> Result<Vec<JsonValue>, UniformError> {
+    if user.password.is_some() {
+        return Ok(Vec::new());
+    }
+    let state = get_state(depot)?;
+    let providers = oauth::linked_providers(state.oauth(), state.db(), user.id).await?;
+    Ok(providers
+        .into_iter()
+        .map(|(id, name)| json!({"id":id,"name":name}))
+        .collect())
+}
+
+/// What to tell `user` when [`confirm_identity`] fails.
+fn identity_error<'a>(user: &user_tb::Model, wrong_password: &'a str) -> &'a str {
+    if user.password.is_some() {
+        wrong_password
+    } else {
+        "请先通过已绑定的第三方账号重新验证身份"
+    }
+}
+
+#[handler]
+pub async fn render_totp_view(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let user = current_user(depot).await?;
+    let base_url = get_base_url(depot)?;
+    let remaining = two_factor::remaining_recovery_codes(get_db(depot)?, user.id).await?;
+    let reauth = reauth_providers(depot, &user).await?;
+    let mut context = construct_context![
+        "enabled"=>user.totp_enabled,
+        "recoveryCodesLeft"=>remaining,
+        "hasPassword"=>user.password.is_some(),
+        "reauthProviders"=>reauth,
+        "baseUrl"=>base_url
+    ];
+    let r = render_page(depot, "totp.html", &mut context)?;
+    res.render(Text::Html(r));
+    Ok(())
+}
+
+#[handler]
+pub async fn totp_setup(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let user = current_user(depot).await?;
+    let state = get_state(depot)?;
+    if user.totp_enabled {
+        let r = json!({
+            "code":400,
+            "msg":"已开启两步验证"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    let secret = two_factor::begin_enrollment(state.db(), user.id).await?;
+    let account = user.name.unwrap_or_default();
+    let provisioning = two_factor::provisioning(&secret, state.totp_issuer(), &account)?;
+    let r = json!({
+        "code":200,
+        "secret":provisioning.secret,
+        "url":provisioning.url,
+        "qr":provisioning.qr_svg
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+#[handler]
+pub async fn totp_confirm(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let code = req.form::<String>("code").await.to_result()?;
+    let user = current_user(depot).await?;
+    if user.totp_enabled {
+        let r = json!({
+            "code":400,
+            "msg":"已开启两步验证"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    let db = get_db(depot)?;
+    let Some(recovery_codes) = two_factor::confirm_enrollment(db, &user, &code).await? else {
+        let r = json!({
+            "code":400,
+            "msg":"两步验证码错误"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    };
+    let r = json!({
+        "code":200,
+        "msg":"已开启两步验证",
+        "recovery_codes":recovery_codes
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+#[handler]
+pub async fn totp_disable(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let pass = req.form::<String>("password").await.unwrap_or_default();
+    let code = req.form::<String>("code").await.unwrap_or_default();
+    let user = current_user(depot).await?;
+    let state = get_state(depot)?;
+    if !user.totp_enabled {
+        let r = json!({
+            "code":400,
+            "msg":"未开启两步验证"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    // Guesses at either factor count against the same quota as at login,
+    // so a stolen session can't be used to try passwords
+    let account_key = format!("login:account:{}", user.name.clone().unwrap_or_default());
+    let checks = [(account_key.clone(), state.rate_limits().login_per_account)];
+    if !allow_request(depot, res, &checks)? {
+        return Ok(());
+    }
+    if !confirm_identity(state.codes(), &user, pass).await? {
+        let r = json!({
+            "code":400,
+            "msg":identity_error(&user, "密码错误")
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    // A password and a session are not enough, the second factor has to agree
+    if !two_factor::verify(state.db(), &user, &code).await? {
+        let r = json!({
+            "code":400,
+            "msg":"两步验证码错误"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    state.throttle().reset(&account_key);
+    two_factor::disable(state.db(), user.id).await?;
+    let r = json!({
+        "code":200,
+        "msg":"已关闭两步验证"
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+#[handler]
+pub async fn totp_recovery_codes(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let pass = req.form::<String>("password").await.unwrap_or_default();
+    let user = current_user(depot).await?;
+    let state = get_state(depot)?;
+    if !user.totp_enabled {
+        let r = json!({
+            "code":400,
+            "msg":"未开启两步验证"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    // New recovery codes get past the second factor, so guessing the
+    // password here is throttled like at login
+    let account_key = format!("login:account:{}", user.name.clone().unwrap_or_default());
+    let checks = [(account_key.clone(), state.rate_limits().login_per_account)];
+    if !allow_request(depot, res, &checks)? {
+        return Ok(());
+    }
+    if !confirm_identity(state.codes(), &user, pass).await? {
+        let r = json!({
+            "code":400,
+            "msg":identity_error(&user, "密码错误")
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    }
+    state.throttle().reset(&account_key);
+    let recovery_codes = two_factor::replace_recovery_codes(state.db(), user.id).await?;
+    let r = json!({
+        "code":200,
+        "msg":"已生成新的恢复码",
+        "recovery_codes":recovery_codes
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
 #[handler]
 pub async fn search(
     req: &mut Request,
     res: &mut Response,
     depot: &mut Depot,
 ) -> Result<(), UniformError> {
-    let base_url = get_base_url()?;
+    let base_url = get_base_url(depot)?;
     let query_key = req.query("query").unwrap_or("");
     //println!("raw query_key = {}",query_key);
     let page = match req.param::<u64>("page") {
@@ -1416,32 +2533,30 @@ pub async fn search(
     };
     // let query_key = query_key.url_decode();
     //println!("query_key = {}",query_key);
-    let db = get_db()?;
+    let db = get_db(depot)?;
     let query_condition = format!("%{query_key}%");
     let possible_tags = TagTb::find()
         .filter(tag_tb::Column::Name.like(&query_condition))
         .all(db)
         .await?;
-    let mut filter_condition = Condition::any().add(
-        article_tb::Column::Title
-            .like(&query_condition)
-            .add(article_tb::Column::Content.like(&query_condition)),
-    );
+    let mut filter_condition = Condition::any()
+        .add(article_tb::Column::Title.like(&query_condition))
+        .add(article_tb::Column::Content.like(&query_condition));
     for tag in possible_tags {
         filter_condition = filter_condition.add(article_tb::Column::TagId.eq(tag.id));
     }
     let pagination = ArticleTb::find()
         .order_by_desc(article_tb::Column::UpdateTime)
         .filter(article_tb::Column::ArticleState.eq(1))
-        .filter(article_tb::Column::Level.ne(999))
+        .filter(article_tb::Column::Hidden.eq(false))
+        .filter(article_tb::Column::Level.ne(access::PRIVATE_LEVEL))
         .filter(filter_condition)
         .into_json()
         .paginate(db, 10);
 
-    let tera = get_tera()?;
     let total_count = pagination.num_items().await?;
     // Optimized: Calculate total_pages locally to avoid redundant COUNT query
-    let total_pages = (total_count + 9) / 10;
+    let total_pages = total_count.div_ceil(10);
     if page != 0 && (page + 1) > total_pages {
         return Err(UniformError(anyhow::anyhow!("请求的资源不存在")));
     }
@@ -1467,15 +2582,20 @@ pub async fn search(
                         "avatar":""
                     });
                 };
+                let notice = Standing::of(&info.0).notice();
                 let avatar = info.0.avatar.unwrap_or_default();
                 let username = info.0.name.unwrap_or_default();
-                let level = info.0.privilege.unwrap_or_default();
+                let role = Role::from_privilege(info.0.privilege);
+                let level = access::clearance(role, info.0.clearance);
+                let role = role.title();
                 let post_count = info.1;
                 json!({
                     "login":true,
                     "avatar":avatar,
                     "name":username,
                     "level":level,
+                    "role":role,
+                    "notice":notice,
                     "post_count":post_count
                 })
             }
@@ -1497,23 +2617,17 @@ pub async fn search(
     context.insert("commentCount", &10);
     context.insert("page", &(page + 1));
     context.insert("hotArticles", &hot_list);
-    let r = tera.render("search.html", &context)?;
+    let r = render_page(depot, "search.html", &mut context)?;
     res.render(Text::Html(r));
     Ok(())
 }
 
-fn gen_code() -> String {
-    [
-        rand::random_range(0..9).to_string(),
-        rand::random_range(0..9).to_string(),
-        rand::random_range(0..9).to_string(),
-        rand::random_range(0..9).to_string(),
-    ]
-    .join("")
-}
-
 #[handler]
-pub async fn sendcode(req: &mut Request, res: &mut Response) -> Result<(), UniformError> {
+pub async fn sendcode(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
     let email = req
         .form::<String>("email")
         .await
@@ -1526,20 +2640,50 @@ pub async fn sendcode(req: &mut Request, res: &mut Response) -> Result<(), Unifo
         res.render(Text::Json(r.to_string()));
         return Ok(());
     }
-    let resend_key = get_resend_key()?;
-    let redis_url = get_redis_url()?;
-    let client = redis::Client::open(redis_url)?;
-    let mut con = client.get_connection()?;
-    let code = gen_code();
-    con.set_ex(&email, &code, 5 * 60)?;
-
-    let resend = Resend::new(resend_key);
-    let from = "Blog <blog@resend.dev>";
-    let to = [email];
-    let subject = "你好，邮箱验证码";
-    let html = format!("<p>验证码5分钟内有效, 你的验证码：{}</p>", code);
-    let email = CreateEmailBaseOptions::new(from, to, subject).with_html(&html);
-    let _email = resend.emails.send(email).await?;
+    let purpose = req.form::<String>("purpose").await;
+    let Some(purpose) = Purpose::from_form(purpose.as_deref()) else {
+        let r = json!({
+            "code":400,
+            "msg":"无效的验证码用途"
+        });
+        res.render(Text::Json(r.to_string()));
+        return Ok(());
+    };
+    let state = get_state(depot)?;
+    let limits = state.rate_limits();
+    let ip = client_ip(req, state.behind_proxy());
+    let checks = [
+        (format!("sendcode:ip:{ip}"), limits.sendcode_per_ip),
+        (
+            format!("sendcode:email:{}", email.to_lowercase()),
+            limits.sendcode_per_email,
+        ),
+    ];
+    if !allow_request(depot, res, &checks)? {
+        return Ok(());
+    }
+    let code = verification::issue(
+        state.codes(),
+        purpose,
+        &email,
+        state.code_length(),
+        state.code_ttl(),
+    )
+    .await?;
+
+    let tera = get_tera(depot)?;
+    let minutes = state.code_ttl().as_secs().div_ceil(60);
+    let context = construct_context!["code"=>code,"minutes"=>minutes,"purpose"=>purpose.as_str()];
+    let html = tera.render("email/verification_code.html", &context)?;
+    let message = Email {
+        to: email,
+        subject: String::from(purpose.email_subject()),
+        html,
+    };
+    get_mailer(depot)?.send(&message).await.map_err(|e| {
+        tracing::error!("failed to send verification email: {e:#}");
+        anyhow::anyhow!("验证码邮件发送失败, 请稍后再试")
+    })?;
 
     let r = json!({
         "code":200,
@@ -0,0 +1,377 @@
+//! Roles and the permissions they grant.
+//!
+//! A user's role is stored in `user_tb.privilege`: 1 reader, 2 author,
+//! 3 editor and 4 admin. Registration hands out authors, as it always has.
+//! Values outside that range, including a missing one, are treated as a
+//! reader so an odd row never gains anything.
+//!
+//! Reading is decided by the user's clearance: an article whose `level` is at
+//! most the clearance can be read, [`PUBLIC_LEVEL`] articles by anyone and
+//! [`PRIVATE_LEVEL`] ones only by their author, whatever their role. Authors
+//! can always read their own articles. The clearance is the role's number, or
+//! `user_tb.clearance` when that is higher. The latter carries the levels
+//! users could read before roles existed, when `privilege` meant only that.
+//!
+//! Permissions also depend on the account's [`Standing`]. Suspended users keep
+//! their reading clearance but hold no permissions until the suspension runs
+//! out; banned users can't sign in at all.
+
+use chrono::{Local, NaiveDateTime};
+use salvo::prelude::*;
+use sea_orm::sea_query::Expr;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use serde_json::json;
+use tera::Context;
+
+use super::database::prelude::*;
+use super::database::user_tb;
+use super::{AppState, JwtClaims, session};
+
+/// Articles at this level are readable without signing in.
+pub const PUBLIC_LEVEL: i64 = 1;
+/// Articles at this level are only readable by their author.
+pub const PRIVATE_LEVEL: i64 = 999;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    Reader,
+    Author,
+    Editor,
+    Admin,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Comment on articles and edit one's own comments
+    CommentPost,
+    /// Write, edit and withdraw one's own articles
+    ArticlePublish,
+    /// Hide or restore anybody's articles
+    ArticleModerate,
+    /// Delete anybody's comments
+    CommentModerate,
+    /// Change other users' roles and accounts
+    UserManage,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Reader, Role::Author, Role::Editor, Role::Admin];
+
+    pub fn from_privilege(privilege: Option<i16>) -> Role {
+        match privilege {
+            Some(2) => Role::Author,
+            Some(3) => Role::Editor,
+            Some(4) => Role::Admin,
+            _ => Role::Reader,
+        }
+    }
+
+    pub fn privilege(self) -> i16 {
+        match self {
+            Role::Reader => 1,
+            Role::Author => 2,
+            Role::Editor => 3,
+            Role::Admin => 4,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Role> {
+        Role::ALL.into_iter().find(|role| role.as_str() == name)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::Reader => "reader",
+            Role::Author => "author",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// The name shown on pages.
+    pub fn title(self) -> &'static str {
+        match self {
+            Role::Reader => "读者",
+            Role::Author => "作者",
+            Role::Editor => "编辑",
+            Role::Admin => "管理员",
+        }
+    }
+
+    /// Every permission the role holds.
+    pub fn permissions(self) -> impl Iterator<Item = Permission> {
+        Permission::ALL.into_iter().filter(move |p| self.can(*p))
+    }
+
+    pub fn can(self, permission: Permission) -> bool {
+        match permission {
+            Permission::CommentPost => true,
+            Permission::ArticlePublish => self >= Role::Author,
+            Permission::ArticleModerate | Permission::CommentModerate => self >= Role::Editor,
+            Permission::UserManage => self >= Role::Admin,
+        }
+    }
+}
+
+impl Permission {
+    pub const ALL: [Permission; 5] = [
+        Permission::CommentPost,
+        Permission::ArticlePublish,
+        Permission::ArticleModerate,
+        Permission::CommentModerate,
+        Permission::UserManage,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Permission::CommentPost => "comment.post",
+            Permission::ArticlePublish => "article.publish",
+            Permission::ArticleModerate => "article.moderate",
+            Permission::CommentModerate => "comment.moderate",
+            Permission::UserManage => "user.manage",
+        }
+    }
+}
+
+/// Whether an account is in good standing, from `user_tb.status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Standing {
+    Active,
+    Suspended {
+        until: NaiveDateTime,
+        reason: String,
+    },
+    Banned {
+        reason: String,
+    },
+}
+
+impl Standing {
+    /// The standing of `user` right now. A suspension that has run out, and
+    /// any status this version doesn't know, count as active.
+    pub fn of(user: &user_tb::Model) -> Standing {
+        let reason = user.status_reason.clone().unwrap_or_default();
+        match (user.status.as_str(), user.suspended_until) {
+            ("banned", _) => Standing::Banned { reason },
+            ("suspended", Some(until)) if until > Local::now().naive_local() => {
+                Standing::Suspended { until, reason }
+            }
+            _ => Standing::Active,
+        }
+    }
+
+    /// The value stored in `user_tb.status`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Standing::Active => "active",
+            Standing::Suspended { .. } => "suspended",
+            Standing::Banned { .. } => "banned",
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        *self == Standing::Active
+    }
+
+    /// What the user is told about their account, `None` while active.
+    pub fn notice(&self) -> Option<String> {
+        let (mut notice, reason) = match self {
+            Standing::Active => return None,
+            Standing::Suspended { until, reason } => (
+                format!(
+                    "账号已被暂停至 {}, 期间只能浏览",
+                    until.format("%Y-%m-%d %H:%M")
+                ),
+                reason,
+            ),
+            Standing::Banned { reason } => ("该账号已被封禁".to_owned(), reason),
+        };
+        if !reason.is_empty() {
+            notice.push_str(&format!(", 原因: {reason}"));
+        }
+        Some(notice)
+    }
+}
+
+/// The signed in user behind a request.
+#[derive(Debug, Clone)]
+pub struct Viewer {
+    pub id: i32,
+    pub role: Role,
+    pub standing: Standing,
+    /// The highest article level the user can read
+    pub clearance: i64,
+}
+
+impl Viewer {
+    pub fn of(user: &user_tb::Model) -> Viewer {
+        let role = Role::from_privilege(user.privilege);
+        Viewer {
+            id: user.id,
+            role,
+            standing: Standing::of(user),
+            clearance: clearance(role, user.clearance),
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        self.standing.is_active() && self.role.can(permission)
+    }
+}
+
+/// The reading clearance of a user with `role` and the stored `clearance`.
+pub fn clearance(role: Role, stored: Option<i32>) -> i64 {
+    i64::from(role.privilege()).max(stored.map_or(0, i64::from))
+}
+
+/// Whether someone may read an article at `level` written by `author_id`.
+///
+/// `reader` is the signed in user and their clearance, `None` when anonymous.
+pub fn can_read(level: i64, author_id: i64, reader: Option<(i64, i64)>) -> bool {
+    match reader {
+        Some((id, _)) if id == author_id => true,
+        _ if level == PRIVATE_LEVEL => false,
+        None => level <= PUBLIC_LEVEL,
+        Some((_, clearance)) => level <= clearance,
+    }
+}
+
+/// The signed in user, `None` for anonymous requests.
+pub async fn current(depot: &Depot, db: &DatabaseConnection) -> anyhow::Result<Option<Viewer>> {
+    if depot.jwt_auth_state() != JwtAuthState::Authorized {
+        return Ok(None);
+    }
+    let Some(data) = depot.jwt_auth_data::<JwtClaims>() else {
+        return Ok(None);
+    };
+    let user_id = data.claims.user_id.parse::<i32>()?;
+    let Some(user) = UserTb::find_by_id(user_id).one(db).await? else {
+        return Ok(None);
+    };
+    Ok(Some(Viewer::of(&user)))
+}
+
+/// Whether the signed in user holds `permission`.
+pub async fn allowed(
+    depot: &Depot,
+    db: &DatabaseConnection,
+    permission: Permission,
+) -> anyhow::Result<bool> {
+    Ok(current(depot, db)
+        .await?
+        .is_some_and(|viewer| viewer.can(permission)))
+}
+
+/// Give the user named `name` a new role, e.g. to appoint the first admin.
+pub async fn set_role_by_name(
+    db: &DatabaseConnection,
+    name: &str,
+    role: Role,
+) -> anyhow::Result<()> {
+    let result = UserTb::update_many()
+        .col_expr(user_tb::Column::Privilege, Expr::value(role.privilege()))
+        .filter(user_tb::Column::Name.eq(name))
+        .exec(db)
+        .await?;
+    anyhow::ensure!(result.rows_affected > 0, "no user is named `{name}`");
+    tracing::info!("{name} is now {}", role.as_str());
+    Ok(())
+}
+
+/// Change the standing of `user_id`. Banning also signs them out everywhere.
+pub async fn set_standing(
+    db: &DatabaseConnection,
+    user_id: i32,
+    standing: &Standing,
+) -> anyhow::Result<()> {
+    let (until, reason) = match standing {
+        Standing::Active => (None, None),
+        Standing::Suspended { until, reason } => (Some(*until), Some(reason)),
+        Standing::Banned { reason } => (None, Some(reason)),
+    };
+    let reason = reason.filter(|r| !r.is_empty()).cloned();
+    UserTb::update_many()
+        .col_expr(user_tb::Column::Status, Expr::value(standing.as_str()))
+        .col_expr(user_tb::Column::SuspendedUntil, Expr::value(until))
+        .col_expr(user_tb::Column::StatusReason, Expr::value(reason))
+        .filter(user_tb::Column::Id.eq(user_id))
+        .exec(db)
+        .await?;
+    if let Standing::Banned { .. } = standing {
+        session::revoke_all(db, user_id).await?;
+    }
+    Ok(())
+}
+
+/// Turn the request away: an error page for `GET`, the JSON error shape otherwise.
+pub fn deny(req: &Request, depot: &Depot, res: &mut Response) {
+    deny_with(req, depot, res, "没有权限执行此操作");
+}
+
+/// [`deny`] with a more specific message.
+pub fn deny_with(req: &Request, depot: &Depot, res: &mut Response, msg: &str) {
+    let Ok(state) = depot.obtain::<AppState>() else {
+        res.status_code(StatusCode::FORBIDDEN);
+        return;
+    };
+    let base_url = state.base_url();
+    if req.method() == salvo::http::Method::GET {
+        let mut context = Context::new();
+        context.insert("code", &404);
+        context.insert("msg", msg);
+        context.insert("baseUrl", &base_url);
+        let r = state
+            .tera()
+            .render("404.html", &context)
+            .unwrap_or(String::from("error"));
+        res.render(Text::Html(r));
+    } else {
+        let r = json!({
+            "code":400,
+            "msg":msg,
+            "baseUrl":base_url,
+            "success":0,
+            "message":msg,
+        });
+        res.render(Text::Json(r.to_string()));
+    }
+}
+
+/// Hoop that only lets signed in users holding the permission through.
+///
+/// Users who would hold it but for a suspension are told why they can't.
+pub struct Require(pub Permission);
+
+#[async_trait]
+impl Handler for Require {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let viewer = match depot.obtain::<AppState>() {
+            Ok(state) => match current(depot, state.db()).await {
+                Ok(viewer) => viewer,
+                Err(e) => {
+                    tracing::error!("failed to check `{}`: {e}", self.0.as_str());
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+        match viewer {
+            Some(viewer) if viewer.can(self.0) => {
+                ctrl.call_next(req, depot, res).await;
+                return;
+            }
+            Some(viewer) if viewer.role.can(self.0) => {
+                let notice = viewer.standing.notice().unwrap_or_default();
+                deny_with(req, depot, res, &notice);
+            }
+            _ => deny(req, depot, res),
+        }
+        ctrl.skip_rest();
+    }
+}
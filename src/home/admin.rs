@@ -0,0 +1,752 @@
+//! The `/admin` console: users, articles, comments, tags and reading levels.
+//!
+//! Articles sit behind `Require(Permission::ArticleModerate)` and comments
+//! behind `Require(Permission::CommentModerate)`, so editors can moderate
+//! them. Users, tags and levels stay behind `Require(Permission::UserManage)`,
+//! and the navigation only links the sections the viewer may open. Pages are
+//! paginated like `person_list` and take an optional `q` to search; the
+//! actions answer with the usual `{code,msg}`.
+
+use std::collections::HashMap;
+
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use rand::distr::Alphanumeric;
+use rand::rngs::OsRng;
+use rand::{Rng, TryRngCore};
+use salvo::prelude::*;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, Condition, EntityTrait, JsonValue, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+use serde_json::json;
+use tera::Context;
+
+use super::access::{self, Permission, Role, Standing};
+use super::database::prelude::*;
+use super::database::{article_tb, comment_tb, level_tb, tag_tb, user_tb};
+use super::{
+    ConverOptionToResult, JwtClaims, RESPONSE_JSON_FOR_ERROR, UniformError, get_base_url, get_db,
+    password, render_page, session,
+};
+
+const PAGE_SIZE: u64 = 10;
+
+/// Each section of the console and the permission its routes require.
+const SECTIONS: [(&str, Permission); 5] = [
+    ("users/1", Permission::UserManage),
+    ("articles/1", Permission::ArticleModerate),
+    ("comments/1", Permission::CommentModerate),
+    ("tags", Permission::UserManage),
+    ("levels", Permission::UserManage),
+];
+
+/// Length of the temporary password handed out by a reset.
+const TEMPORARY_PASSWORD_LEN: usize = 12;
+
+fn fail<T>(msg: &str) -> Result<T, UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    Err(UniformError(anyhow::anyhow!(msg.to_owned())))
+}
+
+fn ok(res: &mut Response, msg: &str) {
+    let r = json!({
+        "code":200,
+        "msg":msg,
+    });
+    res.render(Text::Json(r.to_string()));
+}
+
+fn format_time(time: Option<chrono::NaiveDateTime>) -> String {
+    time.map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+        .unwrap_or_default()
+}
+
+/// The zero based page from the `{page}` segment, or `None` after redirecting
+/// to the first page of `section` when it is missing or out of range.
+fn page_or_redirect(
+    req: &Request,
+    res: &mut Response,
+    base_url: &str,
+    section: &str,
+) -> Option<u64> {
+    match req.param::<u64>("page") {
+        Some(x) if x >= 1 => Some(x - 1),
+        _ => {
+            res.render(Redirect::other(format!("{base_url}admin/{section}/1")));
+            None
+        }
+    }
+}
+
+/// The trimmed `q` query parameter, empty when there is no search.
+fn search_term(req: &Request) -> String {
+    req.query::<String>("q")
+        .unwrap_or_default()
+        .trim()
+        .to_owned()
+}
+
+fn current_user_id(depot: &Depot) -> Result<i32, UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    Ok(depot
+        .jwt_auth_data::<JwtClaims>()
+        .to_result()?
+        .claims
+        .user_id
+        .parse()?)
+}
+
+/// [`render_page`] with what the console's navigation needs.
+async fn render(
+    depot: &Depot,
+    res: &mut Response,
+    template: &str,
+    mut context: Context,
+) -> Result<(), UniformError> {
+    let viewer = access::current(depot, get_db(depot)?).await?;
+    let permissions: Vec<_> = viewer
+        .iter()
+        .flat_map(|v| v.role.permissions())
+        .map(Permission::as_str)
+        .collect();
+    context.insert("permissions", &permissions);
+    context.insert("baseUrl", get_base_url(depot)?);
+    res.render(Text::Html(render_page(depot, template, &mut context)?));
+    Ok(())
+}
+
+/// Context shared by the paginated pages.
+fn page_context(page: u64, total: u64, query: &str) -> Result<Context, UniformError> {
+    if page != 0 && page + 1 > total.div_ceil(PAGE_SIZE) {
+        return Err(UniformError(anyhow::anyhow!("请求的资源不存在")));
+    }
+    let mut context = Context::new();
+    context.insert("page", &(page + 1));
+    context.insert("total", &total);
+    context.insert("query", query);
+    Ok(context)
+}
+
+/// Names of the users in `ids`, for labelling articles and comments.
+async fn user_names(
+    db: &sea_orm::DatabaseConnection,
+    ids: impl IntoIterator<Item = i32>,
+) -> anyhow::Result<HashMap<i32, String>> {
+    Ok(UserTb::find()
+        .filter(user_tb::Column::Id.is_in(ids))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|u| (u.id, u.name.unwrap_or_default()))
+        .collect())
+}
+
+/// The first section of the console the viewer may open.
+#[handler]
+pub async fn index(
+    req: &mut Request,
+    depot: &mut Depot,
+    res: &mut Response,
+) -> Result<(), UniformError> {
+    let base_url = get_base_url(depot)?;
+    let viewer = access::current(depot, get_db(depot)?).await?;
+    let section = SECTIONS
+        .iter()
+        .find(|(_, permission)| viewer.as_ref().is_some_and(|v| v.can(*permission)));
+    match section {
+        Some((path, _)) => res.render(Redirect::other(format!("{base_url}admin/{path}"))),
+        None => access::deny(req, depot, res),
+    }
+    Ok(())
+}
+
+#[handler]
+pub async fn render_users_view(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let base_url = get_base_url(depot)?;
+    let Some(page) = page_or_redirect(req, res, base_url, "users") else {
+        return Ok(());
+    };
+    let query = search_term(req);
+    let db = get_db(depot)?;
+    let mut find = UserTb::find().order_by_asc(user_tb::Column::Id);
+    if !query.is_empty() {
+        let like = format!("%{query}%");
+        find = find.filter(
+            Condition::any()
+                .add(user_tb::Column::Name.like(&like))
+                .add(user_tb::Column::Email.like(&like)),
+        );
+    }
+    let pagination = find.paginate(db, PAGE_SIZE);
+    let total = pagination.num_items().await?;
+    let mut context = page_context(page, total, &query)?;
+    let users: Vec<JsonValue> = pagination
+        .fetch_page(page)
+        .await?
+        .into_iter()
+        .map(|u| {
+            let role = Role::from_privilege(u.privilege);
+            let standing = Standing::of(&u);
+            json!({
+                "id":u.id,
+                "name":u.name.unwrap_or_default(),
+                "email":u.email.unwrap_or_default(),
+                "role":role.as_str(),
+                "roleTitle":role.title(),
+                "status":standing.as_str(),
+                "notice":standing.notice(),
+                "create_time":format_time(u.create_time),
+            })
+        })
+        .collect();
+    let roles: Vec<JsonValue> = Role::ALL
+        .iter()
+        .map(|r| json!({"name":r.as_str(),"title":r.title()}))
+        .collect();
+    context.insert("users", &users);
+    context.insert("roles", &roles);
+    context.insert("currentId", &current_user_id(depot).ok());
+    render(depot, res, "admin/users.html", context).await
+}
+
+/// The user named by `{id}`, refusing the admin's own account: demoting or
+/// disabling yourself by accident would lock everyone out.
+async fn other_user(
+    req: &Request,
+    depot: &Depot,
+) -> Result<user_tb::Model, UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let id = req.param::<i32>("id").to_result()?;
+    if id == current_user_id(depot)? {
+        return fail("不能修改自己的账号");
+    }
+    match UserTb::find_by_id(id).one(get_db(depot)?).await? {
+        Some(user) => Ok(user),
+        None => fail("用户不存在"),
+    }
+}
+
+#[handler]
+pub async fn set_user_role(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let user = other_user(req, depot).await?;
+    let role = req.form::<String>("role").await.unwrap_or_default();
+    let Some(role) = Role::from_name(&role) else {
+        return fail("未知的角色");
+    };
+    let mut user = user_tb::ActiveModel::from(user);
+    user.privilege = ActiveValue::set(Some(role.privilege()));
+    user.update_time = ActiveValue::set(Some(Local::now().naive_local()));
+    user.update(get_db(depot)?).await?;
+    ok(res, "角色已更新");
+    Ok(())
+}
+
+/// Parse the `until` of a suspension as sent by a `datetime-local` input,
+/// or a plain date meaning the start of that day.
+fn parse_until(until: &str) -> Option<NaiveDateTime> {
+    let until = until.trim();
+    ["%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(until, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(until, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+}
+
+/// Set the user's standing from the form's `status` (`active`, `suspended`
+/// or `banned`), `until` for a suspension and an optional `reason`.
+#[handler]
+pub async fn set_user_status(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let user = other_user(req, depot).await?;
+    let status = req.form::<String>("status").await.unwrap_or_default();
+    let reason = req
+        .form::<String>("reason")
+        .await
+        .unwrap_or_default()
+        .trim()
+        .to_owned();
+    let (standing, msg) = match status.as_str() {
+        "active" => (Standing::Active, "账号已恢复正常"),
+        "suspended" => {
+            let until = req.form::<String>("until").await.unwrap_or_default();
+            let Some(until) = parse_until(&until) else {
+                return fail("请填写正确的暂停截止时间");
+            };
+            if until <= Local::now().naive_local() {
+                return fail("暂停截止时间必须晚于当前时间");
+            }
+            (Standing::Suspended { until, reason }, "账号已暂停")
+        }
+        "banned" => (Standing::Banned { reason }, "账号已封禁"),
+        _ => return fail("未知的账号状态"),
+    };
+    access::set_standing(get_db(depot)?, user.id, &standing).await?;
+    ok(res, msg);
+    Ok(())
+}
+
+/// Replace the password with a random one, shown once to the admin so it can
+/// be passed on, and sign the user out everywhere.
+#[handler]
+pub async fn reset_user_password(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let user = other_user(req, depot).await?;
+    let db = get_db(depot)?;
+    let temporary: String = OsRng
+        .unwrap_err()
+        .sample_iter(Alphanumeric)
+        .take(TEMPORARY_PASSWORD_LEN)
+        .map(char::from)
+        .collect();
+    let user_id = user.id;
+    let mut user = user_tb::ActiveModel::from(user);
+    user.password = ActiveValue::set(Some(password::hash(temporary.clone()).await?));
+    user.update_time = ActiveValue::set(Some(Local::now().naive_local()));
+    user.update(db).await?;
+    session::revoke_all(db, user_id).await?;
+    let r = json!({
+        "code":200,
+        "msg":"密码已重置",
+        "password":temporary,
+    });
+    res.render(Text::Json(r.to_string()));
+    Ok(())
+}
+
+#[handler]
+pub async fn render_articles_view(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let base_url = get_base_url(depot)?;
+    let Some(page) = page_or_redirect(req, res, base_url, "articles") else {
+        return Ok(());
+    };
+    let query = search_term(req);
+    let db = get_db(depot)?;
+    let mut find = ArticleTb::find().order_by_desc(article_tb::Column::UpdateTime);
+    if !query.is_empty() {
+        find = find.filter(article_tb::Column::Title.like(format!("%{query}%")));
+    }
+    let pagination = find.paginate(db, PAGE_SIZE);
+    let total = pagination.num_items().await?;
+    let mut context = page_context(page, total, &query)?;
+    let rows = pagination.fetch_page(page).await?;
+    let names = user_names(db, rows.iter().filter_map(|a| a.user_id)).await?;
+    let tags: HashMap<i32, String> = TagTb::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|t| (t.id, t.name.unwrap_or_default()))
+        .collect();
+    let articles: Vec<JsonValue> = rows
+        .into_iter()
+        .map(|a| {
+            json!({
+                "id":a.id,
+                "title":a.title.unwrap_or_default(),
+                "author":a.user_id.and_then(|id| names.get(&id).cloned()).unwrap_or_default(),
+                "tag":a.tag_id.and_then(|id| tags.get(&id).cloned()).unwrap_or_default(),
+                "level":a.level,
+                "hidden":a.hidden,
+                "update_time":format_time(a.update_time),
+            })
+        })
+        .collect();
+    context.insert("articles", &articles);
+    render(depot, res, "admin/articles.html", context).await
+}
+
+/// Hide an article from everyone but its author and moderators, or bring it
+/// back. Authors withdrawing and restoring it themselves leave this alone.
+#[handler]
+pub async fn set_article_hidden(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let id = req.param::<i32>("id").to_result()?;
+    let hidden = req.form::<bool>("hidden").await.unwrap_or(true);
+    let db = get_db(depot)?;
+    let Some(article) = ArticleTb::find_by_id(id).one(db).await? else {
+        return fail("文章不存在");
+    };
+    let mut article = article_tb::ActiveModel::from(article);
+    article.hidden = ActiveValue::set(hidden);
+    article.update(db).await?;
+    ok(
+        res,
+        if hidden {
+            "文章已隐藏"
+        } else {
+            "文章已恢复"
+        },
+    );
+    Ok(())
+}
+
+#[handler]
+pub async fn render_comments_view(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let base_url = get_base_url(depot)?;
+    let Some(page) = page_or_redirect(req, res, base_url, "comments") else {
+        return Ok(());
+    };
+    let query = search_term(req);
+    let db = get_db(depot)?;
+    let mut find = CommentTb::find().order_by_desc(comment_tb::Column::CreateTime);
+    if !query.is_empty() {
+        find = find.filter(comment_tb::Column::MdContent.like(format!("%{query}%")));
+    }
+    let pagination = find.paginate(db, PAGE_SIZE);
+    let total = pagination.num_items().await?;
+    let mut context = page_context(page, total, &query)?;
+    let rows = pagination.fetch_page(page).await?;
+    let names = user_names(db, rows.iter().filter_map(|c| c.user_id)).await?;
+    let titles: HashMap<i32, String> = ArticleTb::find()
+        .filter(article_tb::Column::Id.is_in(rows.iter().filter_map(|c| c.article_id)))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|a| (a.id, a.title.unwrap_or_default()))
+        .collect();
+    let comments: Vec<JsonValue> = rows
+        .into_iter()
+        .map(|c| {
+            json!({
+                "id":c.id,
+                "article_id":c.article_id,
+                "article":c.article_id.and_then(|id| titles.get(&id).cloned()).unwrap_or_default(),
+                "author":c.user_id.and_then(|id| names.get(&id).cloned()).unwrap_or_default(),
+                "content":c.md_content.unwrap_or_default(),
+                "create_time":format_time(c.create_time),
+            })
+        })
+        .collect();
+    context.insert("comments", &comments);
+    render(depot, res, "admin/comments.html", context).await
+}
+
+#[handler]
+pub async fn delete_comment(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let id = req.param::<i32>("id").to_result()?;
+    let result = CommentTb::delete_by_id(id).exec(get_db(depot)?).await?;
+    if result.rows_affected == 0 {
+        return fail("评论不存在");
+    }
+    ok(res, "评论已删除");
+    Ok(())
+}
+
+/// How many articles use each value of `column`, a tag id or level number.
+async fn article_counts(
+    db: &sea_orm::DatabaseConnection,
+    column: article_tb::Column,
+) -> anyhow::Result<HashMap<i32, i64>> {
+    let rows: Vec<(Option<i32>, i64)> = ArticleTb::find()
+        .select_only()
+        .column(column)
+        .column_as(article_tb::Column::Id.count(), "count")
+        .group_by(column)
+        .into_tuple()
+        .all(db)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(key, count)| Some((key?, count)))
+        .collect())
+}
+
+#[handler]
+pub async fn render_tags_view(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let db = get_db(depot)?;
+    let counts = article_counts(db, article_tb::Column::TagId).await?;
+    let tags: Vec<JsonValue> = TagTb::find()
+        .order_by_asc(tag_tb::Column::Id)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|t| {
+            json!({
+                "id":t.id,
+                "name":t.name.unwrap_or_default(),
+                "articles":counts.get(&t.id).copied().unwrap_or(0),
+            })
+        })
+        .collect();
+    let mut context = Context::new();
+    context.insert("tags", &tags);
+    render(depot, res, "admin/tags.html", context).await
+}
+
+async fn tag_name(req: &mut Request) -> Result<String, UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let name = req.form::<String>("name").await.unwrap_or_default();
+    let name = name.trim();
+    if name.is_empty() || name.chars().count() > 255 {
+        return fail("请填写标签名称");
+    }
+    Ok(name.to_owned())
+}
+
+async fn tag_name_taken(
+    db: &sea_orm::DatabaseConnection,
+    name: &str,
+    except: Option<i32>,
+) -> anyhow::Result<bool> {
+    let mut find = TagTb::find().filter(tag_tb::Column::Name.eq(name));
+    if let Some(id) = except {
+        find = find.filter(tag_tb::Column::Id.ne(id));
+    }
+    Ok(find.count(db).await? > 0)
+}
+
+#[handler]
+pub async fn add_tag(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let name = tag_name(req).await?;
+    let db = get_db(depot)?;
+    if tag_name_taken(db, &name, None).await? {
+        return fail("该标签已存在");
+    }
+    TagTb::insert(tag_tb::ActiveModel {
+        name: ActiveValue::set(Some(name)),
+        create_time: ActiveValue::set(Some(Local::now().naive_local())),
+        ..Default::default()
+    })
+    .exec(db)
+    .await?;
+    ok(res, "标签已添加");
+    Ok(())
+}
+
+#[handler]
+pub async fn edit_tag(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let id = req.param::<i32>("id").to_result()?;
+    let name = tag_name(req).await?;
+    let db = get_db(depot)?;
+    let Some(tag) = TagTb::find_by_id(id).one(db).await? else {
+        return fail("标签不存在");
+    };
+    if tag_name_taken(db, &name, Some(id)).await? {
+        return fail("该标签已存在");
+    }
+    let mut tag = tag_tb::ActiveModel::from(tag);
+    tag.name = ActiveValue::set(Some(name));
+    tag.update(db).await?;
+    ok(res, "标签已更新");
+    Ok(())
+}
+
+#[handler]
+pub async fn delete_tag(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let id = req.param::<i32>("id").to_result()?;
+    let db = get_db(depot)?;
+    let used = ArticleTb::find()
+        .filter(article_tb::Column::TagId.eq(id))
+        .count(db)
+        .await?;
+    if used > 0 {
+        return fail("该标签下还有文章, 不能删除");
+    }
+    if TagTb::delete_by_id(id).exec(db).await?.rows_affected == 0 {
+        return fail("标签不存在");
+    }
+    ok(res, "标签已删除");
+    Ok(())
+}
+
+#[handler]
+pub async fn render_levels_view(
+    _req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError> {
+    let db = get_db(depot)?;
+    let counts = article_counts(db, article_tb::Column::Level).await?;
+    let levels: Vec<JsonValue> = LevelTb::find()
+        .order_by_asc(level_tb::Column::Level)
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|l| {
+            let level = l.level.unwrap_or_default();
+            json!({
+                "id":l.id,
+                "level":level,
+                "name":l.name.unwrap_or_default(),
+                "articles":counts.get(&level).copied().unwrap_or(0),
+            })
+        })
+        .collect();
+    let roles: Vec<JsonValue> = Role::ALL
+        .iter()
+        .map(|r| json!({"privilege":r.privilege(),"title":r.title()}))
+        .collect();
+    let mut context = Context::new();
+    context.insert("levels", &levels);
+    context.insert("roles", &roles);
+    context.insert("privateLevel", &access::PRIVATE_LEVEL);
+    render(depot, res, "admin/levels.html", context).await
+}
+
+/// The `level` and `name` fields of the level forms.
+///
+/// New numbers must be ones a role can read. [`access::PRIVATE_LEVEL`] has
+/// its seeded row, and higher numbers only survive from before roles, so
+/// `current` may keep its number when renamed.
+async fn level_form(
+    req: &mut Request,
+    current: Option<i32>,
+) -> Result<(i32, String), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let level = req.form::<i32>("level").await;
+    let name = req.form::<String>("name").await.unwrap_or_default();
+    let name = name.trim();
+    let Some(level) = level.filter(|_| !name.is_empty() && name.chars().count() <= 255) else {
+        return fail("请填写等级数值和名称");
+    };
+    let highest = i32::from(Role::Admin.privilege());
+    if !(1..=highest).contains(&level) && Some(level) != current {
+        return fail(&format!("阅读等级须在 1 到 {highest} 之间"));
+    }
+    Ok((level, name.to_owned()))
+}
+
+/// Whether `row` is the seeded [`access::PRIVATE_LEVEL`] one.
+fn is_private(row: &level_tb::Model) -> bool {
+    row.level.map(i64::from) == Some(access::PRIVATE_LEVEL)
+}
+
+async fn level_taken(
+    db: &impl sea_orm::ConnectionTrait,
+    level: i32,
+    except: Option<i32>,
+) -> anyhow::Result<bool> {
+    let mut find = LevelTb::find().filter(level_tb::Column::Level.eq(level));
+    if let Some(id) = except {
+        find = find.filter(level_tb::Column::Id.ne(id));
+    }
+    Ok(find.count(db).await? > 0)
+}
+
+#[handler]
+pub async fn add_level(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let (level, name) = level_form(req, None).await?;
+    let db = get_db(depot)?;
+    if level_taken(db, level, None).await? {
+        return fail("该阅读等级已存在");
+    }
+    LevelTb::insert(level_tb::ActiveModel {
+        level: ActiveValue::set(Some(level)),
+        name: ActiveValue::set(Some(name)),
+        create_time: ActiveValue::set(Some(Local::now().naive_local())),
+        ..Default::default()
+    })
+    .exec(db)
+    .await?;
+    ok(res, "阅读等级已添加");
+    Ok(())
+}
+
+/// Rename a level or change its number. Articles store the number rather
+/// than the row id, so they move along with it.
+#[handler]
+pub async fn edit_level(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let id = req.param::<i32>("id").to_result()?;
+    let txn = get_db(depot)?.begin().await?;
+    let Some(row) = LevelTb::find_by_id(id).one(&txn).await? else {
+        return fail("阅读等级不存在");
+    };
+    let (level, name) = level_form(req, row.level).await?;
+    if is_private(&row) && row.level != Some(level) {
+        return fail("仅自己可见的等级不能修改数值");
+    }
+    if level_taken(&txn, level, Some(id)).await? {
+        return fail("该阅读等级已存在");
+    }
+    if let Some(old) = row.level.filter(|old| *old != level) {
+        ArticleTb::update_many()
+            .col_expr(article_tb::Column::Level, Expr::value(level))
+            .filter(article_tb::Column::Level.eq(old))
+            .exec(&txn)
+            .await?;
+    }
+    let mut row = level_tb::ActiveModel::from(row);
+    row.level = ActiveValue::set(Some(level));
+    row.name = ActiveValue::set(Some(name));
+    row.update(&txn).await?;
+    txn.commit().await?;
+    ok(res, "阅读等级已更新");
+    Ok(())
+}
+
+#[handler]
+pub async fn delete_level(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+) -> Result<(), UniformError<RESPONSE_JSON_FOR_ERROR>> {
+    let id = req.param::<i32>("id").to_result()?;
+    let db = get_db(depot)?;
+    let Some(row) = LevelTb::find_by_id(id).one(db).await? else {
+        return fail("阅读等级不存在");
+    };
+    if is_private(&row) {
+        return fail("仅自己可见的等级不能删除");
+    }
+    let used = ArticleTb::find()
+        .filter(article_tb::Column::Level.eq(row.level))
+        .count(db)
+        .await?;
+    if used > 0 {
+        return fail("该阅读等级下还有文章, 不能删除");
+    }
+    LevelTb::delete_by_id(id).exec(db).await?;
+    ok(res, "阅读等级已删除");
+    Ok(())
+}
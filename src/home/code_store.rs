@@ -0,0 +1,240 @@
+//! Storage for the email verification codes sent by `sendcode`.
+//!
+//! Codes live either in Redis, so every node behind a load balancer sees them,
+//! or in a map inside the process, which is enough for a single node and lets
+//! the blog run without a Redis server. `code_store` in the config picks one.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use redis::AsyncTypedCommands;
+use redis::aio::{ConnectionManager, ConnectionManagerConfig};
+use salvo::async_trait;
+
+use super::verification::constant_time_eq;
+use crate::config::{AppConfig, CodeStoreKind};
+
+#[async_trait]
+pub trait CodeStore: Send + Sync {
+    /// Store `code` under `key`, replacing any previous code and its failed
+    /// attempts, until `ttl` has passed.
+    async fn put(&self, key: &str, code: &str, ttl: Duration) -> anyhow::Result<()>;
+
+    /// The code stored under `key`, unless it has expired.
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>>;
+
+    /// Record a wrong guess for the code under `key` and return how many
+    /// wrong guesses it has had so far.
+    async fn fail(&self, key: &str) -> anyhow::Result<u32>;
+
+    /// Forget the code under `key` so it cannot be used again.
+    async fn remove(&self, key: &str) -> anyhow::Result<()>;
+
+    /// Remove the code under `key` if it is `guess`, as one step, so two
+    /// requests racing with the same code can't both have it accepted.
+    async fn take(&self, key: &str, guess: &str) -> anyhow::Result<Take>;
+}
+
+/// The outcome of [`CodeStore::take`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Take {
+    /// The guess matched and the code is gone
+    Taken,
+    Mismatch,
+    /// No code is stored under the key, or it expired
+    Missing,
+}
+
+/// Build the store selected by `config.code_store`.
+pub async fn from_config(config: &AppConfig) -> anyhow::Result<Arc<dyn CodeStore>> {
+    Ok(match config.code_store {
+        CodeStoreKind::Redis => Arc::new(RedisCodeStore::new(connect_redis(config).await?)),
+        CodeStoreKind::Memory => Arc::new(MemoryCodeStore::default()),
+    })
+}
+
+/// Open the Redis connection used by [`RedisCodeStore`].
+///
+/// Fails if Redis cannot be reached within `redis_connect_timeout_ms`, so a
+/// misconfigured `redis_url` is reported at startup rather than on the first
+/// email verification.
+pub async fn connect_redis(config: &AppConfig) -> anyhow::Result<ConnectionManager> {
+    let client = redis::Client::open(config.redis_url.as_str())
+        .with_context(|| format!("invalid redis_url `{}`", config.redis_url))?;
+    let manager_config = ConnectionManagerConfig::new()
+        .set_connection_timeout(Duration::from_millis(config.redis_connect_timeout_ms))
+        .set_response_timeout(Duration::from_millis(config.redis_response_timeout_ms))
+        .set_number_of_retries(2)
+        .set_max_delay(config.redis_connect_timeout_ms);
+    ConnectionManager::new_with_config(client, manager_config)
+        .await
+        .with_context(|| format!("failed to connect to Redis at `{}`", config.redis_url))
+}
+
+/// Codes kept in Redis with `SETEX`, so Redis takes care of expiry.
+///
+/// Wrong guesses are counted under `<key>:attempts`, which is written with the
+/// same TTL as the code so both disappear together.
+///
+/// The connection manager multiplexes every request over one connection and
+/// reconnects on its own after an outage.
+pub struct RedisCodeStore {
+    redis: ConnectionManager,
+}
+
+impl RedisCodeStore {
+    pub fn new(redis: ConnectionManager) -> Self {
+        RedisCodeStore { redis }
+    }
+
+    fn attempts_key(key: &str) -> String {
+        format!("{key}:attempts")
+    }
+}
+
+/// Deletes the code in `KEYS[1]` and its attempts in `KEYS[2]` when the code
+/// is `ARGV[1]`. Answers 1 when it was, 0 when it wasn't and -1 without a code.
+const TAKE_SCRIPT: &str = r"
+local code = redis.call('GET', KEYS[1])
+if not code then
+    return -1
+end
+if code ~= ARGV[1] then
+    return 0
+end
+redis.call('DEL', KEYS[1], KEYS[2])
+return 1
+";
+
+/// Redis only holds the verification codes, so tell the user that part is
+/// unavailable instead of passing the driver error through.
+fn unavailable(e: redis::RedisError) -> anyhow::Error {
+    tracing::error!("redis request failed: {e}");
+    anyhow::anyhow!("验证码服务暂时不可用, 请稍后再试")
+}
+
+#[async_trait]
+impl CodeStore for RedisCodeStore {
+    async fn put(&self, key: &str, code: &str, ttl: Duration) -> anyhow::Result<()> {
+        let mut redis = self.redis.clone();
+        let seconds = ttl.as_secs().max(1);
+        redis
+            .set_ex(Self::attempts_key(key), 0, seconds)
+            .await
+            .map_err(unavailable)?;
+        redis.set_ex(key, code, seconds).await.map_err(unavailable)
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        self.redis.clone().get(key).await.map_err(unavailable)
+    }
+
+    async fn fail(&self, key: &str) -> anyhow::Result<u32> {
+        // INCR keeps the TTL set by `put`
+        let attempts = self
+            .redis
+            .clone()
+            .incr(Self::attempts_key(key), 1)
+            .await
+            .map_err(unavailable)?;
+        Ok(u32::try_from(attempts).unwrap_or(u32::MAX))
+    }
+
+    async fn remove(&self, key: &str) -> anyhow::Result<()> {
+        self.redis
+            .clone()
+            .del(&[key.to_owned(), Self::attempts_key(key)])
+            .await
+            .map(drop)
+            .map_err(unavailable)
+    }
+
+    async fn take(&self, key: &str, guess: &str) -> anyhow::Result<Take> {
+        // Redis runs a script without interleaving other commands
+        let taken: i64 = redis::Script::new(TAKE_SCRIPT)
+            .key(key)
+            .key(Self::attempts_key(key))
+            .arg(guess)
+            .invoke_async(&mut self.redis.clone())
+            .await
+            .map_err(unavailable)?;
+        Ok(match taken {
+            1 => Take::Taken,
+            0 => Take::Mismatch,
+            _ => Take::Missing,
+        })
+    }
+}
+
+/// Codes kept in this process. They are lost on restart and not shared between nodes.
+#[derive(Default)]
+pub struct MemoryCodeStore {
+    entries: Mutex<HashMap<String, MemoryEntry>>,
+}
+
+struct MemoryEntry {
+    code: String,
+    expires: Instant,
+    failures: u32,
+}
+
+#[async_trait]
+impl CodeStore for MemoryCodeStore {
+    async fn put(&self, key: &str, code: &str, ttl: Duration) -> anyhow::Result<()> {
+        let now = Instant::now();
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        // Sweep on write so codes that were never used don't pile up
+        entries.retain(|_, entry| entry.expires > now);
+        entries.insert(
+            key.to_owned(),
+            MemoryEntry {
+                code: code.to_owned(),
+                expires: now + ttl,
+                failures: 0,
+            },
+        );
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> anyhow::Result<Option<String>> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(entries
+            .get(key)
+            .filter(|entry| entry.expires > Instant::now())
+            .map(|entry| entry.code.clone()))
+    }
+
+    async fn fail(&self, key: &str) -> anyhow::Result<u32> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(match entries.get_mut(key) {
+            Some(entry) => {
+                entry.failures += 1;
+                entry.failures
+            }
+            None => 0,
+        })
+    }
+
+    async fn remove(&self, key: &str) -> anyhow::Result<()> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.remove(key);
+        Ok(())
+    }
+
+    async fn take(&self, key: &str, guess: &str) -> anyhow::Result<Take> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(entry) = entries
+            .get(key)
+            .filter(|entry| entry.expires > Instant::now())
+        else {
+            return Ok(Take::Missing);
+        };
+        if !constant_time_eq(entry.code.as_bytes(), guess.as_bytes()) {
+            return Ok(Take::Mismatch);
+        }
+        entries.remove(key);
+        Ok(Take::Taken)
+    }
+}
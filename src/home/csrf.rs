@@ -0,0 +1,138 @@
+//! Cross-site request forgery protection.
+//!
+//! Signed double-submit cookie: every visitor gets a `csrf_token` cookie
+//! holding a random nonce and its HMAC under `secret_key`, and pages carry the
+//! same token in `csrfToken` for their `<meta name="csrf-token">` tag, from
+//! where `public/js/csrf.js` sends it back as the `x-csrf-token` header on
+//! every jQuery request. [`CsrfGuard`] turns away any request other than
+//! `GET`, `HEAD` or `OPTIONS` unless that header, or a `csrf_token` form or
+//! query field, matches the cookie.
+//!
+//! Another site can make the browser send the cookie along but can't read it,
+//! and the signature stops a cookie planted from elsewhere being accepted.
+
+use hmac::{Hmac, Mac};
+use rand::rngs::OsRng;
+use rand::{RngCore, TryRngCore};
+use salvo::http::Method;
+use salvo::http::cookie::{Cookie, SameSite};
+use salvo::prelude::*;
+use sha2::Sha256;
+
+use super::AppState;
+use super::verification::constant_time_eq;
+
+pub const COOKIE: &str = "csrf_token";
+pub const HEADER: &str = "x-csrf-token";
+/// Form or query field accepted instead of the header, for the editor's image
+/// upload which posts a plain form from a hidden iframe.
+pub const FIELD: &str = "csrf_token";
+
+/// Where the guard leaves the token for the templates.
+const DEPOT_KEY: &str = "csrf_token";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn sign(secret: &str, nonce: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any length");
+    mac.update(b"csrf:");
+    mac.update(nonce.as_bytes());
+    hex(&mac.finalize().into_bytes())
+}
+
+/// A fresh token: 128 random bits and their signature.
+pub fn issue(secret: &str) -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.unwrap_err().fill_bytes(&mut bytes);
+    let nonce = hex(&bytes);
+    let signature = sign(secret, &nonce);
+    format!("{nonce}.{signature}")
+}
+
+/// Whether `token` was issued with `secret`.
+pub fn is_valid(secret: &str, token: &str) -> bool {
+    let Some((nonce, signature)) = token.split_once('.') else {
+        return false;
+    };
+    constant_time_eq(sign(secret, nonce).as_bytes(), signature.as_bytes())
+}
+
+/// The token of the current visitor, for pages that send requests back.
+pub fn token(depot: &Depot) -> &str {
+    depot
+        .get::<String>(DEPOT_KEY)
+        .map(String::as_str)
+        .unwrap_or_default()
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// The token sent back with the request, from the header or a field.
+async fn submitted(req: &mut Request) -> Option<String> {
+    if let Some(token) = req.header::<String>(HEADER) {
+        return Some(token);
+    }
+    if let Some(token) = req.query::<String>(FIELD) {
+        return Some(token);
+    }
+    req.form::<String>(FIELD).await
+}
+
+/// Hoop that hands out the `csrf_token` cookie and checks it on every request
+/// that can change something.
+pub struct CsrfGuard;
+
+#[async_trait]
+impl Handler for CsrfGuard {
+    async fn handle(
+        &self,
+        req: &mut Request,
+        depot: &mut Depot,
+        res: &mut Response,
+        ctrl: &mut FlowCtrl,
+    ) {
+        let Ok(state) = depot.obtain::<AppState>() else {
+            return;
+        };
+        let secret = state.secret_key().to_owned();
+        let base_url = state.base_url().to_owned();
+        let cookie = req
+            .cookie(COOKIE)
+            .map(|c| c.value().to_owned())
+            .filter(|token| is_valid(&secret, token));
+
+        if !is_safe(req.method()) {
+            let accepted = match (&cookie, submitted(req).await) {
+                (Some(expected), Some(sent)) => {
+                    constant_time_eq(expected.as_bytes(), sent.trim().as_bytes())
+                }
+                _ => false,
+            };
+            if !accepted {
+                super::access::deny_with(req, depot, res, "页面已过期, 请刷新后重试");
+                ctrl.skip_rest();
+                return;
+            }
+        }
+
+        let token = match cookie {
+            Some(token) => token,
+            None => {
+                let token = issue(&secret);
+                let cookie = Cookie::build((COOKIE, token.clone()))
+                    .path(base_url)
+                    .http_only(true)
+                    .same_site(SameSite::Lax)
+                    .build();
+                res.add_cookie(cookie);
+                token
+            }
+        };
+        depot.insert(DEPOT_KEY, token);
+    }
+}
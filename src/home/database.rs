@@ -4,7 +4,10 @@ pub mod prelude;
 
 pub mod article_tb;
 pub mod comment_tb;
+pub mod identity_tb;
 pub mod level_tb;
+pub mod recovery_code_tb;
+pub mod session_tb;
 pub mod tag_tb;
 pub mod user_tb;
 pub mod view_tb;
@@ -16,6 +16,8 @@ pub struct Model {
     pub level: Option<i32>,
     pub tag_id: Option<i32>,
     pub article_state: Option<i32>,
+    /// Hidden by a moderator, which the author can't undo
+    pub hidden: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -2,7 +2,10 @@
 
 pub use super::article_tb::Entity as ArticleTb;
 pub use super::comment_tb::Entity as CommentTb;
+pub use super::identity_tb::Entity as IdentityTb;
 pub use super::level_tb::Entity as LevelTb;
+pub use super::recovery_code_tb::Entity as RecoveryCodeTb;
+pub use super::session_tb::Entity as SessionTb;
 pub use super::tag_tb::Entity as TagTb;
 pub use super::user_tb::Entity as UserTb;
 pub use super::view_tb::Entity as ViewTb;
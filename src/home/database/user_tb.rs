@@ -14,6 +14,21 @@ pub struct Model {
     pub password: Option<String>,
     pub privilege: Option<i16>,
     pub avatar: Option<String>,
+    pub token_version: i32,
+    pub totp_secret: Option<String>,
+    pub totp_enabled: bool,
+    pub totp_last_step: Option<i64>,
+    pub status: String,
+    pub suspended_until: Option<DateTime>,
+    pub status_reason: Option<String>,
+    pub display_name: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub bio: Option<String>,
+    pub website: Option<String>,
+    pub location: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub social_links: Option<String>,
+    pub clearance: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
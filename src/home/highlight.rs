@@ -0,0 +1,161 @@
+//! Syntax highlighting of fenced code blocks.
+//!
+//! Code is tokenised with syntect and marked up with `hl-` prefixed classes
+//! rather than inline colours, so the look comes from the stylesheet of the
+//! configured theme under `public/public/css/highlight/`. Each line is wrapped
+//! in its own `<span class="line">`, which the stylesheet numbers with a CSS
+//! counter so copying the code never picks up the numbers.
+//!
+//! The info string of a fence may list lines to emphasise after the language,
+//! e.g. `rust {3,5-7}`. Code in a language syntect doesn't know is shown as
+//! plain text with the same line markup.
+
+use std::collections::BTreeSet;
+use std::sync::LazyLock;
+
+use syntect::html::{ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+/// Stylesheets shipped in `public/public/css/highlight`, by file stem.
+pub const THEMES: [&str; 7] = [
+    "inspired-github",
+    "base16-ocean-light",
+    "base16-ocean-dark",
+    "base16-eighties-dark",
+    "base16-mocha-dark",
+    "solarized-light",
+    "solarized-dark",
+];
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// Loading the grammars takes a while, so every renderer shares one set.
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+
+/// A fence's info string taken apart.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Fence {
+    pub lang: String,
+    /// Line numbers to emphasise, counting from 1
+    pub marked: BTreeSet<usize>,
+}
+
+impl Fence {
+    /// Parse `lang {1,3-4}`. Anything that isn't a line number or a range of
+    /// them is ignored.
+    pub fn parse(info: &str) -> Fence {
+        let info = info.trim();
+        let (lang, rest) = match info.find(|c: char| c.is_whitespace() || c == '{') {
+            Some(at) => info.split_at(at),
+            None => (info, ""),
+        };
+        let mut marked = BTreeSet::new();
+        let spec = rest
+            .trim()
+            .strip_prefix('{')
+            .and_then(|r| r.split_once('}'))
+            .map(|(spec, _)| spec)
+            .unwrap_or_default();
+        for part in spec.split(',') {
+            let part = part.trim();
+            let (from, to) = part.split_once('-').unwrap_or((part, part));
+            if let (Ok(from), Ok(to)) = (from.trim().parse::<usize>(), to.trim().parse::<usize>())
+                && from >= 1
+                && to >= from
+            {
+                // Guard against `{1-99999999}` blowing up the set
+                marked.extend((from..=to).take(10_000));
+            }
+        }
+        Fence {
+            lang: lang.to_ascii_lowercase(),
+            marked,
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Classed spans for `code`, or `None` if the language is unknown.
+fn classed(code: &str, lang: &str) -> Option<String> {
+    if lang.is_empty() {
+        return None;
+    }
+    let syntax = SYNTAXES.find_syntax_by_token(lang)?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .ok()?;
+    }
+    Some(generator.finalize())
+}
+
+/// Split highlighted HTML into lines, closing the spans still open at the
+/// end of a line and opening them again on the next.
+fn split_lines(html: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut open: Vec<&str> = Vec::new();
+    let mut line = String::new();
+    let mut has_text = false;
+    let mut rest = html;
+    while !rest.is_empty() {
+        if rest.starts_with("<span") {
+            let end = rest.find('>').map_or(rest.len(), |at| at + 1);
+            open.push(&rest[..end]);
+            line.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix("</span>") {
+            open.pop();
+            line.push_str("</span>");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix('\n') {
+            line.push_str(&"</span>".repeat(open.len()));
+            lines.push(std::mem::take(&mut line));
+            line.extend(open.iter().copied());
+            has_text = false;
+            rest = after;
+        } else {
+            let end = rest.find(['<', '\n']).unwrap_or(rest.len()).max(1);
+            line.push_str(&rest[..end]);
+            has_text = true;
+            rest = &rest[end..];
+        }
+    }
+    if has_text {
+        line.push_str(&"</span>".repeat(open.len()));
+        lines.push(line);
+    }
+    lines
+}
+
+/// The HTML of a code block whose fence carried `info`.
+pub fn code_block(code: &str, info: &str) -> String {
+    let fence = Fence::parse(info);
+    let lines = match classed(code, &fence.lang) {
+        Some(html) => split_lines(&html),
+        None => code.lines().map(escape).collect(),
+    };
+    let mut out = String::from("<pre class=\"hl-code\"><code");
+    if !fence.lang.is_empty() {
+        out.push_str(&format!(" class=\"language-{}\"", escape(&fence.lang)));
+    }
+    out.push('>');
+    for (n, line) in lines.iter().enumerate() {
+        if fence.marked.contains(&(n + 1)) {
+            out.push_str("<span class=\"line hl-marked\">");
+        } else {
+            out.push_str("<span class=\"line\">");
+        }
+        out.push_str(line);
+        out.push_str("\n</span>");
+    }
+    out.push_str("</code></pre>\n");
+    out
+}
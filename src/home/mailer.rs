@@ -0,0 +1,151 @@
+//! Outgoing email.
+//!
+//! Messages are handed to a [`Mailer`] chosen by `mail_transport` in the config:
+//! the Resend HTTP API, any SMTP server, or a local sink that writes each
+//! message to `mail_dir` (or stdout) for development and tests. Bodies are
+//! rendered by the caller from the Tera templates in `views/email/`.
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use anyhow::Context;
+use lettre::message::{Mailbox, header::ContentType};
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use salvo::async_trait;
+use serde_json::json;
+
+use crate::config::{AppConfig, MailTransport};
+
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub html: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> anyhow::Result<()>;
+}
+
+/// Build the mailer selected by `config.mail_transport`.
+pub fn from_config(config: &AppConfig) -> anyhow::Result<Arc<dyn Mailer>> {
+    let from = config.mail_from.clone();
+    // Every transport rejects a malformed sender, so catch it at startup
+    from.parse::<Mailbox>()
+        .with_context(|| format!("`mail_from` is not a valid address: `{from}`"))?;
+    Ok(match config.mail_transport {
+        MailTransport::Resend => Arc::new(ResendMailer {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .context("failed to build the HTTP client for Resend")?,
+            endpoint: format!("{}/emails", config.resend_url.trim_end_matches('/')),
+            key: config.resend_key.clone(),
+            from,
+        }),
+        MailTransport::Smtp => Arc::new(SmtpMailer {
+            transport: AsyncSmtpTransport::<Tokio1Executor>::from_url(&config.smtp_url)
+                .context("invalid `smtp_url`")?
+                .build(),
+            from,
+        }),
+        MailTransport::File => Arc::new(FileMailer {
+            dir: config.mail_dir.clone(),
+            from,
+            sequence: AtomicU64::new(0),
+        }),
+    })
+}
+
+/// Posts to the `/emails` endpoint of the Resend API at `resend_url`.
+pub struct ResendMailer {
+    client: reqwest::Client,
+    endpoint: String,
+    key: String,
+    from: String,
+}
+
+#[async_trait]
+impl Mailer for ResendMailer {
+    async fn send(&self, email: &Email) -> anyhow::Result<()> {
+        let body = json!({
+            "from": self.from,
+            "to": [email.to],
+            "subject": email.subject,
+            "html": email.html,
+        });
+        let response = self
+            .client
+            .post(&self.endpoint)
+            .bearer_auth(&self.key)
+            .json(&body)
+            .send()
+            .await
+            .context("failed to reach Resend")?;
+        let status = response.status();
+        if !status.is_success() {
+            let reply = response.text().await.unwrap_or_default();
+            anyhow::bail!("Resend rejected the email ({status}): {reply}");
+        }
+        Ok(())
+    }
+}
+
+pub struct SmtpMailer {
+    transport: AsyncSmtpTransport<Tokio1Executor>,
+    from: String,
+}
+
+#[async_trait]
+impl Mailer for SmtpMailer {
+    async fn send(&self, email: &Email) -> anyhow::Result<()> {
+        let message = Message::builder()
+            .from(self.from.parse()?)
+            .to(email.to.parse().context("invalid recipient")?)
+            .subject(&email.subject)
+            .header(ContentType::TEXT_HTML)
+            .body(email.html.clone())?;
+        self.transport
+            .send(message)
+            .await
+            .context("SMTP server rejected the email")?;
+        Ok(())
+    }
+}
+
+/// Writes every message to its own `.eml` file in `dir`, or to stdout when no
+/// directory is configured. Nothing is delivered.
+pub struct FileMailer {
+    dir: Option<PathBuf>,
+    from: String,
+    sequence: AtomicU64,
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: &Email) -> anyhow::Result<()> {
+        let message = format!(
+            "From: {}\nTo: {}\nSubject: {}\n\n{}\n",
+            self.from, email.to, email.subject, email.html
+        );
+        let Some(dir) = &self.dir else {
+            println!("{message}");
+            return Ok(());
+        };
+        tokio::fs::create_dir_all(dir)
+            .await
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        // Zero-padded so the files sort in the order they were sent
+        let name = format!(
+            "{:013}-{:06}.eml",
+            chrono::Utc::now().timestamp_millis(),
+            self.sequence.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        tokio::fs::write(&path, message)
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
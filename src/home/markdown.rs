@@ -0,0 +1,239 @@
+//! Server-side rendering of article and comment Markdown.
+//!
+//! The dialect is CommonMark with the GitHub extensions for tables, task
+//! lists, strikethrough and footnotes. Math between `$…$` or `$$…$$` becomes
+//! MathML through [`math`]. Every heading gets an `id` and an anchor link to
+//! itself. Fenced code blocks are highlighted by [`highlight`]. The resulting
+//! HTML goes through the [`Sanitizer`] before it is shown, so raw HTML in the
+//! source is held to the same policy as everywhere else.
+//!
+//! Rendering a long article on every view is wasteful. [`Markdown`] therefore
+//! keeps the HTML of each article and comment until its `update_time` changes.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use pulldown_cmark::{
+    CodeBlockKind, CowStr, Event, HeadingLevel, Options, Parser, Tag, TagEnd, html,
+};
+
+use super::highlight;
+use super::math;
+use super::sanitize::Sanitizer;
+
+/// Extensions the blog's Markdown is parsed with.
+pub const OPTIONS: Options = Options::ENABLE_TABLES
+    .union(Options::ENABLE_FOOTNOTES)
+    .union(Options::ENABLE_STRIKETHROUGH)
+    .union(Options::ENABLE_TASKLISTS)
+    .union(Options::ENABLE_MATH)
+    .union(Options::ENABLE_GFM);
+
+/// Past this many entries the cache starts over.
+const CACHE_CAPACITY: usize = 1024;
+
+/// What a cached rendering belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Source {
+    Article,
+    Comment,
+}
+
+/// Where rendered Markdown is shown.
+///
+/// Pages carry a `<base href>`, so links to headings and footnotes spell out
+/// the page they are on; `id_prefix` keeps the ids of the comments on an
+/// article apart from those of the article.
+#[derive(Debug, Clone, Copy)]
+pub struct Target<'a> {
+    pub page: &'a str,
+    pub id_prefix: &'a str,
+}
+
+struct Cached {
+    stamp: String,
+    html: Arc<str>,
+}
+
+/// The renderer and its cache, shared through `AppState`.
+pub struct Markdown {
+    sanitizer: Sanitizer,
+    cache: Mutex<HashMap<(Source, i32), Cached>>,
+}
+
+impl Markdown {
+    pub fn new(sanitizer: Sanitizer) -> Markdown {
+        Markdown {
+            sanitizer,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Render `markdown` to sanitized HTML.
+    pub fn render(&self, markdown: &str, target: Target<'_>) -> String {
+        self.sanitizer.clean(&to_html(markdown, target))
+    }
+
+    /// Render the Markdown of article or comment `id`, reusing the HTML from
+    /// an earlier call as long as `stamp`, its update time, is the same.
+    pub fn cached(
+        &self,
+        source: Source,
+        id: i32,
+        stamp: &str,
+        markdown: &str,
+        target: Target<'_>,
+    ) -> Arc<str> {
+        let key = (source, id);
+        {
+            let cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(cached) = cache.get(&key).filter(|c| c.stamp == stamp) {
+                return cached.html.clone();
+            }
+        }
+        let html: Arc<str> = self.render(markdown, target).into();
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+        cache.insert(
+            key,
+            Cached {
+                stamp: stamp.to_owned(),
+                html: html.clone(),
+            },
+        );
+        html
+    }
+
+    /// Drop the cached HTML of `id`, for changes made within the same second.
+    pub fn forget(&self, source: Source, id: i32) {
+        let mut cache = self.cache.lock().unwrap_or_else(|e| e.into_inner());
+        cache.remove(&(source, id));
+    }
+}
+
+/// Turn `text` into an id: lowercase letters and digits, words joined by `-`.
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        String::from("section")
+    } else {
+        slug.to_owned()
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Render without sanitizing.
+fn to_html(markdown: &str, target: Target<'_>) -> String {
+    let Target { page, id_prefix } = target;
+    let page = escape(page);
+    let mut events = Vec::new();
+    let mut heading: Option<(HeadingLevel, Vec<Event<'_>>)> = None;
+    let mut code: Option<(CowStr<'_>, String)> = None;
+    let mut slugs: HashMap<String, usize> = HashMap::new();
+    let mut footnotes: HashMap<String, usize> = HashMap::new();
+    let mut footnote_number = |name: &str| {
+        let next = footnotes.len() + 1;
+        *footnotes.entry(name.to_owned()).or_insert(next)
+    };
+
+    for event in Parser::new_ext(markdown, OPTIONS) {
+        if let Some((level, inner)) = &mut heading {
+            if !matches!(event, Event::End(TagEnd::Heading(_))) {
+                inner.push(event);
+                continue;
+            }
+            let text: String = inner
+                .iter()
+                .filter_map(|e| match e {
+                    Event::Text(t) | Event::Code(t) | Event::InlineMath(t) => Some(t.as_ref()),
+                    _ => None,
+                })
+                .collect();
+            let mut slug = slugify(&text);
+            let seen = slugs.entry(slug.clone()).or_default();
+            if *seen > 0 {
+                slug = format!("{slug}-{seen}");
+            }
+            *seen += 1;
+            let mut content = String::new();
+            html::push_html(&mut content, inner.drain(..));
+            events.push(Event::Html(
+                format!(
+                    "<{level} id=\"{id_prefix}{slug}\">{content}<a class=\"heading-anchor\" href=\"{page}#{id_prefix}{slug}\">#</a></{level}>\n"
+                )
+                .into(),
+            ));
+            heading = None;
+            continue;
+        }
+        if let Some((info, text)) = &mut code {
+            match event {
+                Event::Text(t) => text.push_str(&t),
+                Event::End(TagEnd::CodeBlock) => {
+                    events.push(Event::Html(highlight::code_block(text, info).into()));
+                    code = None;
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match event {
+            Event::Start(Tag::Heading { level, .. }) => heading = Some((level, Vec::new())),
+            Event::Start(Tag::CodeBlock(kind)) => {
+                let info = match kind {
+                    CodeBlockKind::Fenced(info) => info,
+                    CodeBlockKind::Indented => CowStr::Borrowed(""),
+                };
+                code = Some((info, String::new()));
+            }
+            Event::FootnoteReference(name) => {
+                let n = footnote_number(&name);
+                let id = escape(&name);
+                events.push(Event::Html(
+                    format!(
+                        "<sup class=\"footnote-reference\"><a href=\"{page}#{id_prefix}fn-{id}\">{n}</a></sup>"
+                    )
+                    .into(),
+                ));
+            }
+            Event::Start(Tag::FootnoteDefinition(name)) => {
+                let n = footnote_number(&name);
+                let id = escape(&name);
+                events.push(Event::Html(
+                    format!(
+                        "<div class=\"footnote-definition\" id=\"{id_prefix}fn-{id}\"><sup class=\"footnote-definition-label\">{n}</sup>"
+                    )
+                    .into(),
+                ));
+            }
+            Event::End(TagEnd::FootnoteDefinition) => events.push(Event::Html("</div>\n".into())),
+            Event::InlineMath(tex) => {
+                events.push(Event::InlineHtml(math::render(&tex, false).into()))
+            }
+            Event::DisplayMath(tex) => {
+                events.push(Event::InlineHtml(math::render(&tex, true).into()))
+            }
+            event => events.push(event),
+        }
+    }
+
+    let mut out = String::with_capacity(markdown.len() * 3 / 2);
+    html::push_html(&mut out, events.into_iter());
+    out
+}
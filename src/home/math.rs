@@ -0,0 +1,852 @@
+//! TeX math to MathML.
+//!
+//! `$…$` and `$$…$$` in articles and comments are turned into MathML when the
+//! Markdown is rendered, which browsers draw without any script and which
+//! survives in feeds and emails. The converter understands the everyday subset
+//! of LaTeX math: scripts, fractions, roots, Greek letters and the common
+//! symbols, accents, fonts, `\left…\right`, `\text` and the matrix, `cases` and
+//! `aligned` environments. Anything else is reported in an inline marker that
+//! shows the source, so a typo never breaks the rest of the page.
+
+use std::fmt::Write;
+
+/// Deeper nesting than this is refused rather than recursed into.
+const MAX_DEPTH: usize = 64;
+
+type Result<T> = std::result::Result<T, String>;
+
+/// Render `tex`, or an error marker holding the source when it can't be.
+pub fn render(tex: &str, display: bool) -> String {
+    match to_mathml(tex, display) {
+        Ok(mathml) => mathml,
+        Err(e) => {
+            let delimiter = if display { "$$" } else { "$" };
+            format!(
+                "<span class=\"math-error\" title=\"公式有误: {}\">{delimiter}{}{delimiter}</span>",
+                escape(&e),
+                escape(tex)
+            )
+        }
+    }
+}
+
+/// The MathML for `tex`, with the source kept as an annotation.
+pub fn to_mathml(tex: &str, display: bool) -> Result<String> {
+    let mut parser = Parser::new(tex);
+    let nodes = parser.sequence(&|t| *t == Tok::Eof, "")?;
+    let mut out = String::from(if display {
+        "<math display=\"block\">"
+    } else {
+        "<math>"
+    });
+    out.push_str("<semantics>");
+    Node::row(nodes).write(&mut out);
+    let _ = write!(
+        out,
+        "<annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        escape(tex.trim())
+    );
+    Ok(out)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Command(String),
+    Char(char),
+    Open,
+    Close,
+    Sup,
+    Sub,
+    Amp,
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Leaf {
+        tag: &'static str,
+        attrs: Vec<(&'static str, &'static str)>,
+        text: String,
+    },
+    Element {
+        tag: &'static str,
+        attrs: Vec<(&'static str, &'static str)>,
+        children: Vec<Node>,
+    },
+}
+
+impl Node {
+    fn leaf(tag: &'static str, text: impl Into<String>) -> Node {
+        Node::Leaf {
+            tag,
+            attrs: Vec::new(),
+            text: text.into(),
+        }
+    }
+
+    fn op(text: impl Into<String>) -> Node {
+        Node::leaf("mo", text)
+    }
+
+    fn with(mut self, name: &'static str, value: &'static str) -> Node {
+        match &mut self {
+            Node::Leaf { attrs, .. } | Node::Element { attrs, .. } => attrs.push((name, value)),
+        }
+        self
+    }
+
+    fn element(tag: &'static str, children: Vec<Node>) -> Node {
+        Node::Element {
+            tag,
+            attrs: Vec::new(),
+            children,
+        }
+    }
+
+    fn row(mut nodes: Vec<Node>) -> Node {
+        if nodes.len() == 1 {
+            nodes.remove(0)
+        } else {
+            Node::element("mrow", nodes)
+        }
+    }
+
+    /// A big operator or function like `\sum` or `\lim`, whose limits go
+    /// under and over it in display math.
+    fn takes_limits(&self) -> bool {
+        matches!(self, Node::Leaf { attrs, .. } if attrs.contains(&("movablelimits", "true")))
+    }
+
+    /// Give every identifier in the tree the font `variant`.
+    fn set_variant(&mut self, variant: &'static str) {
+        match self {
+            Node::Leaf { tag, attrs, .. } if *tag == "mi" || *tag == "mn" => {
+                attrs.retain(|(name, _)| *name != "mathvariant");
+                attrs.push(("mathvariant", variant));
+            }
+            Node::Leaf { .. } => {}
+            Node::Element { children, .. } => {
+                for child in children {
+                    child.set_variant(variant);
+                }
+            }
+        }
+    }
+
+    fn write(&self, out: &mut String) {
+        match self {
+            Node::Leaf { tag, attrs, text } => {
+                out.push('<');
+                out.push_str(tag);
+                for (name, value) in attrs {
+                    let _ = write!(out, " {name}=\"{value}\"");
+                }
+                let _ = write!(out, ">{}</{tag}>", escape(text));
+            }
+            Node::Element {
+                tag,
+                attrs,
+                children,
+            } => {
+                out.push('<');
+                out.push_str(tag);
+                for (name, value) in attrs {
+                    let _ = write!(out, " {name}=\"{value}\"");
+                }
+                out.push('>');
+                for child in children {
+                    child.write(out);
+                }
+                let _ = write!(out, "</{tag}>");
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    depth: usize,
+}
+
+impl Parser {
+    fn new(tex: &str) -> Parser {
+        Parser {
+            chars: tex.chars().collect(),
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn skip_space(&mut self) {
+        while self.chars.get(self.pos).is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn next(&mut self) -> Tok {
+        self.skip_space();
+        let Some(&c) = self.chars.get(self.pos) else {
+            return Tok::Eof;
+        };
+        self.pos += 1;
+        match c {
+            '{' => Tok::Open,
+            '}' => Tok::Close,
+            '^' => Tok::Sup,
+            '_' => Tok::Sub,
+            '&' => Tok::Amp,
+            '\\' => {
+                let start = self.pos;
+                while self
+                    .chars
+                    .get(self.pos)
+                    .is_some_and(|c| c.is_ascii_alphabetic())
+                {
+                    self.pos += 1;
+                }
+                if self.pos == start {
+                    match self.chars.get(self.pos) {
+                        Some(&c) => {
+                            self.pos += 1;
+                            Tok::Command(c.to_string())
+                        }
+                        None => Tok::Command(String::new()),
+                    }
+                } else {
+                    Tok::Command(self.chars[start..self.pos].iter().collect())
+                }
+            }
+            c => Tok::Char(c),
+        }
+    }
+
+    fn peek(&mut self) -> Tok {
+        let pos = self.pos;
+        let tok = self.next();
+        self.pos = pos;
+        tok
+    }
+
+    /// Atoms with their scripts up to, not including, a token `stop` accepts.
+    /// Running out of input first is an error saying `missing`.
+    fn sequence(&mut self, stop: &dyn Fn(&Tok) -> bool, missing: &str) -> Result<Vec<Node>> {
+        let mut nodes = Vec::new();
+        loop {
+            let tok = self.peek();
+            if stop(&tok) {
+                break;
+            }
+            match tok {
+                Tok::Eof => return Err(format!("缺少 {missing}")),
+                Tok::Close => return Err(String::from("多余的 }")),
+                Tok::Amp => return Err(String::from("& 只能用在矩阵等环境中")),
+                _ => {}
+            }
+            let base = match tok {
+                Tok::Sup | Tok::Sub => Node::element("mrow", Vec::new()),
+                _ => self.atom()?,
+            };
+            nodes.push(self.scripts(base)?);
+        }
+        Ok(nodes)
+    }
+
+    /// Attach any `^` and `_` that follow `base`.
+    fn scripts(&mut self, base: Node) -> Result<Node> {
+        let mut sup = None;
+        let mut sub = None;
+        loop {
+            match self.peek() {
+                Tok::Sup => {
+                    self.next();
+                    if sup.is_some() {
+                        return Err(String::from("重复的上标"));
+                    }
+                    sup = Some(self.argument("^")?);
+                }
+                Tok::Sub => {
+                    self.next();
+                    if sub.is_some() {
+                        return Err(String::from("重复的下标"));
+                    }
+                    sub = Some(self.argument("_")?);
+                }
+                Tok::Char('\'') => {
+                    self.next();
+                    sup = Some(match sup {
+                        Some(Node::Leaf {
+                            tag: "mo", text, ..
+                        }) => Node::op(text + "′"),
+                        Some(other) => Node::row(vec![other, Node::op("′")]),
+                        None => Node::op("′"),
+                    });
+                }
+                _ => break,
+            }
+        }
+        let limits = base.takes_limits();
+        Ok(match (sub, sup) {
+            (None, None) => base,
+            (Some(sub), None) if limits => Node::element("munder", vec![base, sub]),
+            (None, Some(sup)) if limits => Node::element("mover", vec![base, sup]),
+            (Some(sub), Some(sup)) if limits => Node::element("munderover", vec![base, sub, sup]),
+            (Some(sub), None) => Node::element("msub", vec![base, sub]),
+            (None, Some(sup)) => Node::element("msup", vec![base, sup]),
+            (Some(sub), Some(sup)) => Node::element("msubsup", vec![base, sub, sup]),
+        })
+    }
+
+    /// The required argument of `command`: a group or a single token. As in
+    /// TeX a single digit is a token of its own, so `\frac12` is ½.
+    fn argument(&mut self, command: &str) -> Result<Node> {
+        match self.peek() {
+            Tok::Eof | Tok::Close | Tok::Amp | Tok::Sup | Tok::Sub => {
+                Err(format!("{command} 缺少参数"))
+            }
+            Tok::Char(c) if c.is_ascii_digit() => {
+                self.next();
+                Ok(Node::leaf("mn", c))
+            }
+            _ => self.atom(),
+        }
+    }
+
+    /// The contents of a `{…}` group taken literally, for `\text` and the like.
+    fn raw_group(&mut self, command: &str) -> Result<String> {
+        if self.next() != Tok::Open {
+            return Err(format!("\\{command} 后面需要 {{…}}"));
+        }
+        let mut depth = 0;
+        let mut text = String::new();
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => return Ok(text),
+                '}' => depth -= 1,
+                '\\' => {
+                    // Escaped braces and the like stand for themselves
+                    if let Some(&next) = self.chars.get(self.pos)
+                        && !next.is_ascii_alphabetic()
+                    {
+                        self.pos += 1;
+                        text.push(next);
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            text.push(c);
+        }
+        Err(String::from("缺少 }"))
+    }
+
+    /// One symbol, group or command with its arguments. Every way of nesting
+    /// goes through here, so this is where the depth is kept in check.
+    fn atom(&mut self) -> Result<Node> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(String::from("嵌套过深"));
+        }
+        let node = self.atom_inner();
+        self.depth -= 1;
+        node
+    }
+
+    fn atom_inner(&mut self) -> Result<Node> {
+        match self.next() {
+            Tok::Open => {
+                let mut nodes = self.sequence(&|t| *t == Tok::Close, "}")?;
+                self.next();
+                // A braced `\sum` is an ordinary symbol, so it keeps its row
+                if nodes.len() == 1 && !nodes[0].takes_limits() {
+                    Ok(nodes.remove(0))
+                } else {
+                    Ok(Node::element("mrow", nodes))
+                }
+            }
+            Tok::Char(c) if c.is_ascii_digit() || c == '.' => {
+                let mut number = c.to_string();
+                while let Some(&c) = self.chars.get(self.pos) {
+                    let decimal = c == '.'
+                        && self
+                            .chars
+                            .get(self.pos + 1)
+                            .is_some_and(|d| d.is_ascii_digit());
+                    if !c.is_ascii_digit() && !decimal {
+                        break;
+                    }
+                    number.push(c);
+                    self.pos += 1;
+                }
+                Ok(Node::leaf(if number == "." { "mo" } else { "mn" }, number))
+            }
+            Tok::Char(c) if c.is_alphabetic() => Ok(Node::leaf("mi", c)),
+            Tok::Char(c) => Ok(Node::op(match c {
+                '-' => '−',
+                '*' => '∗',
+                '\'' => '′',
+                c => c,
+            })),
+            Tok::Command(name) => self.command(&name),
+            Tok::Eof => Err(String::from("公式不完整")),
+            Tok::Close => Err(String::from("多余的 }")),
+            Tok::Amp => Err(String::from("& 只能用在矩阵等环境中")),
+            Tok::Sup | Tok::Sub => Err(String::from("上下标缺少底数")),
+        }
+    }
+
+    /// A delimiter after `\left`, `\right` or `\big`; `.` is none at all.
+    fn delimiter(&mut self, command: &str) -> Result<String> {
+        match self.next() {
+            Tok::Char('.') => Ok(String::new()),
+            Tok::Char(c) if "()[]|/<>".contains(c) => Ok(match c {
+                '<' => String::from("⟨"),
+                '>' => String::from("⟩"),
+                c => c.to_string(),
+            }),
+            Tok::Command(name) => match DELIMITERS.iter().find(|(n, _)| *n == name) {
+                Some((_, d)) => Ok((*d).to_owned()),
+                None => Err(format!("\\{command} 后面不能是 \\{name}")),
+            },
+            _ => Err(format!("\\{command} 后面需要括号")),
+        }
+    }
+
+    fn command(&mut self, name: &str) -> Result<Node> {
+        if let Some((_, c)) = GREEK.iter().find(|(n, _)| *n == name) {
+            let node = Node::leaf("mi", *c);
+            // Capital Greek letters are upright, as in TeX
+            return Ok(if c.chars().all(char::is_uppercase) {
+                node.with("mathvariant", "normal")
+            } else {
+                node
+            });
+        }
+        if let Some((_, c)) = IDENTIFIERS.iter().find(|(n, _)| *n == name) {
+            return Ok(Node::leaf("mi", *c));
+        }
+        if let Some((_, c)) = OPERATORS.iter().find(|(n, _)| *n == name) {
+            return Ok(Node::op(*c));
+        }
+        if let Some((_, c)) = DELIMITERS.iter().find(|(n, _)| *n == name) {
+            return Ok(Node::op(*c).with("stretchy", "false"));
+        }
+        if let Some((_, c, limits)) = BIG_OPERATORS.iter().find(|(n, _, _)| *n == name) {
+            let node = Node::op(*c).with("largeop", "true");
+            return Ok(if *limits {
+                node.with("movablelimits", "true")
+            } else {
+                node
+            });
+        }
+        if FUNCTIONS.contains(&name) {
+            return Ok(Node::leaf("mi", name));
+        }
+        if LIMIT_FUNCTIONS.contains(&name) {
+            return Ok(Node::leaf("mo", name).with("movablelimits", "true"));
+        }
+        if let Some((_, width)) = SPACES.iter().find(|(n, _)| *n == name) {
+            return Ok(Node::element("mspace", Vec::new()).with("width", width));
+        }
+        if let Some((_, accent, under)) = ACCENTS.iter().find(|(n, _, _)| *n == name) {
+            let base = self.argument(&format!("\\{name}"))?;
+            let mark = Node::op(*accent).with("stretchy", "true");
+            return Ok(if *under {
+                Node::element("munder", vec![base, mark]).with("accentunder", "true")
+            } else {
+                Node::element("mover", vec![base, mark]).with("accent", "true")
+            });
+        }
+        if let Some((_, variant)) = FONTS.iter().find(|(n, _)| *n == name) {
+            let mut node = self.argument(&format!("\\{name}"))?;
+            node.set_variant(variant);
+            return Ok(node);
+        }
+        match name {
+            "frac" | "dfrac" | "tfrac" | "cfrac" => {
+                let num = self.argument("\\frac")?;
+                let den = self.argument("\\frac")?;
+                Ok(Node::element("mfrac", vec![num, den]))
+            }
+            "binom" => {
+                let n = self.argument("\\binom")?;
+                let k = self.argument("\\binom")?;
+                Ok(Node::element(
+                    "mrow",
+                    vec![
+                        Node::op("("),
+                        Node::element("mfrac", vec![n, k]).with("linethickness", "0"),
+                        Node::op(")"),
+                    ],
+                ))
+            }
+            "sqrt" => {
+                if self.peek() == Tok::Char('[') {
+                    self.next();
+                    let index = self.sequence(&|t| *t == Tok::Char(']'), "]")?;
+                    self.next();
+                    let radicand = self.argument("\\sqrt")?;
+                    Ok(Node::element("mroot", vec![radicand, Node::row(index)]))
+                } else {
+                    let radicand = self.argument("\\sqrt")?;
+                    Ok(Node::element("msqrt", vec![radicand]))
+                }
+            }
+            "text" | "textrm" | "textnormal" | "mbox" | "textit" | "textbf" => {
+                let text = self.raw_group(name)?;
+                let node = Node::leaf("mtext", text);
+                Ok(match name {
+                    "textit" => node.with("mathvariant", "italic"),
+                    "textbf" => node.with("mathvariant", "bold"),
+                    _ => node,
+                })
+            }
+            "operatorname" => {
+                let text = self.raw_group(name)?;
+                Ok(Node::leaf("mi", text.trim()).with("mathvariant", "normal"))
+            }
+            "left" => {
+                let open = self.delimiter("left")?;
+                let inner =
+                    self.sequence(&|t| *t == Tok::Command(String::from("right")), "\\right")?;
+                self.next();
+                let close = self.delimiter("right")?;
+                let mut nodes = vec![Node::op(open).with("stretchy", "true")];
+                nodes.extend(inner);
+                nodes.push(Node::op(close).with("stretchy", "true"));
+                Ok(Node::element("mrow", nodes))
+            }
+            "right" => Err(String::from("\\right 缺少对应的 \\left")),
+            "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl" | "Bigr" | "biggl"
+            | "biggr" | "Biggl" | "Biggr" | "bigm" | "Bigm" => {
+                let delimiter = self.delimiter(name)?;
+                Ok(Node::op(delimiter).with("stretchy", "false"))
+            }
+            "not" => match self.atom()? {
+                Node::Leaf {
+                    tag: "mo", text, ..
+                } => Ok(Node::op(format!("{text}\u{338}"))),
+                _ => Err(String::from("\\not 后面需要关系符号")),
+            },
+            "displaystyle" | "textstyle" | "scriptstyle" | "limits" | "nolimits" => {
+                Ok(Node::element("mrow", Vec::new()))
+            }
+            "begin" => self.environment(),
+            "end" => Err(String::from("\\end 缺少对应的 \\begin")),
+            "\\" => Err(String::from("\\\\ 只能用在矩阵等环境中")),
+            "" => Err(String::from("多余的 \\")),
+            name => Err(format!("未知的命令 \\{name}")),
+        }
+    }
+
+    fn environment(&mut self) -> Result<Node> {
+        let name = self.raw_group("begin")?;
+        let (open, close, align) = match name.as_str() {
+            "matrix" | "smallmatrix" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("‖", "‖", None),
+            "cases" => ("{", "", Some("left left")),
+            "aligned" | "align" | "align*" | "split" => ("", "", Some("right left")),
+            "gathered" | "gather" | "gather*" => ("", "", None),
+            "array" => {
+                // The column spec only aligns, which is left to the defaults
+                self.raw_group("begin{array}")?;
+                ("", "", None)
+            }
+            other => return Err(format!("不支持的环境 {other}")),
+        };
+        let end_of_cell = |t: &Tok| {
+            matches!(t, Tok::Amp) || matches!(t, Tok::Command(c) if c == "\\" || c == "end")
+        };
+        let mut rows = Vec::new();
+        let mut cells = Vec::new();
+        loop {
+            let cell = self.sequence(&end_of_cell, &format!("\\end{{{name}}}"))?;
+            cells.push(Node::element("mtd", vec![Node::row(cell)]));
+            match self.next() {
+                Tok::Amp => {}
+                Tok::Command(c) if c == "\\" => {
+                    rows.push(Node::element("mtr", std::mem::take(&mut cells)));
+                }
+                _ => break,
+            }
+        }
+        let end = self.raw_group("end")?;
+        if end != name {
+            return Err(format!("\\begin{{{name}}} 和 \\end{{{end}}} 不匹配"));
+        }
+        // A trailing `\\` leaves an empty last row behind
+        let empty = cells.len() == 1
+            && matches!(&cells[0], Node::Element { children, .. } if matches!(children.as_slice(), [Node::Element { tag: "mrow", children, .. }] if children.is_empty()));
+        if !empty || rows.is_empty() {
+            rows.push(Node::element("mtr", cells));
+        }
+        let mut table = Node::element("mtable", rows);
+        if let Some(align) = align {
+            table = table.with("columnalign", align);
+        }
+        let mut nodes = Vec::new();
+        if !open.is_empty() {
+            nodes.push(Node::op(open).with("stretchy", "true"));
+        }
+        nodes.push(table);
+        if !close.is_empty() {
+            nodes.push(Node::op(close).with("stretchy", "true"));
+        }
+        Ok(Node::element("mrow", nodes))
+    }
+}
+
+const GREEK: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("omicron", "ο"),
+    ("pi", "π"),
+    ("varpi", "ϖ"),
+    ("rho", "ρ"),
+    ("varrho", "ϱ"),
+    ("sigma", "σ"),
+    ("varsigma", "ς"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+];
+
+const IDENTIFIERS: &[(&str, &str)] = &[
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("emptyset", "∅"),
+    ("varnothing", "∅"),
+    ("hbar", "ℏ"),
+    ("ell", "ℓ"),
+    ("Re", "ℜ"),
+    ("Im", "ℑ"),
+    ("aleph", "ℵ"),
+    ("wp", "℘"),
+    ("imath", "ı"),
+    ("jmath", "ȷ"),
+];
+
+const OPERATORS: &[(&str, &str)] = &[
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("cdot", "⋅"),
+    ("ast", "∗"),
+    ("star", "⋆"),
+    ("circ", "∘"),
+    ("bullet", "∙"),
+    ("oplus", "⊕"),
+    ("ominus", "⊖"),
+    ("otimes", "⊗"),
+    ("odot", "⊙"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("setminus", "∖"),
+    ("wedge", "∧"),
+    ("land", "∧"),
+    ("vee", "∨"),
+    ("lor", "∨"),
+    ("neg", "¬"),
+    ("lnot", "¬"),
+    ("le", "≤"),
+    ("leq", "≤"),
+    ("ge", "≥"),
+    ("geq", "≥"),
+    ("ne", "≠"),
+    ("neq", "≠"),
+    ("ll", "≪"),
+    ("gg", "≫"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("sim", "∼"),
+    ("simeq", "≃"),
+    ("cong", "≅"),
+    ("propto", "∝"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("ni", "∋"),
+    ("subset", "⊂"),
+    ("supset", "⊃"),
+    ("subseteq", "⊆"),
+    ("supseteq", "⊇"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("nexists", "∄"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("gets", "←"),
+    ("leftrightarrow", "↔"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("Leftrightarrow", "⇔"),
+    ("implies", "⟹"),
+    ("impliedby", "⟸"),
+    ("iff", "⟺"),
+    ("mapsto", "↦"),
+    ("uparrow", "↑"),
+    ("downarrow", "↓"),
+    ("longrightarrow", "⟶"),
+    ("longleftarrow", "⟵"),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("cdots", "⋯"),
+    ("vdots", "⋮"),
+    ("ddots", "⋱"),
+    ("prime", "′"),
+    ("angle", "∠"),
+    ("perp", "⊥"),
+    ("parallel", "∥"),
+    ("mid", "∣"),
+    ("vdash", "⊢"),
+    ("models", "⊨"),
+    ("top", "⊤"),
+    ("bot", "⊥"),
+    ("triangle", "△"),
+    ("colon", ":"),
+    ("%", "%"),
+    ("$", "$"),
+    ("#", "#"),
+    ("&", "&"),
+    ("_", "_"),
+    (",", "\u{2009}"),
+];
+
+const DELIMITERS: &[(&str, &str)] = &[
+    ("{", "{"),
+    ("}", "}"),
+    ("lbrace", "{"),
+    ("rbrace", "}"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("vert", "|"),
+    ("lvert", "|"),
+    ("rvert", "|"),
+    ("|", "‖"),
+    ("Vert", "‖"),
+    ("lVert", "‖"),
+    ("rVert", "‖"),
+    ("backslash", "\\"),
+];
+
+/// Name, symbol and whether limits go under and over it.
+const BIG_OPERATORS: &[(&str, &str, bool)] = &[
+    ("sum", "∑", true),
+    ("prod", "∏", true),
+    ("coprod", "∐", true),
+    ("bigcup", "⋃", true),
+    ("bigcap", "⋂", true),
+    ("bigoplus", "⨁", true),
+    ("bigotimes", "⨂", true),
+    ("bigvee", "⋁", true),
+    ("bigwedge", "⋀", true),
+    ("int", "∫", false),
+    ("iint", "∬", false),
+    ("iiint", "∭", false),
+    ("oint", "∮", false),
+];
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "coth", "log", "ln", "lg", "exp", "arg", "deg", "dim", "hom", "ker", "mod",
+];
+
+const LIMIT_FUNCTIONS: &[&str] = &[
+    "lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "gcd", "Pr", "argmax", "argmin",
+];
+
+/// `\,` is a thin space but also an operator above, so it isn't here.
+const SPACES: &[(&str, &str)] = &[
+    (":", "0.2222em"),
+    (";", "0.2778em"),
+    ("!", "-0.1667em"),
+    (" ", "0.3333em"),
+    ("quad", "1em"),
+    ("qquad", "2em"),
+    ("enspace", "0.5em"),
+    ("thinspace", "0.1667em"),
+];
+
+/// Name, mark and whether it goes under the base.
+const ACCENTS: &[(&str, &str, bool)] = &[
+    ("hat", "^", false),
+    ("widehat", "^", false),
+    ("check", "ˇ", false),
+    ("tilde", "~", false),
+    ("widetilde", "~", false),
+    ("bar", "¯", false),
+    ("overline", "¯", false),
+    ("vec", "→", false),
+    ("overrightarrow", "→", false),
+    ("overleftarrow", "←", false),
+    ("dot", "˙", false),
+    ("ddot", "¨", false),
+    ("acute", "´", false),
+    ("grave", "`", false),
+    ("breve", "˘", false),
+    ("overbrace", "⏞", false),
+    ("underline", "_", true),
+    ("underbrace", "⏟", true),
+];
+
+const FONTS: &[(&str, &str)] = &[
+    ("mathrm", "normal"),
+    ("mathit", "italic"),
+    ("mathbf", "bold"),
+    ("boldsymbol", "bold-italic"),
+    ("bm", "bold-italic"),
+    ("mathbb", "double-struck"),
+    ("mathcal", "script"),
+    ("mathscr", "script"),
+    ("mathfrak", "fraktur"),
+    ("mathsf", "sans-serif"),
+    ("mathtt", "monospace"),
+];
@@ -0,0 +1,541 @@
+//! "Sign in with …" through OAuth 2.0 and OpenID Connect providers.
+//!
+//! `/oauth/<id>` sends the browser to the provider with a random `state` and a
+//! PKCE challenge, both remembered in the [`CodeStore`] for a few minutes. The
+//! provider sends it back to `/oauth/<id>/callback`, where the code is traded
+//! for an access token and the account is looked up at the provider's userinfo
+//! endpoint. Accounts are tied to users through `identity_tb`, and the first
+//! sign-in with an account nobody has linked creates a new user.
+//!
+//! The browser that starts a round trip also gets the [`BINDING_COOKIE`], a
+//! hash of the `state`, and the callback is only accepted alongside it. A
+//! callback URL made for someone else's account therefore can't sign a
+//! victim in as that account (RFC 6749 §10.12).
+//!
+//! Accounts created here have no password to confirm sensitive changes with.
+//! Their users go through a linked provider again instead (`?reauth=1`),
+//! which leaves a [`REAUTH_TTL`] long mark that [`take_reauth`] uses up.
+
+use std::time::Duration;
+
+use anyhow::{Context, bail};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Local;
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore, TryRngCore};
+use sea_orm::{
+    ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::OnceCell;
+
+use super::access::Role;
+use super::code_store::CodeStore;
+use super::database::prelude::*;
+use super::database::{identity_tb, user_tb};
+use super::verification::constant_time_eq;
+use super::{email_taken, name_taken};
+use crate::config::{AppConfig, OAuthKind, OAuthProviderConfig};
+
+/// How long the user may take at the provider before the callback is refused
+pub const STATE_TTL: Duration = Duration::from_secs(10 * 60);
+/// Ties a round trip to the browser that started it
+pub const BINDING_COOKIE: &str = "oauth_binding";
+/// How long a fresh trip through the provider stands in for a password
+const REAUTH_TTL: Duration = Duration::from_secs(5 * 60);
+const EXPIRED: &str = "登录请求已失效, 请重试";
+const FAILED: &str = "第三方登录失败, 请稍后再试";
+
+/// The configured providers and the HTTP client used to talk to them.
+pub struct OAuth {
+    client: reqwest::Client,
+    providers: Vec<Provider>,
+    /// `public_url` and `base_url` joined, ending in `/`
+    site_url: String,
+}
+
+struct Provider {
+    config: OAuthProviderConfig,
+    /// Looked up on first use, so a provider being down doesn't stop the blog from starting
+    endpoints: OnceCell<Endpoints>,
+}
+
+struct Endpoints {
+    authorize: String,
+    token: String,
+    userinfo: String,
+}
+
+/// What the browser has to bring back to the callback.
+#[derive(Serialize, Deserialize)]
+struct Pending {
+    provider: String,
+    verifier: String,
+    /// Set when a signed in user is linking another account to their own
+    link_user: Option<i32>,
+    /// The signed in user is proving who they are rather than linking
+    #[serde(default)]
+    reauth: bool,
+}
+
+/// Where to send the browser, and the value of its [`BINDING_COOKIE`].
+pub struct Authorization {
+    pub url: String,
+    pub binding: String,
+}
+
+/// An account at a provider, as told by its userinfo endpoint.
+pub struct ExternalAccount {
+    pub provider: String,
+    pub subject: String,
+    pub username: Option<String>,
+    /// Only an address the provider has verified
+    pub email: Option<String>,
+}
+
+/// The outcome of a round trip through a provider.
+pub struct Completed {
+    pub account: ExternalAccount,
+    pub link_user: Option<i32>,
+    /// Only `link_user` going through an account of theirs again, see [`confirm_reauth`]
+    pub reauth: bool,
+}
+
+impl OAuth {
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<OAuth> {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            // GitHub's API refuses requests without one
+            .user_agent(concat!("blog-rust/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("failed to build the HTTP client for OAuth")?;
+        Ok(OAuth {
+            client,
+            providers: config
+                .oauth_providers
+                .iter()
+                .map(|config| Provider {
+                    config: config.clone(),
+                    endpoints: OnceCell::new(),
+                })
+                .collect(),
+            site_url: format!(
+                "{}{}",
+                config.public_url.trim_end_matches('/'),
+                config.base_url
+            ),
+        })
+    }
+
+    /// `(id, name)` of every provider, for the login page.
+    pub fn providers(&self) -> Vec<(&str, &str)> {
+        self.providers
+            .iter()
+            .map(|p| (p.config.id.as_str(), p.config.display_name()))
+            .collect()
+    }
+
+    fn provider(&self, id: &str) -> anyhow::Result<&Provider> {
+        self.providers
+            .iter()
+            .find(|p| p.config.id == id)
+            .context("不支持的登录方式")
+    }
+
+    fn redirect_uri(&self, provider: &Provider) -> String {
+        format!("{}oauth/{}/callback", self.site_url, provider.config.id)
+    }
+
+    /// Where to send the browser to sign in with `provider_id`, or with
+    /// `reauth` to confirm it is still `link_user`.
+    pub async fn authorize_url(
+        &self,
+        codes: &dyn CodeStore,
+        provider_id: &str,
+        link_user: Option<i32>,
+        reauth: bool,
+    ) -> anyhow::Result<Authorization> {
+        if reauth && link_user.is_none() {
+            bail!("请先登录");
+        }
+        let provider = self.provider(provider_id)?;
+        let endpoints = provider.endpoints(&self.client).await?;
+        let state = random_token();
+        let verifier = random_token();
+        let pending = Pending {
+            provider: provider_id.to_owned(),
+            verifier: verifier.clone(),
+            link_user,
+            reauth,
+        };
+        codes
+            .put(
+                &state_key(&state),
+                &serde_json::to_string(&pending)?,
+                STATE_TTL,
+            )
+            .await?;
+        let challenge = URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()));
+        let mut url = reqwest::Url::parse(&endpoints.authorize)
+            .with_context(|| format!("invalid authorization endpoint of `{provider_id}`"))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.config.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri(provider))
+            .append_pair("scope", &provider.scopes().join(" "))
+            .append_pair("state", &state)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+        Ok(Authorization {
+            url: url.into(),
+            binding: binding(&state),
+        })
+    }
+
+    /// Check the `state` the provider sent back against the `binding` cookie
+    /// of the browser and fetch the account the authorization `code` belongs to.
+    pub async fn finish(
+        &self,
+        codes: &dyn CodeStore,
+        provider_id: &str,
+        code: &str,
+        state: &str,
+        binding: Option<&str>,
+    ) -> anyhow::Result<Completed> {
+        let key = state_key(state);
+        let Some(stored) = codes.get(&key).await? else {
+            bail!(EXPIRED);
+        };
+        // A state is good for one attempt, whatever the outcome
+        codes.remove(&key).await?;
+        let expected = self::binding(state);
+        if !binding.is_some_and(|b| constant_time_eq(b.as_bytes(), expected.as_bytes())) {
+            bail!(EXPIRED);
+        }
+        let pending: Pending = serde_json::from_str(&stored).context(EXPIRED)?;
+        if pending.provider != provider_id {
+            bail!(EXPIRED);
+        }
+        let provider = self.provider(provider_id)?;
+        let account = self
+            .fetch_account(provider, code, &pending.verifier)
+            .await
+            .map_err(|e| {
+                tracing::error!("OAuth sign-in with `{provider_id}` failed: {e:#}");
+                anyhow::anyhow!(FAILED)
+            })?;
+        Ok(Completed {
+            account,
+            link_user: pending.link_user,
+            reauth: pending.reauth,
+        })
+    }
+
+    async fn fetch_account(
+        &self,
+        provider: &Provider,
+        code: &str,
+        verifier: &str,
+    ) -> anyhow::Result<ExternalAccount> {
+        let endpoints = provider.endpoints(&self.client).await?;
+        let token: Value = self
+            .client
+            .post(&endpoints.token)
+            .header("accept", "application/json")
+            .form(&[
+                ("grant_type", "authorization_code"),
+                ("code", code),
+                ("redirect_uri", &self.redirect_uri(provider)),
+                ("client_id", &provider.config.client_id),
+                ("client_secret", &provider.config.client_secret),
+                ("code_verifier", verifier),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // GitHub reports errors with a 200 and an `error` field
+        let Some(access_token) = token["access_token"].as_str() else {
+            bail!("no access token in the token response: {token}");
+        };
+        let info: Value = self
+            .client
+            .get(&endpoints.userinfo)
+            .header("accept", "application/json")
+            .bearer_auth(access_token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        account_from_userinfo(&provider.config.id, &info)
+            .with_context(|| format!("no account id in the userinfo response: {info}"))
+    }
+}
+
+impl Provider {
+    fn scopes(&self) -> Vec<&str> {
+        if !self.config.scopes.is_empty() {
+            return self.config.scopes.iter().map(String::as_str).collect();
+        }
+        match self.config.kind {
+            OAuthKind::Github => vec!["read:user", "user:email"],
+            OAuthKind::Gitea | OAuthKind::Oidc => vec!["openid", "profile", "email"],
+        }
+    }
+
+    async fn endpoints(&self, client: &reqwest::Client) -> anyhow::Result<&Endpoints> {
+        self.endpoints
+            .get_or_try_init(|| async {
+                let url = self.config.url.trim_end_matches('/');
+                Ok(match self.config.kind {
+                    OAuthKind::Github if url.is_empty() => Endpoints {
+                        authorize: "https://github.com/login/oauth/authorize".to_owned(),
+                        token: "https://github.com/login/oauth/access_token".to_owned(),
+                        userinfo: "https://api.github.com/user".to_owned(),
+                    },
+                    OAuthKind::Github => Endpoints {
+                        authorize: format!("{url}/login/oauth/authorize"),
+                        token: format!("{url}/login/oauth/access_token"),
+                        userinfo: format!("{url}/api/v3/user"),
+                    },
+                    OAuthKind::Gitea => Endpoints {
+                        authorize: format!("{url}/login/oauth/authorize"),
+                        token: format!("{url}/login/oauth/access_token"),
+                        userinfo: format!("{url}/login/oauth/userinfo"),
+                    },
+                    OAuthKind::Oidc => discover(client, url).await.map_err(|e| {
+                        tracing::error!("OIDC discovery at `{url}` failed: {e:#}");
+                        anyhow::anyhow!(FAILED)
+                    })?,
+                })
+            })
+            .await
+    }
+}
+
+async fn discover(client: &reqwest::Client, issuer: &str) -> anyhow::Result<Endpoints> {
+    let document: Value = client
+        .get(format!("{issuer}/.well-known/openid-configuration"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let endpoint = |name: &str| {
+        document[name]
+            .as_str()
+            .map(str::to_owned)
+            .with_context(|| format!("`{name}` missing from the discovery document"))
+    };
+    Ok(Endpoints {
+        authorize: endpoint("authorization_endpoint")?,
+        token: endpoint("token_endpoint")?,
+        userinfo: endpoint("userinfo_endpoint")?,
+    })
+}
+
+/// Read the account out of an OIDC userinfo response, or GitHub's `/user`,
+/// which has a numeric `id` and a `login` instead of `sub` and `preferred_username`.
+fn account_from_userinfo(provider: &str, info: &Value) -> Option<ExternalAccount> {
+    let subject = match (&info["sub"], &info["id"]) {
+        (Value::String(sub), _) => sub.clone(),
+        (_, Value::Number(id)) => id.to_string(),
+        (_, Value::String(id)) => id.clone(),
+        _ => return None,
+    };
+    let text = |name: &str| {
+        info[name]
+            .as_str()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_owned)
+    };
+    let username = text("preferred_username")
+        .or_else(|| text("login"))
+        .or_else(|| text("nickname"))
+        .or_else(|| text("name"));
+    // Only keep an address the provider says it checked. GitHub's `/user`
+    // never says, so its addresses are left out.
+    let email = text("email").filter(|_| info["email_verified"] == Value::Bool(true));
+    Some(ExternalAccount {
+        provider: provider.to_owned(),
+        subject,
+        username,
+        email,
+    })
+}
+
+fn state_key(state: &str) -> String {
+    format!("oauth:{state}")
+}
+
+/// What the [`BINDING_COOKIE`] holds for `state`. Only a hash, so the cookie
+/// alone can't be replayed as the `state` parameter.
+fn binding(state: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(state.as_bytes()))
+}
+
+/// 256 bits from the operating system's CSPRNG, URL safe.
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.unwrap_err().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn reauth_key(user_id: i32) -> String {
+    format!("reauth:{user_id}")
+}
+
+/// Mark `completed.link_user` as having just proven who they are, if the
+/// account they went through is one already linked to them. Nothing is
+/// linked here, so a freshly made account at the provider doesn't count.
+pub async fn confirm_reauth(
+    db: &DatabaseConnection,
+    codes: &dyn CodeStore,
+    completed: &Completed,
+) -> anyhow::Result<()> {
+    let account = &completed.account;
+    let user_id = completed.link_user.context("请先登录")?;
+    let linked = IdentityTb::find()
+        .filter(identity_tb::Column::Provider.eq(account.provider.as_str()))
+        .filter(identity_tb::Column::Subject.eq(account.subject.as_str()))
+        .filter(identity_tb::Column::UserId.eq(user_id))
+        .count(db)
+        .await?;
+    if linked == 0 {
+        bail!("请使用已绑定的第三方账号验证身份");
+    }
+    codes.put(&reauth_key(user_id), "1", REAUTH_TTL).await
+}
+
+/// Whether `user_id` went through a linked provider in the last
+/// [`REAUTH_TTL`]. Each trip is good for one change.
+pub async fn take_reauth(codes: &dyn CodeStore, user_id: i32) -> anyhow::Result<bool> {
+    let key = reauth_key(user_id);
+    if codes.get(&key).await?.is_none() {
+        return Ok(false);
+    }
+    codes.remove(&key).await?;
+    Ok(true)
+}
+
+/// `(id, name)` of the configured providers `user_id` has an account linked from.
+pub async fn linked_providers<'a>(
+    oauth: &'a OAuth,
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> anyhow::Result<Vec<(&'a str, &'a str)>> {
+    let linked: Vec<String> = IdentityTb::find()
+        .filter(identity_tb::Column::UserId.eq(user_id))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|identity| identity.provider)
+        .collect();
+    Ok(oauth
+        .providers()
+        .into_iter()
+        .filter(|(id, _)| linked.iter().any(|p| p == id))
+        .collect())
+}
+
+/// The user `completed` signs in as, linking or creating one as needed.
+pub async fn sign_in(
+    db: &DatabaseConnection,
+    completed: &Completed,
+) -> anyhow::Result<user_tb::Model> {
+    let account = &completed.account;
+    let identity = IdentityTb::find()
+        .filter(identity_tb::Column::Provider.eq(account.provider.as_str()))
+        .filter(identity_tb::Column::Subject.eq(account.subject.as_str()))
+        .one(db)
+        .await?;
+    let user_id = match (identity, completed.link_user) {
+        (Some(identity), Some(link_user)) if identity.user_id != link_user => {
+            bail!("该第三方账号已绑定其他用户");
+        }
+        (Some(identity), _) => identity.user_id,
+        (None, Some(link_user)) => {
+            IdentityTb::insert(new_identity(account, link_user))
+                .exec(db)
+                .await?;
+            link_user
+        }
+        (None, None) => create_user(db, account).await?,
+    };
+    UserTb::find_by_id(user_id)
+        .one(db)
+        .await?
+        .context("绑定的用户不存在")
+}
+
+fn new_identity(account: &ExternalAccount, user_id: i32) -> identity_tb::ActiveModel {
+    identity_tb::ActiveModel {
+        user_id: ActiveValue::set(user_id),
+        provider: ActiveValue::set(account.provider.clone()),
+        subject: ActiveValue::set(account.subject.clone()),
+        email: ActiveValue::set(account.email.clone()),
+        create_time: ActiveValue::set(Local::now().naive_local()),
+        ..Default::default()
+    }
+}
+
+/// A user for an account signing in for the first time. It has no password,
+/// so it can only sign in through the provider until one is set.
+async fn create_user(db: &DatabaseConnection, account: &ExternalAccount) -> anyhow::Result<i32> {
+    let txn = db.begin().await?;
+    let name = unused_name(&txn, account).await?;
+    // An address someone already has would let this account reset their
+    // password, so the new user goes without one
+    let email = match &account.email {
+        Some(email) if !email_taken(&txn, email, None).await? => Some(email.clone()),
+        _ => None,
+    };
+    let now = Local::now().naive_local();
+    let user = user_tb::ActiveModel {
+        name: ActiveValue::set(Some(name)),
+        email: ActiveValue::set(email),
+        password: ActiveValue::set(None),
+        privilege: ActiveValue::set(Some(Role::Author.privilege())),
+        avatar: ActiveValue::set(None),
+        create_time: ActiveValue::set(Some(now)),
+        update_time: ActiveValue::set(Some(now)),
+        ..Default::default()
+    };
+    let user_id = UserTb::insert(user).exec(&txn).await?.last_insert_id;
+    IdentityTb::insert(new_identity(account, user_id))
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    Ok(user_id)
+}
+
+/// The provider's username, or failing that a generic one, made unique.
+async fn unused_name(
+    db: &impl sea_orm::ConnectionTrait,
+    account: &ExternalAccount,
+) -> anyhow::Result<String> {
+    let base: String = account
+        .username
+        .clone()
+        .unwrap_or_else(|| format!("{}_user", account.provider))
+        .chars()
+        .take(50)
+        .collect();
+    let mut candidates = vec![base.clone()];
+    candidates.extend((2..10).map(|n| format!("{base}_{n}")));
+    let mut rng = OsRng.unwrap_err();
+    candidates.push(format!("{base}_{}", rng.random_range(100_000..1_000_000)));
+    for name in candidates {
+        if !name_taken(db, &name, None).await? {
+            return Ok(name);
+        }
+    }
+    bail!("用户名已存在")
+}
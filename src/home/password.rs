@@ -0,0 +1,87 @@
+//! Password hashing for `user_tb.password`.
+//!
+//! New and reset passwords are stored as Argon2id PHC strings. Rows written by
+//! older versions of the blog hold an unsalted MD5 hex digest; those are still
+//! accepted by [`verify`] and reported as [`Verification::NeedsRehash`] so the
+//! caller can upgrade them on the next successful login.
+
+use std::sync::LazyLock;
+
+use argon2::{
+    Argon2,
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Invalid,
+    Valid,
+    /// The password matched a legacy hash and should be stored again with [`hash`].
+    NeedsRehash,
+}
+
+impl Verification {
+    pub fn is_valid(self) -> bool {
+        self != Verification::Invalid
+    }
+}
+
+/// Hash `password` into an Argon2id PHC string.
+///
+/// Argon2 is deliberately slow, so the work runs on the blocking thread pool.
+pub async fn hash(password: String) -> anyhow::Result<String> {
+    tokio::task::spawn_blocking(move || hash_blocking(&password)).await?
+}
+
+/// Check `password` against the value stored in `user_tb.password`.
+pub async fn verify(password: String, stored: String) -> anyhow::Result<Verification> {
+    tokio::task::spawn_blocking(move || verify_blocking(&password, &stored)).await?
+}
+
+/// Spend as long as [`verify`] would on a real hash when there is nothing to
+/// check against, so the time a login takes doesn't tell which names exist.
+/// Always [`Verification::Invalid`].
+pub async fn verify_absent(password: String) -> anyhow::Result<Verification> {
+    tokio::task::spawn_blocking(move || {
+        verify_blocking(&password, &DUMMY_HASH)?;
+        Ok(Verification::Invalid)
+    })
+    .await?
+}
+
+/// Hashed with the same parameters as real passwords, so checking it costs the same.
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_blocking("no such user").expect("hashing a constant password"));
+
+fn hash_blocking(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hashed = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("failed to hash password: {e}"))?;
+    Ok(hashed.to_string())
+}
+
+fn verify_blocking(password: &str, stored: &str) -> anyhow::Result<Verification> {
+    if is_legacy_md5(stored) {
+        let digest = format!("{:?}", md5::compute(password));
+        return Ok(if digest.eq_ignore_ascii_case(stored) {
+            Verification::NeedsRehash
+        } else {
+            Verification::Invalid
+        });
+    }
+    let parsed =
+        PasswordHash::new(stored).map_err(|e| anyhow::anyhow!("malformed password hash: {e}"))?;
+    match Argon2::default().verify_password(password.as_bytes(), &parsed) {
+        Ok(()) if parsed.algorithm != argon2::Algorithm::Argon2id.ident() => {
+            Ok(Verification::NeedsRehash)
+        }
+        Ok(()) => Ok(Verification::Valid),
+        Err(argon2::password_hash::Error::Password) => Ok(Verification::Invalid),
+        Err(e) => Err(anyhow::anyhow!("failed to verify password: {e}")),
+    }
+}
+
+fn is_legacy_md5(stored: &str) -> bool {
+    stored.len() == 32 && stored.bytes().all(|b| b.is_ascii_hexdigit())
+}
@@ -0,0 +1,142 @@
+//! Public profile fields: display name, bio, website, location and social links.
+//!
+//! They are edited from `/profile` and shown on the author page at `/user/{id}`.
+//! Every field is optional and stored trimmed, with an empty value kept as
+//! `NULL`. Links must be absolute `http(s)` URLs so nothing else ends up in an
+//! `href`.
+
+use reqwest::Url;
+use sea_orm::ActiveValue;
+use serde_json::{Value, json};
+
+use super::database::user_tb;
+
+const DISPLAY_NAME_MAX: usize = 32;
+const BIO_MAX: usize = 500;
+const LOCATION_MAX: usize = 32;
+const URL_MAX: usize = 255;
+const SOCIAL_LINKS_MAX: usize = 5;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    pub display_name: Option<String>,
+    pub bio: Option<String>,
+    pub website: Option<String>,
+    pub location: Option<String>,
+    pub social_links: Vec<String>,
+}
+
+/// The fields as submitted from `person.html`, `social_links` one per line.
+#[derive(Debug, Default)]
+pub struct ProfileForm {
+    pub display_name: String,
+    pub bio: String,
+    pub website: String,
+    pub location: String,
+    pub social_links: String,
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+fn too_long(value: &Option<String>, max: usize) -> bool {
+    value.as_ref().is_some_and(|v| v.chars().count() > max)
+}
+
+fn is_web_url(value: &str) -> bool {
+    value.len() <= URL_MAX
+        && Url::parse(value)
+            .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some())
+}
+
+impl Profile {
+    pub fn of(user: &user_tb::Model) -> Profile {
+        Profile {
+            display_name: user.display_name.clone(),
+            bio: user.bio.clone(),
+            website: user.website.clone(),
+            location: user.location.clone(),
+            social_links: user
+                .social_links
+                .as_deref()
+                .unwrap_or_default()
+                .lines()
+                .filter_map(non_empty)
+                .collect(),
+        }
+    }
+
+    /// Validate a submitted form, returning the message to show when it is
+    /// not acceptable.
+    pub fn parse(form: &ProfileForm) -> Result<Profile, &'static str> {
+        let profile = Profile {
+            display_name: non_empty(&form.display_name),
+            bio: non_empty(&form.bio),
+            website: non_empty(&form.website),
+            location: non_empty(&form.location),
+            social_links: form.social_links.lines().filter_map(non_empty).collect(),
+        };
+        if too_long(&profile.display_name, DISPLAY_NAME_MAX) {
+            return Err("显示名称不能超过32个字");
+        }
+        if too_long(&profile.bio, BIO_MAX) {
+            return Err("个人简介不能超过500个字");
+        }
+        if too_long(&profile.location, LOCATION_MAX) {
+            return Err("所在地不能超过32个字");
+        }
+        if profile.website.as_deref().is_some_and(|w| !is_web_url(w)) {
+            return Err("个人网站必须是 http 或 https 链接");
+        }
+        if profile.social_links.len() > SOCIAL_LINKS_MAX {
+            return Err("社交链接最多5个");
+        }
+        if !profile.social_links.iter().all(|link| is_web_url(link)) {
+            return Err("社交链接必须是 http 或 https 链接");
+        }
+        Ok(profile)
+    }
+
+    /// Write the fields onto `model`.
+    pub fn apply(self, model: &mut user_tb::ActiveModel) {
+        let links = (!self.social_links.is_empty()).then(|| self.social_links.join("\n"));
+        model.display_name = ActiveValue::set(self.display_name);
+        model.bio = ActiveValue::set(self.bio);
+        model.website = ActiveValue::set(self.website);
+        model.location = ActiveValue::set(self.location);
+        model.social_links = ActiveValue::set(links);
+    }
+
+    /// The fields for the templates, each link with its host as the label.
+    pub fn to_json(&self) -> Value {
+        let links: Vec<Value> = self
+            .social_links
+            .iter()
+            .map(|link| {
+                let label = Url::parse(link)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_owned))
+                    .unwrap_or_else(|| link.clone());
+                json!({"url":link,"label":label})
+            })
+            .collect();
+        json!({
+            "displayName":self.display_name,
+            "bio":self.bio,
+            "website":self.website,
+            "location":self.location,
+            "socialLinks":self.social_links.join("\n"),
+            "links":links,
+        })
+    }
+}
+
+/// The name to show for `user`: their display name, or the user name.
+pub fn display_name(user: &user_tb::Model) -> String {
+    user.display_name
+        .clone()
+        .or_else(|| user.name.clone())
+        .unwrap_or_default()
+}
@@ -0,0 +1,276 @@
+//! Allow-list sanitizing of user-supplied HTML.
+//!
+//! Comments arrive as the HTML editor.md previewed in the browser, next to
+//! their Markdown source, and articles are Markdown that may embed raw HTML.
+//! Both go through [`Sanitizer`] before they are stored, so only the tags,
+//! attributes and URL schemes of the configured [`HtmlPolicy`] survive, and
+//! the HTML rendered from the Markdown goes through it once more, see
+//! [`super::markdown`]. `blog-rust sanitize` runs the rows stored before, or
+//! under a looser policy, through it again.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use ammonia::Builder;
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, PaginatorTrait, QueryFilter, QueryOrder,
+};
+
+use super::database::prelude::*;
+use super::database::{article_tb, comment_tb};
+use super::markdown;
+use crate::config::HtmlPolicy;
+
+/// Rows loaded at a time by [`resanitize`].
+const BATCH: u64 = 100;
+
+/// Elements [`math`](super::math) writes.
+const MATHML_TAGS: [&str; 21] = [
+    "math",
+    "semantics",
+    "annotation",
+    "mrow",
+    "mi",
+    "mn",
+    "mo",
+    "mtext",
+    "mspace",
+    "msub",
+    "msup",
+    "msubsup",
+    "munder",
+    "mover",
+    "munderover",
+    "mfrac",
+    "msqrt",
+    "mroot",
+    "mtable",
+    "mtr",
+    "mtd",
+];
+
+#[derive(Debug, Clone)]
+pub struct Sanitizer {
+    policy: HtmlPolicy,
+}
+
+impl Sanitizer {
+    pub fn new(policy: &HtmlPolicy) -> Sanitizer {
+        Sanitizer {
+            policy: policy.clone(),
+        }
+    }
+
+    fn builder(&self) -> Builder<'_> {
+        let mut builder = Builder::default();
+        // The checkboxes of editor.md task lists, and nothing else one could type into
+        builder
+            .add_tags(["input"])
+            .add_tag_attributes("input", ["checked", "disabled"])
+            .set_tag_attribute_value("input", "type", "checkbox")
+            .add_generic_attributes(["class"])
+            // The targets of heading anchors and footnote references
+            .add_tag_attributes("div", ["id"])
+            .add_tag_attributes("h1", ["id"])
+            .add_tag_attributes("h2", ["id"])
+            .add_tag_attributes("h3", ["id"])
+            .add_tag_attributes("h4", ["id"])
+            .add_tag_attributes("h5", ["id"])
+            .add_tag_attributes("h6", ["id"])
+            // The MathML that `$…$` renders to
+            .add_tags(MATHML_TAGS)
+            .add_tag_attributes("math", ["display"])
+            .add_tag_attributes("annotation", ["encoding"])
+            .add_tag_attributes("mi", ["mathvariant"])
+            .add_tag_attributes("mn", ["mathvariant"])
+            .add_tag_attributes("mtext", ["mathvariant"])
+            .add_tag_attributes(
+                "mo",
+                [
+                    "stretchy",
+                    "fence",
+                    "largeop",
+                    "movablelimits",
+                    "lspace",
+                    "rspace",
+                ],
+            )
+            .add_tag_attributes("mfrac", ["linethickness"])
+            .add_tag_attributes("mover", ["accent"])
+            .add_tag_attributes("munder", ["accentunder"])
+            .add_tag_attributes("mspace", ["width"])
+            .add_tag_attributes("mtable", ["columnalign"])
+            .add_tags(self.policy.extra_tags.iter().map(String::as_str))
+            .rm_tags(self.policy.removed_tags.iter().map(String::as_str))
+            .add_generic_attributes(self.policy.extra_attributes.iter().map(String::as_str))
+            .url_schemes(self.policy.url_schemes.iter().map(String::as_str).collect());
+        builder
+    }
+
+    /// Sanitize an HTML fragment.
+    pub fn clean(&self, html: &str) -> String {
+        self.builder().clean(html).to_string()
+    }
+
+    /// Sanitize the raw HTML embedded in Markdown, leaving the Markdown itself
+    /// alone. Links and images whose URL uses a scheme the policy doesn't
+    /// allow are replaced by their text.
+    pub fn clean_markdown(&self, markdown: &str) -> String {
+        let builder = self.builder();
+        let tags = builder.clone_tags();
+        let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+        let mut html_block: Option<Range<usize>> = None;
+        let mut unsafe_link: Option<(Range<usize>, String)> = None;
+        let parser = Parser::new_ext(markdown, markdown::OPTIONS).into_offset_iter();
+        for (event, range) in parser {
+            if let Some((link, text)) = &mut unsafe_link {
+                match event {
+                    Event::End(TagEnd::Link | TagEnd::Image) if range == *link => {
+                        edits.extend(unsafe_link.take());
+                    }
+                    Event::Text(t) | Event::Code(t) => text.push_str(&escape_markdown(&t)),
+                    _ => {}
+                }
+                continue;
+            }
+            match event {
+                Event::Start(Tag::HtmlBlock) => html_block = Some(range),
+                Event::End(TagEnd::HtmlBlock) => {
+                    if let Some(range) = html_block.take() {
+                        let source = &markdown[range.clone()];
+                        let mut cleaned = builder.clean(source).to_string();
+                        if source.ends_with('\n') && !cleaned.ends_with('\n') {
+                            cleaned.push('\n');
+                        }
+                        edits.push((range, cleaned));
+                    }
+                }
+                Event::InlineHtml(html) => edits.push((range, clean_tag(&builder, &tags, &html))),
+                Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. })
+                    if !self.allows_url(&dest_url) =>
+                {
+                    unsafe_link = Some((range, String::new()))
+                }
+                _ => {}
+            }
+        }
+
+        let mut out = String::with_capacity(markdown.len());
+        let mut at = 0;
+        for (range, replacement) in edits {
+            if range.start < at {
+                continue;
+            }
+            out.push_str(&markdown[at..range.start]);
+            out.push_str(&replacement);
+            at = range.end;
+        }
+        out.push_str(&markdown[at..]);
+        out
+    }
+
+    /// Whether a Markdown link may point at `url`.
+    fn allows_url(&self, url: &str) -> bool {
+        // Browsers ignore whitespace and control characters inside the scheme
+        let url: String = url
+            .chars()
+            .filter(|c| !c.is_ascii_whitespace() && !c.is_control())
+            .collect();
+        let Some((scheme, _)) = url.split_once(':') else {
+            return true;
+        };
+        if scheme.contains(['/', '?', '#']) {
+            return true;
+        }
+        self.policy
+            .url_schemes
+            .iter()
+            .any(|allowed| allowed.eq_ignore_ascii_case(scheme))
+    }
+}
+
+/// Sanitize a single tag of inline HTML. Opening and closing tags come apart
+/// in Markdown, so a closing tag is kept whenever its element is allowed.
+fn clean_tag(builder: &Builder<'_>, tags: &HashSet<&str>, html: &str) -> String {
+    let html = html.trim();
+    if let Some(name) = html.strip_prefix("</") {
+        let name = name.trim_end_matches('>').trim().to_ascii_lowercase();
+        return if tags.contains(name.as_str()) {
+            format!("</{name}>")
+        } else {
+            String::new()
+        };
+    }
+    let cleaned = builder.clean(html).to_string();
+    // A lone opening tag comes back closed, its closing tag follows later on
+    match cleaned.rfind("</") {
+        Some(at) if at > 0 => cleaned[..at].to_owned(),
+        _ => cleaned,
+    }
+}
+
+fn escape_markdown(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c.is_ascii_punctuation() {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// Run every stored article and comment through `sanitizer` again, returning
+/// how many of each changed.
+pub async fn resanitize(
+    db: &DatabaseConnection,
+    sanitizer: &Sanitizer,
+) -> anyhow::Result<(u64, u64)> {
+    let mut articles = 0;
+    let mut pages = ArticleTb::find()
+        .order_by_asc(article_tb::Column::Id)
+        .paginate(db, BATCH);
+    while let Some(batch) = pages.fetch_and_next().await? {
+        for article in batch {
+            let Some(content) = article.content else {
+                continue;
+            };
+            let cleaned = sanitizer.clean_markdown(&content);
+            if cleaned != content {
+                ArticleTb::update_many()
+                    .col_expr(article_tb::Column::Content, Expr::value(cleaned))
+                    .filter(article_tb::Column::Id.eq(article.id))
+                    .exec(db)
+                    .await?;
+                articles += 1;
+            }
+        }
+    }
+
+    let mut comments = 0;
+    let mut pages = CommentTb::find()
+        .order_by_asc(comment_tb::Column::Id)
+        .paginate(db, BATCH);
+    while let Some(batch) = pages.fetch_and_next().await? {
+        for comment in batch {
+            let html = comment.comment.as_deref().map(|c| sanitizer.clean(c));
+            let markdown = comment
+                .md_content
+                .as_deref()
+                .map(|m| sanitizer.clean_markdown(m));
+            if html != comment.comment || markdown != comment.md_content {
+                CommentTb::update_many()
+                    .col_expr(comment_tb::Column::Comment, Expr::value(html))
+                    .col_expr(comment_tb::Column::MdContent, Expr::value(markdown))
+                    .filter(comment_tb::Column::Id.eq(comment.id))
+                    .exec(db)
+                    .await?;
+                comments += 1;
+            }
+        }
+    }
+    tracing::info!("sanitized {articles} articles and {comments} comments");
+    Ok((articles, comments))
+}
@@ -0,0 +1,309 @@
+//! Server-side sessions behind the JWTs handed out at login.
+//!
+//! A token stays valid until its `exp`, so on its own it can't be taken back.
+//! Every token therefore carries a `jti` naming a row in `session_tb` and the
+//! `token_version` its user had when it was issued. [`SessionGuard`] runs after
+//! `JwtAuth` and treats the request as anonymous when the session has been
+//! revoked or the version has moved on, which is how logging out, signing out
+//! everywhere and resetting the password take effect immediately.
+//!
+//! Sessions also record the address and browser they were started from and
+//! when they were last used, so users can review them on `/profile/sessions`.
+
+use anyhow::Context;
+use chrono::{Duration, Local};
+use jsonwebtoken::{EncodingKey, TokenData};
+use rand::rngs::OsRng;
+use rand::{RngCore, TryRngCore};
+use salvo::jwt_auth::{JWT_AUTH_DATA_KEY, JWT_AUTH_STATE_KEY};
+use salvo::prelude::*;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QueryTrait,
+};
+
+use super::access::Standing;
+use super::database::prelude::*;
+use super::database::{session_tb, user_tb};
+use super::throttle::client_ip;
+use super::{AppState, JwtClaims};
+
+/// `last_seen_time` is only written when it is older than this, so browsing
+/// doesn't turn every page view into a database write.
+const TOUCH_INTERVAL: Duration = Duration::minutes(1);
+
+/// Where a session was started from.
+pub struct Origin {
+    pub ip: String,
+    pub user_agent: String,
+}
+
+impl Origin {
+    pub fn from_request(req: &Request, behind_proxy: bool) -> Origin {
+        let user_agent = req
+            .header::<String>("user-agent")
+            .unwrap_or_default()
+            .chars()
+            .take(255)
+            .collect();
+        Origin {
+            ip: client_ip(req, behind_proxy),
+            user_agent,
+        }
+    }
+}
+
+/// Start a session for `user_id` and return the token that identifies it.
+///
+/// `token_version` is the user's current `user_tb.token_version`. Banned
+/// accounts are refused here, so every way of signing in is covered.
+pub async fn issue(
+    db: &DatabaseConnection,
+    secret_key: &str,
+    user_id: i32,
+    token_version: i32,
+    remember: bool,
+    origin: &Origin,
+) -> anyhow::Result<String> {
+    let user = UserTb::find_by_id(user_id)
+        .one(db)
+        .await?
+        .context("用户不存在")?;
+    if let banned @ Standing::Banned { .. } = Standing::of(&user) {
+        anyhow::bail!(banned.notice().unwrap_or_default());
+    }
+    let issued = Local::now();
+    let expires = issued + Duration::days(if remember { 30 } else { 1 });
+    let (now, expire_time) = (issued.naive_local(), expires.naive_local());
+    // Sessions that ran out are of no use to anyone, drop them while we are here
+    SessionTb::delete_many()
+        .filter(session_tb::Column::UserId.eq(user_id))
+        .filter(session_tb::Column::ExpireTime.lt(now))
+        .exec(db)
+        .await?;
+    let jti = new_jti();
+    SessionTb::insert(session_tb::ActiveModel {
+        user_id: ActiveValue::set(user_id),
+        jti: ActiveValue::set(jti.clone()),
+        create_time: ActiveValue::set(now),
+        expire_time: ActiveValue::set(expire_time),
+        revoke_time: ActiveValue::set(None),
+        user_agent: ActiveValue::set(Some(origin.user_agent.clone())),
+        ip: ActiveValue::set(Some(origin.ip.clone())),
+        last_seen_time: ActiveValue::set(Some(now)),
+        ..Default::default()
+    })
+    .exec(db)
+    .await?;
+    let claims = JwtClaims {
+        user_id: user_id.to_string(),
+        exp: expires.timestamp(),
+        jti,
+        ver: token_version,
+    };
+    Ok(jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret_key.as_bytes()),
+    )?)
+}
+
+/// The session named by `claims`, if it may still be used.
+pub async fn find_active(
+    db: &DatabaseConnection,
+    claims: &JwtClaims,
+) -> anyhow::Result<Option<session_tb::Model>> {
+    // Tokens issued before sessions existed have no `jti` and are not accepted
+    let Ok(user_id) = claims.user_id.parse::<i32>() else {
+        return Ok(None);
+    };
+    if claims.jti.is_empty() {
+        return Ok(None);
+    }
+    let session = SessionTb::find()
+        .filter(session_tb::Column::Jti.eq(claims.jti.as_str()))
+        .filter(session_tb::Column::UserId.eq(user_id))
+        .filter(session_tb::Column::RevokeTime.is_null())
+        .one(db)
+        .await?;
+    let Some(session) = session else {
+        return Ok(None);
+    };
+    if session.expire_time <= Local::now().naive_local() {
+        return Ok(None);
+    }
+    let user = UserTb::find_by_id(user_id).one(db).await?;
+    let revoked = user.is_none_or(|user| {
+        user.token_version != claims.ver || matches!(Standing::of(&user), Standing::Banned { .. })
+    });
+    if revoked {
+        return Ok(None);
+    }
+    Ok(Some(session))
+}
+
+/// Record that `session` was just used.
+async fn touch(db: &DatabaseConnection, session: &session_tb::Model) -> anyhow::Result<()> {
+    let now = Local::now().naive_local();
+    if session
+        .last_seen_time
+        .is_some_and(|seen| now - seen < TOUCH_INTERVAL)
+    {
+        return Ok(());
+    }
+    SessionTb::update_many()
+        .col_expr(session_tb::Column::LastSeenTime, Expr::value(now))
+        .filter(session_tb::Column::Id.eq(session.id))
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// The sessions of `user_id` that are neither revoked nor expired, most
+/// recently used first.
+pub async fn list_active(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> anyhow::Result<Vec<session_tb::Model>> {
+    Ok(SessionTb::find()
+        .filter(session_tb::Column::UserId.eq(user_id))
+        .filter(session_tb::Column::RevokeTime.is_null())
+        .filter(session_tb::Column::ExpireTime.gt(Local::now().naive_local()))
+        .order_by_desc(session_tb::Column::LastSeenTime)
+        .order_by_desc(session_tb::Column::Id)
+        .all(db)
+        .await?)
+}
+
+/// End the session named by `jti`.
+pub async fn revoke(db: &DatabaseConnection, jti: &str) -> anyhow::Result<()> {
+    SessionTb::update_many()
+        .col_expr(
+            session_tb::Column::RevokeTime,
+            Expr::value(Local::now().naive_local()),
+        )
+        .filter(session_tb::Column::Jti.eq(jti))
+        .filter(session_tb::Column::RevokeTime.is_null())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// End the session with row id `id`, provided it belongs to `user_id` and,
+/// when `jti` is given, is the session of that token.
+///
+/// Returns whether there was such a session to end.
+pub async fn revoke_by_id(
+    db: &DatabaseConnection,
+    user_id: i32,
+    id: i32,
+    jti: Option<&str>,
+) -> anyhow::Result<bool> {
+    let result = SessionTb::update_many()
+        .col_expr(
+            session_tb::Column::RevokeTime,
+            Expr::value(Local::now().naive_local()),
+        )
+        .filter(session_tb::Column::Id.eq(id))
+        .filter(session_tb::Column::UserId.eq(user_id))
+        .filter(session_tb::Column::RevokeTime.is_null())
+        .apply_if(jti, |query, jti| {
+            query.filter(session_tb::Column::Jti.eq(jti))
+        })
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// End every session of `user_id` except the one named by `keep_jti`, so the
+/// device making a change stays signed in.
+pub async fn revoke_others(
+    db: &DatabaseConnection,
+    user_id: i32,
+    keep_jti: &str,
+) -> anyhow::Result<()> {
+    SessionTb::update_many()
+        .col_expr(
+            session_tb::Column::RevokeTime,
+            Expr::value(Local::now().naive_local()),
+        )
+        .filter(session_tb::Column::UserId.eq(user_id))
+        .filter(session_tb::Column::Jti.ne(keep_jti))
+        .filter(session_tb::Column::RevokeTime.is_null())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// End every session of `user_id`, including tokens that are still in flight.
+pub async fn revoke_all(db: &DatabaseConnection, user_id: i32) -> anyhow::Result<()> {
+    UserTb::update_many()
+        .col_expr(
+            user_tb::Column::TokenVersion,
+            Expr::col(user_tb::Column::TokenVersion).add(1),
+        )
+        .filter(user_tb::Column::Id.eq(user_id))
+        .exec(db)
+        .await?;
+    SessionTb::update_many()
+        .col_expr(
+            session_tb::Column::RevokeTime,
+            Expr::value(Local::now().naive_local()),
+        )
+        .filter(session_tb::Column::UserId.eq(user_id))
+        .filter(session_tb::Column::RevokeTime.is_null())
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// 128 bits from the operating system's CSPRNG, hex encoded.
+fn new_jti() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.unwrap_err().fill_bytes(&mut bytes);
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Hoop placed right after `JwtAuth` that downgrades requests carrying a
+/// token whose session is no longer active, as if the token were invalid, so
+/// `AuthorGuardByMethod` turns them away. Active sessions are marked as seen.
+pub struct SessionGuard;
+
+#[async_trait]
+impl Handler for SessionGuard {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        depot: &mut Depot,
+        _res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        if depot.jwt_auth_state() != JwtAuthState::Authorized {
+            return;
+        }
+        let (Ok(state), Some(data)) = (
+            depot.obtain::<AppState>(),
+            depot.jwt_auth_data::<JwtClaims>(),
+        ) else {
+            return;
+        };
+        let active = match find_active(state.db(), &data.claims).await {
+            Ok(Some(session)) => {
+                if let Err(e) = touch(state.db(), &session).await {
+                    tracing::warn!("failed to record session activity: {e}");
+                }
+                true
+            }
+            Ok(None) => false,
+            Err(e) => {
+                // Fail closed, a token we can't check is not trusted
+                tracing::error!("failed to check session: {e}");
+                false
+            }
+        };
+        if !active {
+            depot.insert(JWT_AUTH_STATE_KEY, JwtAuthState::Forbidden);
+            let _ = depot.remove::<TokenData<JwtClaims>>(JWT_AUTH_DATA_KEY);
+        }
+    }
+}
@@ -0,0 +1,164 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use salvo::prelude::*;
+use sea_orm::DatabaseConnection;
+use tera::Tera;
+
+use super::code_store::CodeStore;
+use super::mailer::Mailer;
+use super::markdown::Markdown;
+use super::oauth::OAuth;
+use super::sanitize::Sanitizer;
+use super::throttle::Throttle;
+use crate::config::{AppConfig, RateLimits};
+
+/// Application-lifetime resources shared by every handler.
+///
+/// Cloning is cheap; the resources live behind an `Arc`. The state reaches the
+/// handlers through the depot, see [`InjectState`].
+#[derive(Clone)]
+pub struct AppState {
+    inner: Arc<StateInner>,
+}
+
+struct StateInner {
+    db: DatabaseConnection,
+    tera: Tera,
+    base_url: String,
+    secret_key: String,
+    codes: Arc<dyn CodeStore>,
+    mailer: Arc<dyn Mailer>,
+    oauth: OAuth,
+    sanitizer: Sanitizer,
+    markdown: Markdown,
+    throttle: Throttle,
+    rate_limits: RateLimits,
+    behind_proxy: bool,
+    code_length: usize,
+    code_ttl: Duration,
+    code_max_attempts: u32,
+    totp_issuer: String,
+    highlight_theme: String,
+}
+
+impl AppState {
+    pub fn new(
+        config: &AppConfig,
+        db: DatabaseConnection,
+        codes: Arc<dyn CodeStore>,
+        mailer: Arc<dyn Mailer>,
+        oauth: OAuth,
+        tera: Tera,
+    ) -> Self {
+        AppState {
+            inner: Arc::new(StateInner {
+                db,
+                tera,
+                base_url: config.base_url.clone(),
+                secret_key: config.secret_key.clone(),
+                codes,
+                mailer,
+                oauth,
+                sanitizer: Sanitizer::new(&config.html_policy),
+                markdown: Markdown::new(Sanitizer::new(&config.html_policy)),
+                throttle: Throttle::default(),
+                rate_limits: config.rate_limits.clone(),
+                behind_proxy: config.behind_proxy,
+                code_length: config.code_length,
+                code_ttl: Duration::from_secs(config.code_ttl_secs),
+                code_max_attempts: config.code_max_attempts,
+                totp_issuer: config.totp_issuer.clone(),
+                highlight_theme: config.highlight_theme.clone(),
+            }),
+        }
+    }
+
+    pub fn db(&self) -> &DatabaseConnection {
+        &self.inner.db
+    }
+
+    pub fn tera(&self) -> &Tera {
+        &self.inner.tera
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.inner.base_url
+    }
+
+    pub fn secret_key(&self) -> &str {
+        &self.inner.secret_key
+    }
+
+    /// Where email verification codes are kept between `sendcode` and their use.
+    pub fn codes(&self) -> &dyn CodeStore {
+        self.inner.codes.as_ref()
+    }
+
+    pub fn mailer(&self) -> &dyn Mailer {
+        self.inner.mailer.as_ref()
+    }
+
+    pub fn oauth(&self) -> &OAuth {
+        &self.inner.oauth
+    }
+
+    /// The allow-list for comment and article HTML.
+    pub fn sanitizer(&self) -> &Sanitizer {
+        &self.inner.sanitizer
+    }
+
+    /// Renders article and comment Markdown, caching the HTML.
+    pub fn markdown(&self) -> &Markdown {
+        &self.inner.markdown
+    }
+
+    pub fn throttle(&self) -> &Throttle {
+        &self.inner.throttle
+    }
+
+    pub fn rate_limits(&self) -> &RateLimits {
+        &self.inner.rate_limits
+    }
+
+    pub fn behind_proxy(&self) -> bool {
+        self.inner.behind_proxy
+    }
+
+    pub fn code_length(&self) -> usize {
+        self.inner.code_length
+    }
+
+    pub fn code_ttl(&self) -> Duration {
+        self.inner.code_ttl
+    }
+
+    pub fn code_max_attempts(&self) -> u32 {
+        self.inner.code_max_attempts
+    }
+
+    pub fn totp_issuer(&self) -> &str {
+        &self.inner.totp_issuer
+    }
+
+    /// File stem of the stylesheet for highlighted code.
+    pub fn highlight_theme(&self) -> &str {
+        &self.inner.highlight_theme
+    }
+}
+
+/// Hoop that makes an [`AppState`] available to the rest of the request via `depot.obtain`.
+pub struct InjectState(pub AppState);
+
+#[async_trait]
+impl Handler for InjectState {
+    async fn handle(
+        &self,
+        _req: &mut Request,
+        depot: &mut Depot,
+        _res: &mut Response,
+        _ctrl: &mut FlowCtrl,
+    ) {
+        depot.inject(self.0.clone());
+    }
+}
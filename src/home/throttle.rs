@@ -0,0 +1,106 @@
+//! Rate limiting for the account endpoints.
+//!
+//! Each limited action is counted under one or more keys, such as the client
+//! IP and the account or email it targets, in fixed windows kept in this
+//! process. Counters are not shared between nodes, so the effective limit of
+//! a deployment is the configured one times the number of nodes.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use salvo::Request;
+
+use crate::config::Quota;
+
+/// Only sweep expired windows once the map grows past this many keys.
+const SWEEP_THRESHOLD: usize = 4096;
+
+#[derive(Default)]
+pub struct Throttle {
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+struct Window {
+    started: Instant,
+    window: Duration,
+    hits: u32,
+}
+
+impl Throttle {
+    /// Count one hit against every `(key, quota)` pair.
+    ///
+    /// Returns how long the caller has to wait if any of the keys has already
+    /// used up its quota. A blocked request is not counted, so a client that
+    /// backs off is let through as soon as the window ends.
+    pub fn hit(&self, checks: &[(String, Quota)]) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        if windows.len() > SWEEP_THRESHOLD {
+            windows.retain(|_, w| now < w.started + w.window);
+        }
+        let mut wait = Duration::ZERO;
+        for (key, quota) in checks.iter().filter(|(_, quota)| quota.max > 0) {
+            if let Some(w) = windows.get(key)
+                && now < w.started + w.window
+                && w.hits >= quota.max
+            {
+                wait = wait.max(w.started + w.window - now);
+            }
+        }
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+        for (key, quota) in checks.iter().filter(|(_, quota)| quota.max > 0) {
+            let window = Duration::from_secs(quota.window_secs);
+            let w = windows.entry(key.clone()).or_insert(Window {
+                started: now,
+                window,
+                hits: 0,
+            });
+            if now >= w.started + w.window {
+                *w = Window {
+                    started: now,
+                    window,
+                    hits: 0,
+                };
+            }
+            w.hits += 1;
+        }
+        Ok(())
+    }
+
+    /// Forget the hits counted under `key`, e.g. after a successful login.
+    pub fn reset(&self, key: &str) {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        windows.remove(key);
+    }
+}
+
+/// The address rate limits are keyed on.
+///
+/// `X-Forwarded-For` and `X-Real-IP` are only believed when the blog is
+/// configured to sit behind a reverse proxy, since anyone can send them. Even
+/// then only the last `X-Forwarded-For` entry counts: that is the one our
+/// proxy appended, while everything before it came from the client.
+pub fn client_ip(req: &Request, behind_proxy: bool) -> String {
+    if behind_proxy {
+        let forwarded = req
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .next_back()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .map(|ip| ip.trim().to_owned())
+            .or_else(|| req.header::<String>("x-real-ip"))
+            .filter(|ip| !ip.is_empty());
+        if let Some(ip) = forwarded {
+            return ip;
+        }
+    }
+    req.remote_addr()
+        .ip()
+        .map(|ip| ip.to_string())
+        .unwrap_or_else(|| String::from("unknown"))
+}
@@ -0,0 +1,342 @@
+//! Optional TOTP (RFC 6238) second factor.
+//!
+//! Enrollment happens on `/profile/totp`: a secret is generated and shown as a
+//! QR code, and the second factor is only switched on once the user has typed
+//! in a code from their authenticator, at which point they are given
+//! single-use recovery codes. Enrolled users log in in two steps: the password
+//! step hands out a short-lived ticket instead of a token, and `login/totp`
+//! trades that ticket and a code for the token.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use chrono::Local;
+use qrcode::QrCode;
+use qrcode::render::svg;
+use rand::rngs::OsRng;
+use rand::{Rng, RngCore, TryRngCore};
+use sea_orm::sea_query::{Condition, Expr};
+use sea_orm::{
+    ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, PaginatorTrait,
+    QueryFilter, TransactionTrait,
+};
+use sha2::{Digest, Sha256};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use super::code_store::CodeStore;
+use super::database::prelude::*;
+use super::database::{recovery_code_tb, user_tb};
+use super::verification::constant_time_eq;
+
+/// 160 bits, the key length RFC 4226 recommends for HMAC-SHA1
+const SECRET_LEN: usize = 20;
+const DIGITS: usize = 6;
+const STEP_SECS: u64 = 30;
+/// Codes from one step either side of now are accepted, for clock drift
+const SKEW_STEPS: u64 = 1;
+const RECOVERY_CODES: usize = 10;
+/// Unambiguous characters only, so codes can be read off paper
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+/// How long the user has to enter a code after their password
+const TICKET_TTL: Duration = Duration::from_secs(5 * 60);
+
+/// What an authenticator app needs to start producing codes.
+pub struct Provisioning {
+    /// Base32, for typing in by hand
+    pub secret: String,
+    pub url: String,
+    pub qr_svg: String,
+}
+
+fn totp(secret: &str, issuer: Option<&str>, account: &str) -> anyhow::Result<TOTP> {
+    let bytes = Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("invalid TOTP secret: {e:?}"))?;
+    // `:` separates issuer and account in the otpauth label
+    Ok(TOTP::new_unchecked(
+        Algorithm::SHA1,
+        DIGITS,
+        SKEW_STEPS as u8,
+        STEP_SECS,
+        bytes,
+        issuer.map(|i| i.replace(':', "_")),
+        account.replace(':', "_"),
+    ))
+}
+
+fn current_step() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+        / STEP_SECS
+}
+
+/// A fresh base32 secret from the operating system's CSPRNG.
+pub fn new_secret() -> String {
+    let mut bytes = vec![0u8; SECRET_LEN];
+    OsRng.unwrap_err().fill_bytes(&mut bytes);
+    match Secret::Raw(bytes).to_encoded() {
+        Secret::Encoded(secret) => secret,
+        Secret::Raw(_) => unreachable!("to_encoded always returns an encoded secret"),
+    }
+}
+
+pub fn provisioning(secret: &str, issuer: &str, account: &str) -> anyhow::Result<Provisioning> {
+    let url = totp(secret, Some(issuer), account)?.get_url();
+    let qr_svg = QrCode::new(url.as_bytes())?
+        .render::<svg::Color>()
+        .min_dimensions(200, 200)
+        .build();
+    Ok(Provisioning {
+        secret: secret.to_owned(),
+        url,
+        qr_svg,
+    })
+}
+
+/// The time step `code` belongs to, if it is valid around now and newer than
+/// `last_step`, the step of the last code that was accepted.
+fn matching_step(secret: &str, code: &str, last_step: Option<i64>) -> anyhow::Result<Option<i64>> {
+    let code = code.trim();
+    if code.len() != DIGITS {
+        return Ok(None);
+    }
+    let totp = totp(secret, None, "")?;
+    let now = current_step();
+    for step in now.saturating_sub(SKEW_STEPS)..=now + SKEW_STEPS {
+        let step_i64 = i64::try_from(step)?;
+        if last_step.is_some_and(|last| step_i64 <= last) {
+            continue;
+        }
+        if constant_time_eq(totp.generate(step * STEP_SECS).as_bytes(), code.as_bytes()) {
+            return Ok(Some(step_i64));
+        }
+    }
+    Ok(None)
+}
+
+/// Remember that the code for `step` was used, unless a concurrent request
+/// got there first, in which case the code is treated as already spent.
+async fn claim_step(db: &impl ConnectionTrait, user_id: i32, step: i64) -> anyhow::Result<bool> {
+    let result = UserTb::update_many()
+        .col_expr(user_tb::Column::TotpLastStep, Expr::value(step))
+        .filter(user_tb::Column::Id.eq(user_id))
+        .filter(
+            Condition::any()
+                .add(user_tb::Column::TotpLastStep.is_null())
+                .add(user_tb::Column::TotpLastStep.lt(step)),
+        )
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// Store a new, not yet enabled secret for `user_id`, replacing any earlier
+/// enrollment that was never confirmed.
+pub async fn begin_enrollment(db: &DatabaseConnection, user_id: i32) -> anyhow::Result<String> {
+    let secret = new_secret();
+    UserTb::update_many()
+        .col_expr(user_tb::Column::TotpSecret, Expr::value(secret.clone()))
+        .col_expr(user_tb::Column::TotpLastStep, Expr::value(None::<i64>))
+        .filter(user_tb::Column::Id.eq(user_id))
+        .filter(user_tb::Column::TotpEnabled.eq(false))
+        .exec(db)
+        .await?;
+    Ok(secret)
+}
+
+/// Enable the second factor if `code` matches the pending secret, returning
+/// the recovery codes to show the user once.
+pub async fn confirm_enrollment(
+    db: &DatabaseConnection,
+    user: &user_tb::Model,
+    code: &str,
+) -> anyhow::Result<Option<Vec<String>>> {
+    let Some(secret) = user.totp_secret.as_deref() else {
+        return Ok(None);
+    };
+    let Some(step) = matching_step(secret, code, None)? else {
+        return Ok(None);
+    };
+    let txn = db.begin().await?;
+    UserTb::update_many()
+        .col_expr(user_tb::Column::TotpEnabled, Expr::value(true))
+        .col_expr(user_tb::Column::TotpLastStep, Expr::value(step))
+        .filter(user_tb::Column::Id.eq(user.id))
+        .exec(&txn)
+        .await?;
+    let codes = replace_recovery_codes(&txn, user.id).await?;
+    txn.commit().await?;
+    Ok(Some(codes))
+}
+
+/// Switch the second factor off and forget the secret and recovery codes.
+pub async fn disable(db: &DatabaseConnection, user_id: i32) -> anyhow::Result<()> {
+    let txn = db.begin().await?;
+    UserTb::update_many()
+        .col_expr(user_tb::Column::TotpEnabled, Expr::value(false))
+        .col_expr(user_tb::Column::TotpSecret, Expr::value(None::<String>))
+        .col_expr(user_tb::Column::TotpLastStep, Expr::value(None::<i64>))
+        .filter(user_tb::Column::Id.eq(user_id))
+        .exec(&txn)
+        .await?;
+    RecoveryCodeTb::delete_many()
+        .filter(recovery_code_tb::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+/// Whether `code`, either from the authenticator or a recovery code, proves
+/// the second factor of `user`. Both kinds only work once.
+pub async fn verify(
+    db: &DatabaseConnection,
+    user: &user_tb::Model,
+    code: &str,
+) -> anyhow::Result<bool> {
+    let (true, Some(secret)) = (user.totp_enabled, user.totp_secret.as_deref()) else {
+        return Ok(false);
+    };
+    if let Some(step) = matching_step(secret, code, user.totp_last_step)? {
+        return claim_step(db, user.id, step).await;
+    }
+    use_recovery_code(db, user.id, code).await
+}
+
+fn new_recovery_code() -> String {
+    let mut rng = OsRng.unwrap_err();
+    let mut code: String = (0..10)
+        .map(|_| char::from(RECOVERY_ALPHABET[rng.random_range(0..RECOVERY_ALPHABET.len())]))
+        .collect();
+    code.insert(5, '-');
+    code
+}
+
+/// Recovery codes are random enough that a fast hash is all they need.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    Sha256::digest(normalized.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Throw away the recovery codes of `user_id` and return a fresh set.
+pub async fn replace_recovery_codes(
+    db: &impl ConnectionTrait,
+    user_id: i32,
+) -> anyhow::Result<Vec<String>> {
+    RecoveryCodeTb::delete_many()
+        .filter(recovery_code_tb::Column::UserId.eq(user_id))
+        .exec(db)
+        .await?;
+    let now = Local::now().naive_local();
+    let codes: Vec<String> = (0..RECOVERY_CODES).map(|_| new_recovery_code()).collect();
+    RecoveryCodeTb::insert_many(codes.iter().map(|code| recovery_code_tb::ActiveModel {
+        user_id: ActiveValue::set(user_id),
+        code_hash: ActiveValue::set(hash_recovery_code(code)),
+        create_time: ActiveValue::set(now),
+        use_time: ActiveValue::set(None),
+        ..Default::default()
+    }))
+    .exec(db)
+    .await?;
+    Ok(codes)
+}
+
+/// How many recovery codes of `user_id` have not been used yet.
+pub async fn remaining_recovery_codes(
+    db: &DatabaseConnection,
+    user_id: i32,
+) -> anyhow::Result<u64> {
+    Ok(RecoveryCodeTb::find()
+        .filter(recovery_code_tb::Column::UserId.eq(user_id))
+        .filter(recovery_code_tb::Column::UseTime.is_null())
+        .count(db)
+        .await?)
+}
+
+async fn use_recovery_code(
+    db: &DatabaseConnection,
+    user_id: i32,
+    code: &str,
+) -> anyhow::Result<bool> {
+    let result = RecoveryCodeTb::update_many()
+        .col_expr(
+            recovery_code_tb::Column::UseTime,
+            Expr::value(Local::now().naive_local()),
+        )
+        .filter(recovery_code_tb::Column::UserId.eq(user_id))
+        .filter(recovery_code_tb::Column::CodeHash.eq(hash_recovery_code(code)))
+        .filter(recovery_code_tb::Column::UseTime.is_null())
+        .exec(db)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// A password that has been checked, waiting for the second factor.
+pub struct Ticket {
+    pub user_id: i32,
+    pub remember: bool,
+}
+
+fn ticket_key(ticket: &str) -> String {
+    format!("login2fa:{ticket}")
+}
+
+/// Hand out the ticket the password step answers with.
+pub async fn issue_ticket(
+    codes: &dyn CodeStore,
+    user_id: i32,
+    remember: bool,
+) -> anyhow::Result<String> {
+    let mut bytes = [0u8; 16];
+    OsRng.unwrap_err().fill_bytes(&mut bytes);
+    let ticket: String = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    codes
+        .put(
+            &ticket_key(&ticket),
+            &format!("{user_id}:{remember}"),
+            TICKET_TTL,
+        )
+        .await?;
+    Ok(ticket)
+}
+
+/// The login `ticket` stands for, unless it expired or was used up.
+pub async fn find_ticket(codes: &dyn CodeStore, ticket: &str) -> anyhow::Result<Option<Ticket>> {
+    let Some(value) = codes.get(&ticket_key(ticket)).await? else {
+        return Ok(None);
+    };
+    let Some((user_id, remember)) = value.split_once(':') else {
+        return Ok(None);
+    };
+    Ok(Some(Ticket {
+        user_id: user_id.parse()?,
+        remember: remember == "true",
+    }))
+}
+
+/// Count a wrong code against `ticket`, and throw the ticket away once it
+/// has had `max_attempts` of them. Returns whether it was thrown away.
+pub async fn fail_ticket(
+    codes: &dyn CodeStore,
+    ticket: &str,
+    max_attempts: u32,
+) -> anyhow::Result<bool> {
+    let key = ticket_key(ticket);
+    if codes.fail(&key).await? >= max_attempts {
+        codes.remove(&key).await?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+pub async fn consume_ticket(codes: &dyn CodeStore, ticket: &str) -> anyhow::Result<()> {
+    codes.remove(&ticket_key(ticket)).await
+}
@@ -0,0 +1,113 @@
+//! One-time email verification codes.
+//!
+//! A code is drawn digit by digit from the operating system's CSPRNG, so every
+//! digit is equally likely, and it is stored under its [`Purpose`] and email:
+//! a code sent to register an account can't be used to reset a password.
+//! Codes expire after `code_ttl_secs` and are removed by the check that accepts them.
+
+use std::time::Duration;
+
+use rand::rngs::OsRng;
+use rand::{Rng, TryRngCore};
+
+use super::code_store::{CodeStore, Take};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    Register,
+    ResetPassword,
+    /// Confirm the new address of a signed in user
+    ChangeEmail,
+}
+
+impl Purpose {
+    /// Parse the `purpose` field sent with `sendcode`. Pages that predate the
+    /// field only ever asked for registration codes.
+    pub fn from_form(value: Option<&str>) -> Option<Purpose> {
+        match value.map(str::trim) {
+            None | Some("") | Some("register") => Some(Purpose::Register),
+            Some("reset") => Some(Purpose::ResetPassword),
+            Some(_) => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Purpose::Register => "register",
+            Purpose::ResetPassword => "reset",
+            Purpose::ChangeEmail => "email",
+        }
+    }
+
+    pub fn email_subject(self) -> &'static str {
+        match self {
+            Purpose::Register => "你好，邮箱验证码",
+            Purpose::ResetPassword => "重置密码验证码",
+            Purpose::ChangeEmail => "修改邮箱验证码",
+        }
+    }
+}
+
+/// The outcome of comparing a submitted code with the stored one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Accepted,
+    /// No code was sent for this purpose and email, it expired or was used up
+    Missing,
+    Wrong,
+    /// The guess was wrong and the code has now been thrown away
+    Exhausted,
+}
+
+fn key(purpose: Purpose, email: &str) -> String {
+    format!("code:{}:{}", purpose.as_str(), email.trim().to_lowercase())
+}
+
+/// A code of `len` uniformly distributed decimal digits.
+pub fn generate(len: usize) -> String {
+    let mut rng = OsRng.unwrap_err();
+    (0..len)
+        .map(|_| char::from(b'0' + rng.random_range(0..10u8)))
+        .collect()
+}
+
+/// Create a code for `purpose` and `email`, replacing any earlier one.
+pub async fn issue(
+    codes: &dyn CodeStore,
+    purpose: Purpose,
+    email: &str,
+    len: usize,
+    ttl: Duration,
+) -> anyhow::Result<String> {
+    let code = generate(len);
+    codes.put(&key(purpose, email), &code, ttl).await?;
+    Ok(code)
+}
+
+/// Compare `guess` with the code issued for `purpose` and `email`, using it
+/// up when it matches.
+///
+/// Wrong guesses are counted, and after `max_attempts` of them the code is
+/// removed so the remaining possibilities can't be tried.
+pub async fn check(
+    codes: &dyn CodeStore,
+    purpose: Purpose,
+    email: &str,
+    guess: &str,
+    max_attempts: u32,
+) -> anyhow::Result<Check> {
+    let key = key(purpose, email);
+    match codes.take(&key, guess.trim()).await? {
+        Take::Taken => Ok(Check::Accepted),
+        Take::Missing => Ok(Check::Missing),
+        Take::Mismatch if codes.fail(&key).await? >= max_attempts => {
+            codes.remove(&key).await?;
+            Ok(Check::Exhausted)
+        }
+        Take::Mismatch => Ok(Check::Wrong),
+    }
+}
+
+pub(super) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -1,6 +1,10 @@
 use salvo::{catcher::Catcher, prelude::*};
 use sea_orm::{ConnectOptions, Database};
+mod config;
 mod home;
+mod migration;
+#[cfg(test)]
+mod tests;
 
 use salvo::serve_static::StaticDir;
 
@@ -11,9 +15,13 @@ use tera::{Context, Tera};
 
 use salvo::jwt_auth::{ConstDecoder, CookieFinder};
 //use salvo::logging::Logger;
-// use salvo::rate_limiter::{BasicQuota, FixedGuard, MokaStore, RateLimiter, RemoteIpIssuer};
 
-use home::{JwtClaims, UniformError};
+use config::{AppConfig, Cli, Command, MigrateAction};
+use home::access::{Permission, Require};
+use home::admin;
+use home::{AppState, InjectState, JwtClaims, UniformError};
+use migration::Migrator;
+use sea_orm_migration::MigratorTrait;
 use tracing::log;
 
 #[derive(Clone)]
@@ -34,7 +42,7 @@ impl AuthorGuardByMethod {
                 Ok(())
             }
             JwtAuthState::Unauthorized | JwtAuthState::Forbidden => {
-                Self::render_unauthorized_response(req, depot, res)?;
+                home::access::deny(req, depot, res);
                 ctrl.skip_rest();
                 Ok(())
             }
@@ -42,57 +50,24 @@ impl AuthorGuardByMethod {
     }
 }
 
-impl AuthorGuardByMethod {
-    fn render_unauthorized_response(
-        req: &Request,
-        depot: &Depot,
-        res: &mut Response,
-    ) -> Result<(), UniformError> {
-        let base_url = depot
-            .get::<String>("base_url")
-            .map_err(|_| anyhow::anyhow!("failed to acquire base url"))?;
-
-        if req.method() == salvo::http::Method::GET {
-            let tera = depot
-                .get::<Tera>("tera")
-                .map_err(|_| anyhow::anyhow!("failed to acquire tera engine"))?;
-            let mut context = Context::new();
-            context.insert("code", &404);
-            context.insert("msg", "没有权限执行此操作");
-            context.insert("baseUrl", &base_url);
-            let r = tera
-                .render("404.html", &context)
-                .unwrap_or(String::from("error"));
-            res.render(Text::Html(r));
-        } else if req.method() == salvo::http::Method::POST {
-            let r = json!({
-                "code":400,
-                "msg":"没有权限执行此操作",
-                "baseUrl":base_url,
-                "success":0,
-                "message":"没有权限执行此操作",
-            });
-            res.render(Text::Json(r.to_string()));
-        }
-        Ok(())
-    }
-}
-
-struct Handle404(String, Tera);
+struct Handle404;
 #[async_trait]
 impl Handler for Handle404 {
     async fn handle(
         &self,
         req: &mut Request,
-        _depot: &mut Depot,
+        depot: &mut Depot,
         res: &mut Response,
         ctrl: &mut FlowCtrl,
     ) {
+        let Ok(state) = depot.obtain::<AppState>() else {
+            return;
+        };
         let http_method = req.method();
         if http_method == salvo::http::Method::GET {
             // response html
-            let base_url = &self.0;
-            let tera = &self.1;
+            let base_url = state.base_url();
+            let tera = state.tera();
             let mut context = Context::new();
             context.insert("code", &404);
             context.insert("msg", "访问的资源不存在");
@@ -102,7 +77,7 @@ impl Handler for Handle404 {
                 .unwrap_or(String::from("error"));
             res.render(Text::Html(r));
         } else if http_method == salvo::http::Method::POST {
-            let base_url = &self.0;
+            let base_url = state.base_url();
             let r = json!({
                 "code":400,
                 "msg":"访问的资源不存在",
@@ -130,6 +105,51 @@ impl tera::Filter for IsNullFilter {
         }
     }
 }
+
+/// Replacement for tera's builtin `date` filter.
+///
+/// The builtin only understands RFC 3339 and `T`-separated datetimes, but
+/// SQLite hands datetimes back as `YYYY-MM-DD HH:MM:SS` text.
+struct DateFilter;
+impl tera::Filter for DateFilter {
+    fn filter(
+        &self,
+        value: &sea_orm::JsonValue,
+        args: &std::collections::HashMap<String, sea_orm::JsonValue>,
+    ) -> tera::Result<sea_orm::JsonValue> {
+        let format = args
+            .get("format")
+            .and_then(|v| v.as_str())
+            .unwrap_or("%Y-%m-%d");
+        let datetime = match value {
+            sea_orm::JsonValue::Number(n) => n
+                .as_i64()
+                .and_then(|ts| chrono::DateTime::from_timestamp(ts, 0))
+                .map(|dt| dt.naive_utc()),
+            sea_orm::JsonValue::String(s) => chrono::DateTime::parse_from_rfc3339(s)
+                .map(|dt| dt.naive_local())
+                .ok()
+                .or_else(|| {
+                    ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"]
+                        .iter()
+                        .find_map(|f| chrono::NaiveDateTime::parse_from_str(s, f).ok())
+                })
+                .or_else(|| {
+                    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                        .ok()
+                        .and_then(|d| d.and_hms_opt(0, 0, 0))
+                }),
+            _ => None,
+        };
+        match datetime {
+            Some(datetime) => Ok(json!(datetime.format(format).to_string())),
+            None => Err(tera::Error::msg(format!(
+                "Filter `date` received an unsupported value: {value}"
+            ))),
+        }
+    }
+}
+
 use time::{UtcOffset, macros::format_description};
 use tracing_subscriber::fmt::time::OffsetTime;
 
@@ -162,40 +182,15 @@ async fn main() {
         }
     };
 
-    let Ok(stream) = fs::read("./config.json").await else {
-        panic!("failed to read config file");
-    };
-
-    let Ok(content) = std::str::from_utf8(&stream[..]) else {
-        panic!("failed to parse the config file content");
+    let cli = match Cli::parse() {
+        Ok(cli) => cli,
+        Err(e) => panic!("{e:#}"),
     };
-
-    let json_v: Result<serde_json::Value, serde_json::Error> = serde_json::from_str(content);
-    let json_v = match json_v {
-        Ok(v) => v,
-        Err(e) => {
-            panic!("{}", e.to_string());
-        }
+    let config = match AppConfig::load(&cli) {
+        Ok(config) => config,
+        Err(e) => panic!("invalid configuration: {e:#}"),
     };
-
-    let base_url = json_v.get("base_url").unwrap().as_str().unwrap().to_owned();
-    let database_url = json_v.get("database_url").unwrap().as_str().unwrap();
-    let bind_addr = json_v.get("bind_addr").unwrap().as_str().unwrap();
-    let secret_key = json_v.get("secret_key").unwrap().as_str().unwrap();
-    let redis_url = json_v
-        .get("redis_url")
-        .unwrap()
-        .as_str()
-        .unwrap()
-        .to_owned();
-    let resend_key = json_v
-        .get("resend_key")
-        .unwrap()
-        .as_str()
-        .unwrap()
-        .to_owned();
-
-    let mut db_opt = ConnectOptions::new(database_url.to_owned());
+    let mut db_opt = ConnectOptions::new(config.database_url.clone());
     if cfg!(debug_assertions) {
         //println!("in debug mode");
         db_opt.sqlx_logging_level(log::LevelFilter::Info);
@@ -211,21 +206,73 @@ async fn main() {
         panic!("db init error");
     };
 
-    let mut tera = match Tera::new("views/**/*.html") {
+    if let Command::SetRole { user, role } = &cli.command {
+        if let Err(e) = home::access::set_role_by_name(&db, user, *role).await {
+            panic!("{e:#}");
+        }
+        return;
+    }
+    if cli.command == Command::Sanitize {
+        let sanitizer = home::sanitize::Sanitizer::new(&config.html_policy);
+        if let Err(e) = home::sanitize::resanitize(&db, &sanitizer).await {
+            panic!("{e:#}");
+        }
+        return;
+    }
+    if let Command::Migrate(action) = cli.command {
+        let result = match action {
+            MigrateAction::Up => Migrator::up(&db, None).await,
+            MigrateAction::Down => Migrator::down(&db, Some(1)).await,
+            MigrateAction::Status => Migrator::status(&db).await,
+            MigrateAction::Fresh => Migrator::fresh(&db).await,
+        };
+        if let Err(e) = result {
+            panic!("migration failed: {e}");
+        }
+        return;
+    }
+    if config.auto_migrate
+        && let Err(e) = Migrator::up(&db, None).await
+    {
+        panic!("migration failed: {e}");
+    }
+
+    let tera = match build_tera() {
         Ok(tera) => tera,
         Err(e) => panic!("{}", e.to_string()),
     };
 
-    tera.register_filter("is_null", IsNullFilter);
+    let codes = match home::code_store::from_config(&config).await {
+        Ok(codes) => codes,
+        Err(e) => panic!("{e:#}"),
+    };
+    let mailer = match home::mailer::from_config(&config) {
+        Ok(mailer) => mailer,
+        Err(e) => panic!("{e:#}"),
+    };
+    let oauth = match home::oauth::OAuth::from_config(&config) {
+        Ok(oauth) => oauth,
+        Err(e) => panic!("{e:#}"),
+    };
+
+    let state = AppState::new(&config, db, codes, mailer, oauth, tera);
+    let bind_addr = config.bind_addr;
 
-    // Initialize static global variables
-    home::init_db(db);
-    home::init_tera(tera.clone());
-    home::init_base_url(base_url.clone());
-    home::init_secret_key(secret_key.to_owned());
-    home::init_redis_url(redis_url.to_owned());
-    home::init_resend_key(resend_key.to_owned());
+    tracing::info!("Listening on {}", bind_addr);
+    let acceptor = TcpListener::new(bind_addr).bind().await;
+    Server::new(acceptor).serve(build_service(state)).await;
+}
+
+/// Load every template under `views` and register the custom filters.
+fn build_tera() -> tera::Result<Tera> {
+    let mut tera = Tera::new("views/**/*.html")?;
+    tera.register_filter("is_null", IsNullFilter);
+    tera.register_filter("date", DateFilter);
+    Ok(tera)
+}
 
+/// Build the complete service, routes and 404 catcher included, around `state`.
+fn build_service(state: AppState) -> Service {
     // let auth_handler: JwtAuth<JwtClaims> = JwtAuth::new(secret_key.to_owned())
     //     .finders(vec![
     //         // Box::new(HeaderFinder::new()),
@@ -234,7 +281,7 @@ async fn main() {
     //     ])
     //     .response_error(false);
     let auth_handler: JwtAuth<JwtClaims, _> =
-        JwtAuth::new(ConstDecoder::from_secret(secret_key.as_bytes()))
+        JwtAuth::new(ConstDecoder::from_secret(state.secret_key().as_bytes()))
             .finders(vec![
                 // Box::new(HeaderFinder::new()),
                 Box::new(CookieFinder::new("token")),
@@ -242,23 +289,29 @@ async fn main() {
             ])
             .force_passed(true);
 
-    // let limiter = RateLimiter::new(
-    //     FixedGuard::new(),
-    //     MokaStore::new(),
-    //     RemoteIpIssuer,
-    //     BasicQuota::per_second(1),
-    // );
-
     let router = Router::new()
         .hoop(auth_handler)
-        //.hoop(limiter)
+        .hoop(home::session::SessionGuard)
+        .hoop(home::csrf::CsrfGuard)
         .get(home::home);
 
     let login_router = Router::with_path("login")
         .post(home::login)
-        .get(home::render_login_view);
+        .get(home::render_login_view)
+        .push(Router::with_path("totp").post(home::login_totp));
+    let oauth_router = Router::with_path("oauth/{provider}")
+        .get(home::oauth_start)
+        .push(Router::with_path("callback").get(home::oauth_callback));
     let home_router = Router::with_path("home/{page}").get(home::home);
     let router = router.push(login_router);
+    let router = router.push(oauth_router);
+    let router = router.push(
+        Router::with_path("logout").post(home::logout).push(
+            Router::with_path("all")
+                .hoop(AuthorGuardByMethod)
+                .post(home::logout_everywhere),
+        ),
+    );
     let router = router.push(home_router);
     let router = router.push(Router::with_path("home/{**}").get(home::home));
     let router = router.push(
@@ -266,6 +319,11 @@ async fn main() {
             .hoop(AuthorGuardByMethod)
             .get(home::person_list),
     );
+    let router = router.push(
+        Router::with_path("user/{id}")
+            .get(home::author_page)
+            .push(Router::with_path("{page}").get(home::author_page)),
+    );
     let router = router.push(Router::with_path("search/{page}").get(home::search));
     let router = router.push(Router::with_path("search/{**}").get(home::search));
     let router = router.push(
@@ -292,45 +350,45 @@ async fn main() {
 
     let router = router.push(
         Router::with_path("add")
-            .hoop(AuthorGuardByMethod)
+            .hoop(Require(Permission::ArticlePublish))
             .get(home::render_add_article_view)
             .post(home::add_article),
     );
 
     let router = router.push(
         Router::with_path("edit/{id}")
-            .hoop(AuthorGuardByMethod)
+            .hoop(Require(Permission::ArticlePublish))
             .get(home::render_article_edit_view)
             .post(home::edit_article),
     );
 
     let router = router.push(
         Router::with_path("delete/{id}")
-            .hoop(AuthorGuardByMethod)
+            .hoop(Require(Permission::ArticlePublish))
             .post(home::shadow_article),
     );
 
     let router = router.push(
         Router::with_path("delcomment/{id}")
-            .hoop(AuthorGuardByMethod)
+            .hoop(Require(Permission::CommentPost))
             .post(home::delete_comment),
     );
 
     let router = router.push(
         Router::with_path("commentedit/{id}")
-            .hoop(AuthorGuardByMethod)
+            .hoop(Require(Permission::CommentPost))
             .get(home::edit_comment),
     );
 
     let router = router.push(
         Router::with_path("editcomment/{id}")
-            .hoop(AuthorGuardByMethod)
+            .hoop(Require(Permission::CommentPost))
             .post(home::save_edit_comment),
     );
 
     let router = router.push(
         Router::with_path("comment/{id}")
-            .hoop(AuthorGuardByMethod)
+            .hoop(Require(Permission::CommentPost))
             .post(home::add_comment),
     );
 
@@ -338,11 +396,80 @@ async fn main() {
         Router::with_path("profile")
             .hoop(AuthorGuardByMethod)
             .get(home::render_profile_view)
-            .post(home::edit_profile),
+            .post(home::edit_profile)
+            .push(
+                // The profile is public, so it is held to the same standing as comments
+                Router::with_path("info")
+                    .hoop(Require(Permission::CommentPost))
+                    .post(home::edit_profile_info),
+            )
+            .push(Router::with_path("password").post(home::change_password))
+            .push(
+                Router::with_path("email")
+                    .post(home::change_email)
+                    .push(Router::with_path("code").post(home::send_email_change_code)),
+            )
+            .push(
+                Router::with_path("sessions")
+                    .get(home::render_sessions_view)
+                    .push(Router::with_path("{id}/revoke").post(home::revoke_session)),
+            )
+            .push(
+                Router::with_path("totp")
+                    .get(home::render_totp_view)
+                    .push(Router::with_path("setup").post(home::totp_setup))
+                    .push(Router::with_path("confirm").post(home::totp_confirm))
+                    .push(Router::with_path("disable").post(home::totp_disable))
+                    .push(Router::with_path("recovery").post(home::totp_recovery_codes)),
+            ),
+    );
+
+    let router = router.push(
+        Router::with_path("admin")
+            .get(admin::index)
+            .push(
+                Router::with_path("users")
+                    .hoop(Require(Permission::UserManage))
+                    .get(admin::render_users_view)
+                    .push(Router::with_path("{page}").get(admin::render_users_view))
+                    .push(Router::with_path("{id}/role").post(admin::set_user_role))
+                    .push(Router::with_path("{id}/status").post(admin::set_user_status))
+                    .push(Router::with_path("{id}/password").post(admin::reset_user_password)),
+            )
+            .push(
+                Router::with_path("articles")
+                    .hoop(Require(Permission::ArticleModerate))
+                    .get(admin::render_articles_view)
+                    .push(Router::with_path("{page}").get(admin::render_articles_view))
+                    .push(Router::with_path("{id}/hide").post(admin::set_article_hidden)),
+            )
+            .push(
+                Router::with_path("comments")
+                    .hoop(Require(Permission::CommentModerate))
+                    .get(admin::render_comments_view)
+                    .push(Router::with_path("{page}").get(admin::render_comments_view))
+                    .push(Router::with_path("{id}/delete").post(admin::delete_comment)),
+            )
+            .push(
+                Router::with_path("tags")
+                    .hoop(Require(Permission::UserManage))
+                    .get(admin::render_tags_view)
+                    .post(admin::add_tag)
+                    .push(Router::with_path("{id}").post(admin::edit_tag))
+                    .push(Router::with_path("{id}/delete").post(admin::delete_tag)),
+            )
+            .push(
+                Router::with_path("levels")
+                    .hoop(Require(Permission::UserManage))
+                    .get(admin::render_levels_view)
+                    .post(admin::add_level)
+                    .push(Router::with_path("{id}").post(admin::edit_level))
+                    .push(Router::with_path("{id}/delete").post(admin::delete_level)),
+            ),
     );
 
     let upload_router = Router::with_path("upload")
-        .hoop(AuthorGuardByMethod)
+        .hoop(Require(Permission::CommentPost))
         .post(home::upload);
 
     let router = router.push(upload_router);
@@ -357,10 +484,8 @@ async fn main() {
 
     let root_router = Router::new().push(router).push(router_static_asserts);
 
-    tracing::info!("Listening on {}", bind_addr);
-    let acceptor = TcpListener::new(bind_addr.to_owned()).bind().await;
-    let service = Service::new(root_router)
+    Service::new(root_router)
+        .hoop(InjectState(state))
         //.hoop(Logger::new())
-        .catcher(Catcher::default().hoop(Handle404(base_url, tera)));
-    Server::new(acceptor).serve(service).await;
+        .catcher(Catcher::default().hoop(Handle404))
 }
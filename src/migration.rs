@@ -0,0 +1,64 @@
+//! Schema migrations.
+//!
+//! Every table behind the entities in `home/database` is created here, and the
+//! versions that have been applied are recorded by sea-orm in `seaql_migrations`.
+//! Schema changes ship as a new `mYYYYMMDD_NNNNNN_*` module appended to
+//! [`Migrator::migrations`].
+
+use sea_orm_migration::prelude::*;
+
+mod m20261018_000001_create_tables;
+mod m20261018_000002_create_session_tb;
+mod m20261018_000003_add_session_details;
+mod m20261018_000004_add_two_factor;
+mod m20261018_000005_create_identity_tb;
+mod m20261018_000006_add_user_status;
+mod m20261018_000007_add_user_profile;
+mod m20261018_000008_add_user_clearance;
+mod m20261018_000009_add_article_hidden;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20261018_000001_create_tables::Migration),
+            Box::new(m20261018_000002_create_session_tb::Migration),
+            Box::new(m20261018_000003_add_session_details::Migration),
+            Box::new(m20261018_000004_add_two_factor::Migration),
+            Box::new(m20261018_000005_create_identity_tb::Migration),
+            Box::new(m20261018_000006_add_user_status::Migration),
+            Box::new(m20261018_000007_add_user_profile::Migration),
+            Box::new(m20261018_000008_add_user_clearance::Migration),
+            Box::new(m20261018_000009_add_article_hidden::Migration),
+        ]
+    }
+}
+
+/// Create `index` unless a table with that index already exists.
+///
+/// Databases that predate the migrations were set up from a SQL dump which
+/// already carried some of these indexes, and MySQL has no
+/// `CREATE INDEX IF NOT EXISTS`.
+async fn create_index_if_missing(
+    manager: &SchemaManager<'_>,
+    table: &str,
+    name: &str,
+    index: IndexCreateStatement,
+) -> Result<(), DbErr> {
+    if !manager.has_index(table, name).await? {
+        manager.create_index(index).await?;
+    }
+    Ok(())
+}
+
+/// An auto-incrementing integer primary key.
+fn id<T: IntoIden>(col: T) -> ColumnDef {
+    ColumnDef::new(col)
+        .integer()
+        .not_null()
+        .auto_increment()
+        .primary_key()
+        .to_owned()
+}
@@ -0,0 +1,372 @@
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::DbBackend;
+
+use super::{create_index_if_missing, id};
+
+/// The initial schema, equivalent to the former `deno.sql` and `database_indexes.sql`.
+///
+/// Tables are created with `IF NOT EXISTS` so an installation that was set up
+/// from the old dump can adopt the migrations without being recreated.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let backend = manager.get_database_backend();
+        manager
+            .create_table(
+                Table::create()
+                    .table(ArticleTb::Table)
+                    .if_not_exists()
+                    .col(id(ArticleTb::Id))
+                    .col(ColumnDef::new(ArticleTb::UserId).integer().null())
+                    .col(ColumnDef::new(ArticleTb::CreateTime).date_time().null())
+                    .col(ColumnDef::new(ArticleTb::UpdateTime).date_time().null())
+                    .col(long_text(backend, ArticleTb::Content))
+                    .col(ColumnDef::new(ArticleTb::Title).string_len(255).null())
+                    .col(ColumnDef::new(ArticleTb::Level).integer().null())
+                    .col(ColumnDef::new(ArticleTb::TagId).integer().null())
+                    .col(
+                        ColumnDef::new(ArticleTb::ArticleState)
+                            .integer()
+                            .null()
+                            .default(1),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(CommentTb::Table)
+                    .if_not_exists()
+                    .col(id(CommentTb::Id))
+                    .col(ColumnDef::new(CommentTb::UserId).integer().null())
+                    .col(ColumnDef::new(CommentTb::ArticleId).integer().null())
+                    .col(ColumnDef::new(CommentTb::CreateTime).date_time().null())
+                    .col(ColumnDef::new(CommentTb::UpdateTime).date_time().null())
+                    .col(long_text(backend, CommentTb::Comment))
+                    .col(long_text(backend, CommentTb::MdContent))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(LevelTb::Table)
+                    .if_not_exists()
+                    .col(id(LevelTb::Id))
+                    .col(ColumnDef::new(LevelTb::Level).integer().null())
+                    .col(ColumnDef::new(LevelTb::CreateTime).date_time().null())
+                    .col(ColumnDef::new(LevelTb::Name).string_len(255).null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(TagTb::Table)
+                    .if_not_exists()
+                    .col(id(TagTb::Id))
+                    .col(ColumnDef::new(TagTb::Name).string_len(255).null())
+                    .col(ColumnDef::new(TagTb::CreateTime).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(UserTb::Table)
+                    .if_not_exists()
+                    .col(id(UserTb::Id))
+                    .col(ColumnDef::new(UserTb::Name).string_len(255).null())
+                    .col(ColumnDef::new(UserTb::CreateTime).date_time().null())
+                    .col(ColumnDef::new(UserTb::UpdateTime).date_time().null())
+                    .col(ColumnDef::new(UserTb::Email).string_len(255).null())
+                    .col(ColumnDef::new(UserTb::Password).string_len(255).null())
+                    .col(ColumnDef::new(UserTb::Privilege).small_integer().null())
+                    .col(
+                        ColumnDef::new(UserTb::Avatar)
+                            .string_len(255)
+                            .null()
+                            .default(""),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(ViewTb::Table)
+                    .if_not_exists()
+                    .col(id(ViewTb::Id))
+                    .col(ColumnDef::new(ViewTb::ArticleId).integer().null())
+                    .col(ColumnDef::new(ViewTb::CreateTime).date_time().null())
+                    .col(ColumnDef::new(ViewTb::Ip).string_len(255).null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Used in every page load with filtering and ordering
+        create_index_if_missing(
+            manager,
+            "article_tb",
+            "idx_article_state_level",
+            Index::create()
+                .name("idx_article_state_level")
+                .table(ArticleTb::Table)
+                .col(ArticleTb::ArticleState)
+                .col(ArticleTb::Level)
+                .to_owned(),
+        )
+        .await?;
+        create_index_if_missing(
+            manager,
+            "article_tb",
+            "idx_article_update_time",
+            Index::create()
+                .name("idx_article_update_time")
+                .table(ArticleTb::Table)
+                .col(ArticleTb::UpdateTime)
+                .to_owned(),
+        )
+        .await?;
+        create_index_if_missing(
+            manager,
+            "article_tb",
+            "idx_article_user_id",
+            Index::create()
+                .name("idx_article_user_id")
+                .table(ArticleTb::Table)
+                .col(ArticleTb::UserId)
+                .to_owned(),
+        )
+        .await?;
+
+        // Used for counting views and comments per article
+        create_index_if_missing(
+            manager,
+            "view_tb",
+            "idx_view_article_id",
+            Index::create()
+                .name("idx_view_article_id")
+                .table(ViewTb::Table)
+                .col(ViewTb::ArticleId)
+                .to_owned(),
+        )
+        .await?;
+        create_index_if_missing(
+            manager,
+            "comment_tb",
+            "idx_comment_article_id",
+            Index::create()
+                .name("idx_comment_article_id")
+                .table(CommentTb::Table)
+                .col(CommentTb::ArticleId)
+                .to_owned(),
+        )
+        .await?;
+        create_index_if_missing(
+            manager,
+            "comment_tb",
+            "idx_comment_user_id",
+            Index::create()
+                .name("idx_comment_user_id")
+                .table(CommentTb::Table)
+                .col(CommentTb::UserId)
+                .to_owned(),
+        )
+        .await?;
+
+        // Used in LIKE searches, registration checks and login
+        create_index_if_missing(
+            manager,
+            "tag_tb",
+            "idx_tag_name",
+            Index::create()
+                .name("idx_tag_name")
+                .table(TagTb::Table)
+                .col(prefixed(backend, TagTb::Name, 50))
+                .to_owned(),
+        )
+        .await?;
+        create_index_if_missing(
+            manager,
+            "user_tb",
+            "idx_user_name",
+            Index::create()
+                .name("idx_user_name")
+                .table(UserTb::Table)
+                .col(prefixed(backend, UserTb::Name, 50))
+                .to_owned(),
+        )
+        .await?;
+        create_index_if_missing(
+            manager,
+            "user_tb",
+            "idx_user_email",
+            Index::create()
+                .name("idx_user_email")
+                .table(UserTb::Table)
+                .col(prefixed(backend, UserTb::Email, 100))
+                .to_owned(),
+        )
+        .await?;
+
+        seed_defaults(manager).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in [
+            ArticleTb::Table.into_iden(),
+            CommentTb::Table.into_iden(),
+            LevelTb::Table.into_iden(),
+            TagTb::Table.into_iden(),
+            UserTb::Table.into_iden(),
+            ViewTb::Table.into_iden(),
+        ] {
+            manager
+                .drop_table(Table::drop().table(table).if_exists().to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// `TEXT` is limited to 64KiB on MySQL, so article bodies and comments use `LONGTEXT` there.
+fn long_text<T: IntoIden>(backend: DbBackend, col: T) -> ColumnDef {
+    let mut def = ColumnDef::new(col);
+    match backend {
+        DbBackend::MySql => def.custom(Alias::new("LONGTEXT")),
+        DbBackend::Postgres | DbBackend::Sqlite => def.text(),
+    };
+    def.null().to_owned()
+}
+
+/// Index prefix lengths are only understood by MySQL.
+fn prefixed<T: IntoIden>(backend: DbBackend, col: T, len: u32) -> IndexColumn {
+    match backend {
+        DbBackend::MySql => (col, len).into_index_column(),
+        DbBackend::Postgres | DbBackend::Sqlite => col.into_index_column(),
+    }
+}
+
+/// A fresh installation needs at least one reading level and one tag before an
+/// article can be posted. Existing rows are never touched.
+async fn seed_defaults(manager: &SchemaManager<'_>) -> Result<(), DbErr> {
+    let db = manager.get_connection();
+    let backend = manager.get_database_backend();
+    let is_empty = |table: &'static str| {
+        let query = Query::select()
+            .expr(Expr::col(Asterisk).count())
+            .from(Alias::new(table))
+            .to_owned();
+        async move {
+            let row = db.query_one(backend.build(&query)).await?;
+            let count: i64 = match row {
+                Some(row) => row.try_get_by_index(0)?,
+                None => 0,
+            };
+            Ok::<bool, DbErr>(count == 0)
+        }
+    };
+
+    if is_empty("level_tb").await? {
+        let insert = Query::insert()
+            .into_table(LevelTb::Table)
+            .columns([LevelTb::Level, LevelTb::Name, LevelTb::CreateTime])
+            .values_panic([1.into(), "公开".into(), Expr::current_timestamp().into()])
+            .values_panic([
+                2.into(),
+                "登录可见".into(),
+                Expr::current_timestamp().into(),
+            ])
+            .values_panic([
+                999.into(),
+                "仅自己可见".into(),
+                Expr::current_timestamp().into(),
+            ])
+            .to_owned();
+        manager.exec_stmt(insert).await?;
+    }
+    if is_empty("tag_tb").await? {
+        let insert = Query::insert()
+            .into_table(TagTb::Table)
+            .columns([TagTb::Name, TagTb::CreateTime])
+            .values_panic(["默认".into(), Expr::current_timestamp().into()])
+            .to_owned();
+        manager.exec_stmt(insert).await?;
+    }
+    Ok(())
+}
+
+#[derive(DeriveIden)]
+enum ArticleTb {
+    Table,
+    Id,
+    UserId,
+    CreateTime,
+    UpdateTime,
+    Content,
+    Title,
+    Level,
+    TagId,
+    ArticleState,
+}
+
+#[derive(DeriveIden)]
+enum CommentTb {
+    Table,
+    Id,
+    UserId,
+    ArticleId,
+    CreateTime,
+    UpdateTime,
+    Comment,
+    MdContent,
+}
+
+#[derive(DeriveIden)]
+enum LevelTb {
+    Table,
+    Id,
+    Level,
+    CreateTime,
+    Name,
+}
+
+#[derive(DeriveIden)]
+enum TagTb {
+    Table,
+    Id,
+    Name,
+    CreateTime,
+}
+
+#[derive(DeriveIden)]
+enum UserTb {
+    Table,
+    Id,
+    Name,
+    CreateTime,
+    UpdateTime,
+    Email,
+    Password,
+    Privilege,
+    Avatar,
+}
+
+#[derive(DeriveIden)]
+enum ViewTb {
+    Table,
+    Id,
+    ArticleId,
+    CreateTime,
+    Ip,
+}
@@ -0,0 +1,103 @@
+use sea_orm_migration::prelude::*;
+
+use super::{create_index_if_missing, id};
+
+/// Server-side sessions, so a token can be revoked before it expires.
+///
+/// Every issued JWT names a row in `session_tb` by its `jti`, and carries the
+/// `token_version` its user had at the time. Bumping the version signs the
+/// user out of every device at once.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        if !manager.has_column("user_tb", "token_version").await? {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserTb::Table)
+                        .add_column(
+                            ColumnDef::new(UserTb::TokenVersion)
+                                .integer()
+                                .not_null()
+                                .default(0),
+                        )
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(SessionTb::Table)
+                    .if_not_exists()
+                    .col(id(SessionTb::Id))
+                    .col(ColumnDef::new(SessionTb::UserId).integer().not_null())
+                    .col(ColumnDef::new(SessionTb::Jti).string_len(64).not_null())
+                    .col(ColumnDef::new(SessionTb::CreateTime).date_time().not_null())
+                    .col(ColumnDef::new(SessionTb::ExpireTime).date_time().not_null())
+                    .col(ColumnDef::new(SessionTb::RevokeTime).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+
+        // Looked up on every authenticated request
+        create_index_if_missing(
+            manager,
+            "session_tb",
+            "idx_session_jti",
+            Index::create()
+                .name("idx_session_jti")
+                .table(SessionTb::Table)
+                .col(SessionTb::Jti)
+                .unique()
+                .to_owned(),
+        )
+        .await?;
+        create_index_if_missing(
+            manager,
+            "session_tb",
+            "idx_session_user_id",
+            Index::create()
+                .name("idx_session_user_id")
+                .table(SessionTb::Table)
+                .col(SessionTb::UserId)
+                .to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(SessionTb::Table).if_exists().to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserTb::Table)
+                    .drop_column(UserTb::TokenVersion)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserTb {
+    Table,
+    TokenVersion,
+}
+
+#[derive(DeriveIden)]
+enum SessionTb {
+    Table,
+    Id,
+    UserId,
+    Jti,
+    CreateTime,
+    ExpireTime,
+    RevokeTime,
+}
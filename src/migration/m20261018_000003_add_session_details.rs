@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+/// Where and when each session was used, for the `/profile/sessions` page.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only takes one column per ALTER TABLE
+        for column in [
+            ColumnDef::new(SessionTb::UserAgent)
+                .string_len(255)
+                .null()
+                .to_owned(),
+            ColumnDef::new(SessionTb::Ip)
+                .string_len(64)
+                .null()
+                .to_owned(),
+            ColumnDef::new(SessionTb::LastSeenTime)
+                .date_time()
+                .null()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(SessionTb::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [SessionTb::UserAgent, SessionTb::Ip, SessionTb::LastSeenTime] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(SessionTb::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum SessionTb {
+    Table,
+    UserAgent,
+    Ip,
+    LastSeenTime,
+}
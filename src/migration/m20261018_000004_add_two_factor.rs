@@ -0,0 +1,120 @@
+use sea_orm_migration::prelude::*;
+
+use super::{create_index_if_missing, id};
+
+/// Optional TOTP second factor.
+///
+/// `totp_secret` is written when enrollment starts and `totp_enabled` set once
+/// the user has proven their authenticator works. `totp_last_step` is the last
+/// 30 second step a code was accepted for, so a code can't be replayed.
+/// Recovery codes are stored hashed, one row each.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only takes one column per ALTER TABLE
+        for column in [
+            ColumnDef::new(UserTb::TotpSecret)
+                .string_len(64)
+                .null()
+                .to_owned(),
+            ColumnDef::new(UserTb::TotpEnabled)
+                .boolean()
+                .not_null()
+                .default(false)
+                .to_owned(),
+            ColumnDef::new(UserTb::TotpLastStep)
+                .big_integer()
+                .null()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserTb::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(RecoveryCodeTb::Table)
+                    .if_not_exists()
+                    .col(id(RecoveryCodeTb::Id))
+                    .col(ColumnDef::new(RecoveryCodeTb::UserId).integer().not_null())
+                    .col(
+                        ColumnDef::new(RecoveryCodeTb::CodeHash)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(RecoveryCodeTb::CreateTime)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(RecoveryCodeTb::UseTime).date_time().null())
+                    .to_owned(),
+            )
+            .await?;
+        create_index_if_missing(
+            manager,
+            "recovery_code_tb",
+            "idx_recovery_code_user_id",
+            Index::create()
+                .name("idx_recovery_code_user_id")
+                .table(RecoveryCodeTb::Table)
+                .col(RecoveryCodeTb::UserId)
+                .to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(RecoveryCodeTb::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await?;
+        for column in [
+            UserTb::TotpSecret,
+            UserTb::TotpEnabled,
+            UserTb::TotpLastStep,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserTb::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserTb {
+    Table,
+    TotpSecret,
+    TotpEnabled,
+    TotpLastStep,
+}
+
+#[derive(DeriveIden)]
+enum RecoveryCodeTb {
+    Table,
+    Id,
+    UserId,
+    CodeHash,
+    CreateTime,
+    UseTime,
+}
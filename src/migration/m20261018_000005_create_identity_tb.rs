@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+use super::{create_index_if_missing, id};
+
+/// Accounts at OAuth / OpenID Connect providers linked to `user_tb` rows.
+///
+/// `subject` is the provider's stable id for the account (`sub`, or GitHub's
+/// numeric `id`), which unlike the username or email never changes.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(IdentityTb::Table)
+                    .if_not_exists()
+                    .col(id(IdentityTb::Id))
+                    .col(ColumnDef::new(IdentityTb::UserId).integer().not_null())
+                    .col(
+                        ColumnDef::new(IdentityTb::Provider)
+                            .string_len(64)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(IdentityTb::Subject)
+                            .string_len(255)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(IdentityTb::Email).string_len(255).null())
+                    .col(
+                        ColumnDef::new(IdentityTb::CreateTime)
+                            .date_time()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        create_index_if_missing(
+            manager,
+            "identity_tb",
+            "idx_identity_provider_subject",
+            Index::create()
+                .name("idx_identity_provider_subject")
+                .table(IdentityTb::Table)
+                .col(IdentityTb::Provider)
+                .col(IdentityTb::Subject)
+                .unique()
+                .to_owned(),
+        )
+        .await?;
+        create_index_if_missing(
+            manager,
+            "identity_tb",
+            "idx_identity_user_id",
+            Index::create()
+                .name("idx_identity_user_id")
+                .table(IdentityTb::Table)
+                .col(IdentityTb::UserId)
+                .to_owned(),
+        )
+        .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(
+                Table::drop()
+                    .table(IdentityTb::Table)
+                    .if_exists()
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum IdentityTb {
+    Table,
+    Id,
+    UserId,
+    Provider,
+    Subject,
+    Email,
+    CreateTime,
+}
@@ -0,0 +1,60 @@
+use sea_orm_migration::prelude::*;
+
+/// Account standing: `active`, `suspended` until `suspended_until`, or
+/// `banned`, with the reason given to the user in `status_reason`.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only takes one column per ALTER TABLE
+        for column in [
+            ColumnDef::new(UserTb::Status)
+                .string_len(16)
+                .not_null()
+                .default("active")
+                .to_owned(),
+            ColumnDef::new(UserTb::SuspendedUntil)
+                .date_time()
+                .null()
+                .to_owned(),
+            ColumnDef::new(UserTb::StatusReason)
+                .string_len(255)
+                .null()
+                .to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserTb::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [UserTb::Status, UserTb::SuspendedUntil, UserTb::StatusReason] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserTb::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserTb {
+    Table,
+    Status,
+    SuspendedUntil,
+    StatusReason,
+}
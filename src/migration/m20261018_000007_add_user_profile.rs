@@ -0,0 +1,70 @@
+use sea_orm_migration::prelude::*;
+
+/// Public profile fields shown on `/user/{id}`.
+///
+/// `social_links` holds one URL per line.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite only takes one column per ALTER TABLE
+        for column in [
+            ColumnDef::new(UserTb::DisplayName)
+                .string_len(64)
+                .null()
+                .to_owned(),
+            ColumnDef::new(UserTb::Bio).text().null().to_owned(),
+            ColumnDef::new(UserTb::Website)
+                .string_len(255)
+                .null()
+                .to_owned(),
+            ColumnDef::new(UserTb::Location)
+                .string_len(64)
+                .null()
+                .to_owned(),
+            ColumnDef::new(UserTb::SocialLinks).text().null().to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserTb::Table)
+                        .add_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [
+            UserTb::DisplayName,
+            UserTb::Bio,
+            UserTb::Website,
+            UserTb::Location,
+            UserTb::SocialLinks,
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(UserTb::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserTb {
+    Table,
+    DisplayName,
+    Bio,
+    Website,
+    Location,
+    SocialLinks,
+}
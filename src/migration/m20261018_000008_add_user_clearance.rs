@@ -0,0 +1,58 @@
+use sea_orm_migration::prelude::*;
+
+/// Reading clearance in its own column, apart from the role in `privilege`.
+///
+/// Before roles `privilege` only said which article levels a user could
+/// read, and every signed in user could publish. Its old value moves to
+/// `clearance` unchanged and every existing user becomes an author, so
+/// nobody loses an article they could read or gains a moderator's powers.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserTb::Table)
+                    .add_column(ColumnDef::new(UserTb::Clearance).integer().null())
+                    .to_owned(),
+            )
+            .await?;
+        let moved = Query::update()
+            .table(UserTb::Table)
+            .value(UserTb::Clearance, Expr::col(UserTb::Privilege))
+            .to_owned();
+        manager.exec_stmt(moved).await?;
+        let authors = Query::update()
+            .table(UserTb::Table)
+            .value(UserTb::Privilege, 2)
+            .to_owned();
+        manager.exec_stmt(authors).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let restored = Query::update()
+            .table(UserTb::Table)
+            .value(UserTb::Privilege, Expr::col(UserTb::Clearance))
+            .and_where(Expr::col(UserTb::Clearance).is_not_null())
+            .to_owned();
+        manager.exec_stmt(restored).await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(UserTb::Table)
+                    .drop_column(UserTb::Clearance)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum UserTb {
+    Table,
+    Privilege,
+    Clearance,
+}
@@ -0,0 +1,44 @@
+use sea_orm_migration::prelude::*;
+
+/// Articles hidden by a moderator.
+///
+/// Kept apart from `article_state`, which authors flip themselves when they
+/// withdraw or restore an article, so an author can't undo a moderator.
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ArticleTb::Table)
+                    .add_column(
+                        ColumnDef::new(ArticleTb::Hidden)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(ArticleTb::Table)
+                    .drop_column(ArticleTb::Hidden)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum ArticleTb {
+    Table,
+    Hidden,
+}
@@ -0,0 +1,660 @@
+//! End-to-end tests that drive the service built by `build_service`.
+//!
+//! Every [`TestApp`] owns a SQLite database in a temporary directory, keeps
+//! verification codes in memory and writes outgoing email to files next to
+//! the database, so tests run in parallel without sharing state and can read
+//! the codes back. [`TestApp::with_redis`] and [`TestApp::with_resend`] swap in
+//! in-process fakes of those services instead, and [`TestApp::with_oidc`] adds
+//! a fake OpenID Connect provider to sign in with.
+
+mod admin;
+mod article_access;
+mod auth;
+mod code_store;
+mod config;
+mod csrf;
+mod flow;
+mod mail;
+mod markdown;
+mod math;
+mod oauth;
+mod profile;
+mod roles;
+mod sanitize;
+mod sessions;
+mod standing;
+mod throttle;
+mod two_factor;
+mod verification;
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use salvo::prelude::*;
+use salvo::test::{ResponseExt, TestClient};
+use sea_orm::{ConnectionTrait, Database, Statement};
+use sea_orm_migration::MigratorTrait;
+use serde_json::Value;
+use tempfile::TempDir;
+
+use crate::config::{AppConfig, CodeStoreKind, MailTransport, OAuthKind, OAuthProviderConfig};
+use crate::home::AppState;
+use crate::migration::Migrator;
+use crate::{build_service, build_tera};
+
+pub struct TestApp {
+    pub service: Service,
+    pub state: AppState,
+    config: AppConfig,
+    redis_fake: Option<FakeRedis>,
+    pub oidc: Option<FakeOidc>,
+    /// The `csrf_token` cookie every request of the test client carries
+    csrf: String,
+    _dir: TempDir,
+}
+
+impl TestApp {
+    pub async fn new() -> TestApp {
+        Self::build(None, |_| {}).await
+    }
+
+    /// Keep verification codes in a fake Redis rather than in memory.
+    pub async fn with_redis() -> TestApp {
+        Self::build(Some(FakeRedis::start()), |_| {}).await
+    }
+
+    /// Send email through the Resend transport, pointed at [`FakeResend`].
+    pub async fn with_resend() -> TestApp {
+        let resend = FakeResend::global();
+        Self::build(None, |config| {
+            config.mail_transport = MailTransport::Resend;
+            config.resend_key = "re_test".to_owned();
+            config.resend_url = resend.url.clone();
+        })
+        .await
+    }
+
+    /// Offer sign-in through a [`FakeOidc`] provider with the id `mock`.
+    pub async fn with_oidc() -> TestApp {
+        let oidc = FakeOidc::start();
+        let mut app = Self::build(None, |config| {
+            config.public_url = "http://127.0.0.1:5800".to_owned();
+            config.oauth_providers = vec![OAuthProviderConfig {
+                id: "mock".to_owned(),
+                name: "Mock".to_owned(),
+                kind: OAuthKind::Oidc,
+                client_id: "blog".to_owned(),
+                client_secret: OIDC_CLIENT_SECRET.to_owned(),
+                client_secret_file: None,
+                url: oidc.url().to_owned(),
+                scopes: Vec::new(),
+            }];
+        })
+        .await;
+        app.oidc = Some(oidc);
+        app
+    }
+
+    async fn build(
+        redis_fake: Option<FakeRedis>,
+        customize: impl FnOnce(&mut AppConfig),
+    ) -> TestApp {
+        let dir = tempfile::tempdir().expect("failed to create temp dir");
+        let mut config = AppConfig {
+            database_url: format!("sqlite://{}?mode=rwc", dir.path().join("blog.db").display()),
+            secret_key: "test-secret-key-that-is-long-enough-for-hs256".to_owned(),
+            code_store: CodeStoreKind::Memory,
+            mail_transport: MailTransport::File,
+            mail_dir: Some(dir.path().join("mail")),
+            ..AppConfig::default()
+        };
+        if let Some(redis) = &redis_fake {
+            config.code_store = CodeStoreKind::Redis;
+            config.redis_url = redis.url();
+        }
+        customize(&mut config);
+
+        let db = Database::connect(&config.database_url)
+            .await
+            .expect("failed to open test database");
+        Migrator::up(&db, None)
+            .await
+            .expect("failed to migrate test database");
+        let codes = crate::home::code_store::from_config(&config)
+            .await
+            .expect("failed to set up the code store");
+        let mailer =
+            crate::home::mailer::from_config(&config).expect("failed to set up the mailer");
+        let oauth = crate::home::oauth::OAuth::from_config(&config)
+            .expect("failed to set up the OAuth client");
+        let state = AppState::new(
+            &config,
+            db,
+            codes,
+            mailer,
+            oauth,
+            build_tera().expect("failed to load templates"),
+        );
+        TestApp {
+            csrf: crate::home::csrf::issue(state.secret_key()),
+            service: build_service(state.clone()),
+            state,
+            config,
+            redis_fake,
+            oidc: None,
+            _dir: dir,
+        }
+    }
+
+    /// Every message the file transport has written so far, oldest first.
+    pub fn sent_mail(&self) -> Vec<String> {
+        let Some(dir) = &self.config.mail_dir else {
+            return Vec::new();
+        };
+        let Ok(entries) = std::fs::read_dir(dir) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<_> = entries.flatten().map(|e| e.path()).collect();
+        paths.sort();
+        paths
+            .iter()
+            .map(|p| std::fs::read_to_string(p).expect("unreadable mail file"))
+            .collect()
+    }
+
+    /// The code in the most recent email sent to `to`.
+    pub fn last_code_sent_to(&self, to: &str) -> String {
+        if self.config.mail_transport == MailTransport::Resend {
+            return FakeResend::global().last_code_sent_to(to);
+        }
+        let header = format!("To: {to}\n");
+        self.sent_mail()
+            .iter()
+            .rev()
+            .find(|message| message.contains(&header))
+            .and_then(|message| extract_code(message.split_once("\n\n")?.1))
+            .unwrap_or_else(|| panic!("no verification email was sent to {to}"))
+    }
+
+    pub fn url(path: &str) -> String {
+        format!("http://127.0.0.1:5800{path}")
+    }
+
+    /// Sign `builder` in with `token`, and send the CSRF token along as a page
+    /// would so it gets past `CsrfGuard`.
+    pub fn with_token(
+        &self,
+        builder: salvo::test::RequestBuilder,
+        token: Option<&str>,
+    ) -> salvo::test::RequestBuilder {
+        let mut cookie = format!("{}={}", crate::home::csrf::COOKIE, self.csrf);
+        if let Some(token) = token {
+            cookie.push_str(&format!("; token={token}"));
+        }
+        builder.add_header("cookie", cookie, true).add_header(
+            crate::home::csrf::HEADER,
+            &self.csrf,
+            true,
+        )
+    }
+
+    /// GET `path` and return the status code and body.
+    pub async fn get(&self, path: &str, token: Option<&str>) -> (StatusCode, String) {
+        let mut res = self
+            .with_token(TestClient::get(Self::url(path)), token)
+            .send(&self.service)
+            .await;
+        let status = res.status_code.unwrap_or(StatusCode::OK);
+        (status, res.take_string().await.unwrap_or_default())
+    }
+
+    /// POST a urlencoded form to `path` and parse the JSON reply.
+    pub async fn post(&self, path: &str, form: &[(&str, &str)], token: Option<&str>) -> Value {
+        let mut res = self
+            .with_token(TestClient::post(Self::url(path)), token)
+            .form(&form)
+            .send(&self.service)
+            .await;
+        let body = res.take_string().await.unwrap_or_default();
+        serde_json::from_str(&body).unwrap_or_else(|_| panic!("not a JSON reply: {body}"))
+    }
+
+    /// POST a form as if it came from `ip` through a reverse proxy.
+    pub async fn post_from(&self, ip: &str, path: &str, form: &[(&str, &str)]) -> Value {
+        let mut res = self
+            .with_token(TestClient::post(Self::url(path)), None)
+            .add_header("x-forwarded-for", ip, true)
+            .form(&form)
+            .send(&self.service)
+            .await;
+        let body = res.take_string().await.unwrap_or_default();
+        serde_json::from_str(&body).unwrap_or_else(|_| panic!("not a JSON reply: {body}"))
+    }
+
+    /// Go through sendcode and register, returning the session token.
+    pub async fn register(&self, name: &str, email: &str, password: &str) -> String {
+        let r = self.post("/sendcode", &[("email", email)], None).await;
+        assert_eq!(r["code"], 200, "sendcode failed: {r}");
+        let code = self.last_code_sent_to(email);
+        let r = self
+            .post(
+                "/register",
+                &[
+                    ("nickName", name),
+                    ("password", password),
+                    ("password2", password),
+                    ("email", email),
+                    ("code", &code),
+                ],
+                None,
+            )
+            .await;
+        assert_eq!(r["code"], 200, "register failed: {r}");
+        r["token"].as_str().expect("token in reply").to_owned()
+    }
+
+    pub async fn login(&self, name: &str, password: &str) -> Value {
+        self.post(
+            "/login",
+            &[
+                ("nickName", name),
+                ("password", password),
+                ("rememberMe", "false"),
+            ],
+            None,
+        )
+        .await
+    }
+
+    /// Run a query returning a single integer, for looking up ids the UI doesn't expose.
+    pub async fn scalar(&self, sql: &str) -> i64 {
+        let db = self.state.db();
+        let row = db
+            .query_one(Statement::from_string(db.get_database_backend(), sql))
+            .await
+            .expect("query failed")
+            .expect("query returned no row");
+        row.try_get_by_index(0).expect("not an integer")
+    }
+
+    /// Make the fake Redis drop every connection, as if the server had gone away.
+    pub fn stop_redis(&self) {
+        let redis = self
+            .redis_fake
+            .as_ref()
+            .expect("app was built without redis");
+        redis.down.store(true, Ordering::SeqCst);
+    }
+
+    pub async fn execute(&self, sql: &str) {
+        let db = self.state.db();
+        db.execute(Statement::from_string(db.get_database_backend(), sql))
+            .await
+            .expect("statement failed");
+    }
+}
+
+/// Pull the first run of 4 to 8 digits out of an email body.
+pub fn extract_code(html: &str) -> Option<String> {
+    html.split(|c: char| !c.is_ascii_digit())
+        .find(|run| (4..=8).contains(&run.len()))
+        .map(str::to_owned)
+}
+
+/// A Redis stand-in that understands the handful of commands the blog uses.
+///
+/// Each connection is served on a plain OS thread so the fake keeps working
+/// regardless of which runtime the test is driving.
+struct FakeRedis {
+    port: u16,
+    down: Arc<AtomicBool>,
+}
+
+impl FakeRedis {
+    fn start() -> FakeRedis {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind fake redis");
+        let port = listener.local_addr().unwrap().port();
+        let store = Arc::new(Mutex::new(HashMap::<String, String>::new()));
+        let down = Arc::new(AtomicBool::new(false));
+        let stopped = down.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if stopped.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let store = store.clone();
+                let stopped = stopped.clone();
+                std::thread::spawn(move || serve_redis(stream, store, stopped));
+            }
+        });
+        FakeRedis { port, down }
+    }
+
+    fn url(&self) -> String {
+        format!("redis://127.0.0.1:{}/", self.port)
+    }
+}
+
+fn serve_redis(
+    stream: TcpStream,
+    store: Arc<Mutex<HashMap<String, String>>>,
+    stopped: Arc<AtomicBool>,
+) {
+    let mut writer = stream.try_clone().expect("failed to clone stream");
+    let mut reader = BufReader::new(stream);
+    while let Some(args) = read_resp_command(&mut reader) {
+        if stopped.load(Ordering::SeqCst) {
+            return;
+        }
+        let command = args
+            .first()
+            .map(|c| c.to_ascii_uppercase())
+            .unwrap_or_default();
+        let mut store = store.lock().unwrap();
+        let reply = match (command.as_str(), &args[1..]) {
+            ("GET", [key]) => match store.get(key) {
+                Some(v) => format!("${}\r\n{v}\r\n", v.len()),
+                None => "$-1\r\n".to_owned(),
+            },
+            ("SET", [key, value, ..]) | ("SETEX", [key, _, value]) => {
+                store.insert(key.clone(), value.clone());
+                "+OK\r\n".to_owned()
+            }
+            ("INCR", [key]) => {
+                let value = store
+                    .get(key)
+                    .and_then(|v| v.parse::<i64>().ok())
+                    .unwrap_or(0)
+                    + 1;
+                store.insert(key.clone(), value.to_string());
+                format!(":{value}\r\n")
+            }
+            // The only script is `RedisCodeStore::take`
+            ("EVALSHA", [_, _, key, attempts, guess]) => match store.get(key) {
+                None => ":-1\r\n".to_owned(),
+                Some(code) if code != guess => ":0\r\n".to_owned(),
+                Some(_) => {
+                    store.remove(key);
+                    store.remove(attempts);
+                    ":1\r\n".to_owned()
+                }
+            },
+            ("DEL", keys) => {
+                let removed = keys.iter().filter(|k| store.remove(*k).is_some()).count();
+                format!(":{removed}\r\n")
+            }
+            _ => "+OK\r\n".to_owned(),
+        };
+        if writer.write_all(reply.as_bytes()).is_err() {
+            return;
+        }
+    }
+}
+
+fn read_resp_command(reader: &mut impl BufRead) -> Option<Vec<String>> {
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let count: usize = line.trim().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        line.clear();
+        reader.read_line(&mut line).ok()?;
+        let len: usize = line.trim().strip_prefix('$')?.parse().ok()?;
+        let mut buf = vec![0; len + 2];
+        reader.read_exact(&mut buf).ok()?;
+        buf.truncate(len);
+        args.push(String::from_utf8(buf).ok()?);
+    }
+    Some(args)
+}
+
+/// A stand-in for the Resend HTTP API that records every email it is asked to send.
+///
+/// One fake serves the whole test binary; every [`TestApp::with_resend`]
+/// points its `resend_url` at it.
+pub struct FakeResend {
+    pub url: String,
+    sent: Arc<Mutex<Vec<Value>>>,
+}
+
+impl FakeResend {
+    /// The process-wide instance, started by the first call.
+    pub fn global() -> &'static FakeResend {
+        static INSTANCE: OnceLock<FakeResend> = OnceLock::new();
+        INSTANCE.get_or_init(|| {
+            let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind fake resend");
+            let port = listener.local_addr().unwrap().port();
+            let sent = Arc::new(Mutex::new(Vec::new()));
+            let inbox = sent.clone();
+            std::thread::spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    let inbox = inbox.clone();
+                    std::thread::spawn(move || serve_resend(stream, inbox));
+                }
+            });
+            FakeResend {
+                url: format!("http://127.0.0.1:{port}"),
+                sent,
+            }
+        })
+    }
+
+    /// The most recent email sent to `to`, as posted to the API.
+    pub fn last_sent_to(&self, to: &str) -> Option<Value> {
+        let sent = self.sent.lock().unwrap();
+        sent.iter()
+            .rev()
+            .find(|email| {
+                email["to"]
+                    .as_array()
+                    .is_some_and(|a| a.iter().any(|t| t == to))
+            })
+            .cloned()
+    }
+
+    /// The code in the most recent email sent to `to`.
+    pub fn last_code_sent_to(&self, to: &str) -> String {
+        self.last_sent_to(to)
+            .and_then(|email| extract_code(email["html"].as_str()?))
+            .unwrap_or_else(|| panic!("no verification email was sent to {to}"))
+    }
+}
+
+fn serve_resend(stream: TcpStream, inbox: Arc<Mutex<Vec<Value>>>) {
+    let mut writer = stream.try_clone().expect("failed to clone stream");
+    let mut reader = BufReader::new(stream);
+    while let Some(request) = read_http_request(&mut reader) {
+        if let Ok(email) = serde_json::from_slice(&request.body) {
+            inbox.lock().unwrap().push(email);
+        }
+        let reply = r#"{"id":"00000000-0000-0000-0000-000000000000"}"#;
+        if !write_http_response(&mut writer, "200 OK", reply) {
+            return;
+        }
+    }
+}
+
+/// An OpenID Connect provider with discovery, token and userinfo endpoints.
+///
+/// Tests play the part of the browser: they take the `state` and PKCE
+/// challenge from the redirect to the provider, [`grant`](Self::grant) an
+/// authorization code for some claims, and call the callback with it.
+pub struct FakeOidc {
+    url: String,
+    grants: Arc<Mutex<HashMap<String, Grant>>>,
+}
+
+struct Grant {
+    challenge: String,
+    claims: Value,
+}
+
+impl FakeOidc {
+    fn start() -> FakeOidc {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind fake oidc");
+        let url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+        let grants = Arc::new(Mutex::new(HashMap::new()));
+        let (issuer, shared) = (url.clone(), grants.clone());
+        std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let (issuer, grants) = (issuer.clone(), shared.clone());
+                std::thread::spawn(move || serve_oidc(stream, &issuer, grants));
+            }
+        });
+        FakeOidc { url, grants }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Answer `code` with `claims` from the userinfo endpoint, provided the
+    /// token request proves it holds the verifier for `challenge`.
+    pub fn grant(&self, code: &str, challenge: &str, claims: Value) {
+        self.grants.lock().unwrap().insert(
+            code.to_owned(),
+            Grant {
+                challenge: challenge.to_owned(),
+                claims,
+            },
+        );
+    }
+}
+
+fn serve_oidc(stream: TcpStream, issuer: &str, grants: Arc<Mutex<HashMap<String, Grant>>>) {
+    use base64::Engine;
+    use sha2::Digest;
+
+    let mut writer = stream.try_clone().expect("failed to clone stream");
+    let mut reader = BufReader::new(stream);
+    while let Some(request) = read_http_request(&mut reader) {
+        let path = request.target.split('?').next().unwrap_or_default();
+        let (status, body) = match (request.method.as_str(), path) {
+            ("GET", "/.well-known/openid-configuration") => (
+                "200 OK",
+                serde_json::json!({
+                    "issuer": issuer,
+                    "authorization_endpoint": format!("{issuer}/authorize"),
+                    "token_endpoint": format!("{issuer}/token"),
+                    "userinfo_endpoint": format!("{issuer}/userinfo"),
+                }),
+            ),
+            ("POST", "/token") => {
+                let form = parse_form(&request.body);
+                let code = form.get("code").cloned().unwrap_or_default();
+                let verifier = form.get("code_verifier").cloned().unwrap_or_default();
+                let challenge = base64::engine::general_purpose::URL_SAFE_NO_PAD
+                    .encode(sha2::Sha256::digest(verifier.as_bytes()));
+                let grants = grants.lock().unwrap();
+                match grants.get(&code) {
+                    Some(grant)
+                        if grant.challenge == challenge
+                            && form.get("client_secret").map(String::as_str)
+                                == Some(OIDC_CLIENT_SECRET) =>
+                    {
+                        (
+                            "200 OK",
+                            serde_json::json!({
+                                "access_token": format!("at-{code}"),
+                                "token_type": "Bearer",
+                            }),
+                        )
+                    }
+                    _ => (
+                        "400 Bad Request",
+                        serde_json::json!({"error": "invalid_grant"}),
+                    ),
+                }
+            }
+            ("GET", "/userinfo") => {
+                let code = request
+                    .header("authorization")
+                    .and_then(|v| v.strip_prefix("Bearer at-"))
+                    .unwrap_or_default();
+                match grants.lock().unwrap().get(code) {
+                    Some(grant) => ("200 OK", grant.claims.clone()),
+                    None => (
+                        "401 Unauthorized",
+                        serde_json::json!({"error": "invalid_token"}),
+                    ),
+                }
+            }
+            _ => ("404 Not Found", serde_json::json!({})),
+        };
+        if !write_http_response(&mut writer, status, &body.to_string()) {
+            return;
+        }
+    }
+}
+
+const OIDC_CLIENT_SECRET: &str = "mock-client-secret";
+
+/// Just enough of an HTTP/1.1 request for the fake servers.
+struct HttpRequest {
+    method: String,
+    target: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl HttpRequest {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+fn read_http_request(reader: &mut impl BufRead) -> Option<HttpRequest> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).ok()? == 0 {
+        return None;
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_owned();
+    let target = parts.next()?.to_owned();
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        if line == "\r\n" {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    let mut request = HttpRequest {
+        method,
+        target,
+        headers,
+        body: Vec::new(),
+    };
+    let length = request
+        .header("content-length")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(0);
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body).ok()?;
+    Some(request)
+}
+
+fn parse_form(body: &[u8]) -> HashMap<String, String> {
+    let mut url = reqwest::Url::parse("http://form.invalid/").unwrap();
+    url.set_query(Some(&String::from_utf8_lossy(body)));
+    url.query_pairs().into_owned().collect()
+}
+
+fn write_http_response(writer: &mut impl Write, status: &str, body: &str) -> bool {
+    let response = format!(
+        "HTTP/1.1 {status}\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{body}",
+        body.len()
+    );
+    writer.write_all(response.as_bytes()).is_ok()
+}
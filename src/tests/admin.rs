@@ -0,0 +1,483 @@
+//! The `/admin` console.
+
+use crate::home::access::{self, Role};
+
+use super::TestApp;
+
+/// Register `root` and make them an admin.
+async fn admin(app: &TestApp) -> String {
+    let token = app
+        .register("root", "root@example.com", "secret-root")
+        .await;
+    access::set_role_by_name(app.state.db(), "root", Role::Admin)
+        .await
+        .expect("failed to appoint admin");
+    token
+}
+
+async fn user_id(app: &TestApp, name: &str) -> i64 {
+    app.scalar(&format!("SELECT id FROM user_tb WHERE name = '{name}'"))
+        .await
+}
+
+async fn post_article(app: &TestApp, token: &str, title: &str, level: &str) -> i64 {
+    let r = app
+        .post(
+            "/add",
+            &[
+                ("tag", "1"),
+                ("title", title),
+                ("content", "body"),
+                ("level", level),
+            ],
+            Some(token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    app.scalar("SELECT MAX(id) FROM article_tb").await
+}
+
+#[tokio::test]
+async fn only_admins_get_in() {
+    let app = TestApp::new().await;
+    let editor = app.register("pete", "pete@example.com", "secret-one").await;
+    access::set_role_by_name(app.state.db(), "pete", Role::Editor)
+        .await
+        .unwrap();
+
+    for token in [None, Some(editor.as_str())] {
+        let (_, page) = app.get("/admin/users/1", token).await;
+        assert!(page.contains("没有权限执行此操作"));
+        let r = app.post("/admin/tags", &[("name", "x")], token).await;
+        assert_eq!(r["msg"], "没有权限执行此操作", "{r}");
+    }
+
+    let root = admin(&app).await;
+    for path in [
+        "/admin/users/1",
+        "/admin/articles/1",
+        "/admin/comments/1",
+        "/admin/tags",
+        "/admin/levels",
+    ] {
+        let (status, page) = app.get(path, Some(&root)).await;
+        assert_eq!(status, salvo::http::StatusCode::OK, "{path}");
+        assert!(page.contains("后台管理"), "{path}");
+    }
+}
+
+#[tokio::test]
+async fn editors_only_get_the_moderation_pages() {
+    let app = TestApp::new().await;
+    let editor = app.register("vic", "vic@example.com", "secret-one").await;
+    access::set_role_by_name(app.state.db(), "vic", Role::Editor)
+        .await
+        .unwrap();
+    let author = app.register("wes", "wes@example.com", "secret-two").await;
+    let id = post_article(&app, &author, "Off topic", "1").await;
+    let r = app
+        .post(
+            &format!("/comment/{id}"),
+            &[("comment", "<p>hello</p>"), ("md_content", "hello")],
+            Some(&author),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let comment = app.scalar("SELECT MAX(id) FROM comment_tb").await;
+
+    let (_, page) = app.get("/admin/articles/1", Some(&editor)).await;
+    assert!(page.contains("Off topic"), "{page}");
+    assert!(!page.contains("admin/users/1"), "no link to the users page");
+    let r = app
+        .post(
+            &format!("/admin/articles/{id}/hide"),
+            &[("hidden", "true")],
+            Some(&editor),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let r = app
+        .post(
+            &format!("/admin/comments/{comment}/delete"),
+            &[],
+            Some(&editor),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM comment_tb").await, 0);
+
+    let (_, page) = app.get("/admin/levels", Some(&editor)).await;
+    assert!(page.contains("没有权限执行此操作"));
+    let user = user_id(&app, "wes").await;
+    let r = app
+        .post(
+            &format!("/admin/users/{user}/role"),
+            &[("role", "admin")],
+            Some(&editor),
+        )
+        .await;
+    assert_eq!(r["msg"], "没有权限执行此操作", "{r}");
+
+    // Authors have no section to land on
+    let (_, page) = app.get("/admin", Some(&author)).await;
+    assert!(page.contains("没有权限执行此操作"));
+}
+
+#[tokio::test]
+async fn users_can_be_searched_and_paged() {
+    let app = TestApp::new().await;
+    let root = admin(&app).await;
+    for n in 0..11 {
+        app.execute(&format!(
+            "INSERT INTO user_tb (name, email, privilege) VALUES ('member{n}', 'member{n}@example.com', 2)"
+        ))
+        .await;
+    }
+    let (_, page) = app.get("/admin/users/1?q=member1", Some(&root)).await;
+    assert!(page.contains("member10") && !page.contains("member2@"));
+    let (_, page) = app.get("/admin/users/2", Some(&root)).await;
+    assert!(page.contains("member10") && !page.contains("root@example.com"));
+    let (_, page) = app.get("/admin/users/3", Some(&root)).await;
+    assert!(page.contains("请求的资源不存在"));
+}
+
+#[tokio::test]
+async fn roles_change_but_not_your_own() {
+    let app = TestApp::new().await;
+    let root = admin(&app).await;
+    app.register("quinn", "quinn@example.com", "secret-two")
+        .await;
+    let quinn = user_id(&app, "quinn").await;
+
+    let r = app
+        .post(
+            &format!("/admin/users/{quinn}/role"),
+            &[("role", "editor")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let privilege = app
+        .scalar(&format!("SELECT privilege FROM user_tb WHERE id = {quinn}"))
+        .await;
+    assert_eq!(privilege, 3);
+
+    let r = app
+        .post(
+            &format!("/admin/users/{quinn}/role"),
+            &[("role", "overlord")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["msg"], "未知的角色", "{r}");
+
+    let me = user_id(&app, "root").await;
+    let r = app
+        .post(
+            &format!("/admin/users/{me}/role"),
+            &[("role", "reader")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["msg"], "不能修改自己的账号", "{r}");
+}
+
+#[tokio::test]
+async fn banned_users_are_signed_out_and_cannot_sign_in() {
+    let app = TestApp::new().await;
+    let root = admin(&app).await;
+    let token = app
+        .register("rita", "rita@example.com", "secret-three")
+        .await;
+    let rita = user_id(&app, "rita").await;
+
+    let r = app
+        .post(
+            &format!("/admin/users/{rita}/status"),
+            &[("status", "banned"), ("reason", "spam")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let (_, page) = app.get("/profile", Some(&token)).await;
+    assert!(page.contains("没有权限执行此操作"), "session was revoked");
+    let r = app.login("rita", "secret-three").await;
+    assert_eq!(r["msg"], "该账号已被封禁, 原因: spam", "{r}");
+
+    let r = app
+        .post(
+            &format!("/admin/users/{rita}/status"),
+            &[("status", "active")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let r = app.login("rita", "secret-three").await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn password_reset_hands_out_a_temporary_password() {
+    let app = TestApp::new().await;
+    let root = admin(&app).await;
+    let token = app.register("sam", "sam@example.com", "secret-four").await;
+    let sam = user_id(&app, "sam").await;
+
+    let r = app
+        .post(&format!("/admin/users/{sam}/password"), &[], Some(&root))
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let temporary = r["password"].as_str().expect("password").to_owned();
+    assert_eq!(temporary.len(), 12);
+
+    let (_, page) = app.get("/profile", Some(&token)).await;
+    assert!(page.contains("没有权限执行此操作"), "session was revoked");
+    let r = app.login("sam", "secret-four").await;
+    assert_ne!(r["code"], 200, "{r}");
+    let r = app.login("sam", &temporary).await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn articles_and_comments_are_moderated() {
+    let app = TestApp::new().await;
+    let root = admin(&app).await;
+    let author = app
+        .register("tina", "tina@example.com", "secret-five")
+        .await;
+    let id = post_article(&app, &author, "Spam", "1").await;
+    let r = app
+        .post(
+            &format!("/comment/{id}"),
+            &[("comment", "<p>buy now</p>"), ("md_content", "buy now")],
+            Some(&author),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let comment = app.scalar("SELECT MAX(id) FROM comment_tb").await;
+
+    let (_, page) = app.get("/admin/comments/1?q=buy", Some(&root)).await;
+    assert!(page.contains("buy now") && page.contains("Spam"));
+    let r = app
+        .post(
+            &format!("/admin/comments/{comment}/delete"),
+            &[],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM comment_tb").await, 0);
+
+    let r = app
+        .post(
+            &format!("/admin/articles/{id}/hide"),
+            &[("hidden", "true")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let (_, page) = app.get("/home/1", None).await;
+    assert!(!page.contains("Spam"));
+    let (_, page) = app.get("/admin/articles/1", Some(&root)).await;
+    assert!(page.contains("Spam") && page.contains("已隐藏"));
+
+    // Nor can it be read by its URL or found on the author's page
+    let reader = app.register("uma", "uma@example.com", "secret-six").await;
+    for token in [None, Some(reader.as_str())] {
+        let (_, page) = app.get(&format!("/article/{id}"), token).await;
+        assert!(page.contains("该文章已被管理员隐藏"), "{page}");
+    }
+    let tina = user_id(&app, "tina").await;
+    let (_, page) = app.get(&format!("/user/{tina}/1"), None).await;
+    assert!(!page.contains("Spam"));
+    let (_, page) = app.get(&format!("/article/{id}"), Some(&author)).await;
+    assert!(
+        !page.contains("该文章已被管理员隐藏"),
+        "the author still sees it"
+    );
+
+    // Withdrawing and restoring it doesn't bring it back either
+    for _ in 0..2 {
+        let r = app.post(&format!("/delete/{id}"), &[], Some(&author)).await;
+        assert_eq!(r["code"], 200, "{r}");
+    }
+    let (_, page) = app.get("/home/1", None).await;
+    assert!(!page.contains("Spam"));
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("该文章已被管理员隐藏"));
+
+    let r = app
+        .post(
+            &format!("/admin/articles/{id}/hide"),
+            &[("hidden", "false")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let (_, page) = app.get("/home/1", None).await;
+    assert!(page.contains("Spam"));
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(!page.contains("该文章已被管理员隐藏"));
+}
+
+#[tokio::test]
+async fn tags_crud() {
+    let app = TestApp::new().await;
+    let root = admin(&app).await;
+
+    let r = app
+        .post("/admin/tags", &[("name", "Rust")], Some(&root))
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let r = app
+        .post("/admin/tags", &[("name", "Rust")], Some(&root))
+        .await;
+    assert_eq!(r["msg"], "该标签已存在", "{r}");
+    let r = app.post("/admin/tags", &[("name", " ")], Some(&root)).await;
+    assert_eq!(r["msg"], "请填写标签名称", "{r}");
+    let rust = app
+        .scalar("SELECT id FROM tag_tb WHERE name = 'Rust'")
+        .await;
+
+    let r = app
+        .post(
+            &format!("/admin/tags/{rust}"),
+            &[("name", "Rustlang")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let (_, page) = app.get("/admin/tags", Some(&root)).await;
+    assert!(page.contains("Rustlang"));
+
+    // The default tag carries an article and stays
+    post_article(&app, &root, "Tagged", "1").await;
+    let r = app.post("/admin/tags/1/delete", &[], Some(&root)).await;
+    assert_eq!(r["msg"], "该标签下还有文章, 不能删除", "{r}");
+    let r = app
+        .post(&format!("/admin/tags/{rust}/delete"), &[], Some(&root))
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM tag_tb").await, 1);
+}
+
+#[tokio::test]
+async fn levels_crud_moves_articles_along() {
+    let app = TestApp::new().await;
+    let root = admin(&app).await;
+
+    let r = app
+        .post(
+            "/admin/levels",
+            &[("level", "3"), ("name", "编辑可见")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let r = app
+        .post(
+            "/admin/levels",
+            &[("level", "3"), ("name", "again")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["msg"], "该阅读等级已存在", "{r}");
+    let level = app.scalar("SELECT id FROM level_tb WHERE level = 3").await;
+
+    let article = post_article(&app, &root, "Editors", "3").await;
+    let r = app
+        .post(
+            &format!("/admin/levels/{level}"),
+            &[("level", "4"), ("name", "管理员可见")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let moved = app
+        .scalar(&format!(
+            "SELECT level FROM article_tb WHERE id = {article}"
+        ))
+        .await;
+    assert_eq!(moved, 4);
+
+    let r = app
+        .post(&format!("/admin/levels/{level}/delete"), &[], Some(&root))
+        .await;
+    assert_eq!(r["msg"], "该阅读等级下还有文章, 不能删除", "{r}");
+    app.execute(&format!("DELETE FROM article_tb WHERE id = {article}"))
+        .await;
+    let r = app
+        .post(&format!("/admin/levels/{level}/delete"), &[], Some(&root))
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn levels_stay_within_what_roles_can_read() {
+    let app = TestApp::new().await;
+    let root = admin(&app).await;
+    for level in ["999", "5", "0"] {
+        let r = app
+            .post(
+                "/admin/levels",
+                &[("level", level), ("name", "odd")],
+                Some(&root),
+            )
+            .await;
+        assert_eq!(r["msg"], "阅读等级须在 1 到 4 之间", "{level}: {r}");
+    }
+
+    // The private level keeps its number and its row
+    let private = app
+        .scalar("SELECT id FROM level_tb WHERE level = 999")
+        .await;
+    let r = app
+        .post(
+            &format!("/admin/levels/{private}"),
+            &[("level", "3"), ("name", "leaked")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["msg"], "仅自己可见的等级不能修改数值", "{r}");
+    let r = app
+        .post(
+            &format!("/admin/levels/{private}"),
+            &[("level", "999"), ("name", "草稿")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "renaming is fine: {r}");
+    let r = app
+        .post(&format!("/admin/levels/{private}/delete"), &[], Some(&root))
+        .await;
+    assert_eq!(r["msg"], "仅自己可见的等级不能删除", "{r}");
+
+    // Articles only take levels that exist
+    let r = app
+        .post(
+            "/add",
+            &[
+                ("tag", "1"),
+                ("title", "Nowhere"),
+                ("content", "body"),
+                ("level", "4"),
+            ],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["msg"], "阅读等级不存在", "{r}");
+    let article = post_article(&app, &root, "Somewhere", "2").await;
+    let r = app
+        .post(
+            &format!("/edit/{article}"),
+            &[
+                ("tag", "1"),
+                ("title", "Somewhere"),
+                ("content", "body"),
+                ("level", "7"),
+            ],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["msg"], "阅读等级不存在", "{r}");
+}
@@ -0,0 +1,138 @@
+//! The reading rules enforced by `read_article`: level 1 is public, any other
+//! level needs a logged-in reader whose clearance reaches it, and 999 is
+//! reserved for the author.
+
+use super::TestApp;
+
+const DENIED: &str = "没有该文章的阅读权限";
+
+async fn post_article(app: &TestApp, token: &str, title: &str, level: &str) -> i64 {
+    let r = app
+        .post(
+            "/add",
+            &[
+                ("tag", "1"),
+                ("title", title),
+                ("content", "body"),
+                ("level", level),
+            ],
+            Some(token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    app.scalar("SELECT MAX(id) FROM article_tb").await
+}
+
+async fn can_read(app: &TestApp, id: i64, token: Option<&str>) -> bool {
+    let (_, page) = app.get(&format!("/article/{id}"), token).await;
+    !page.contains(DENIED)
+}
+
+#[tokio::test]
+async fn public_articles_are_readable_by_everyone() {
+    let app = TestApp::new().await;
+    let author = app
+        .register("erin", "erin@example.com", "secret-four")
+        .await;
+    let id = post_article(&app, &author, "Public", "1").await;
+
+    assert!(can_read(&app, id, None).await);
+    assert!(can_read(&app, id, Some("expired-or-forged")).await);
+    assert!(can_read(&app, id, Some(&author)).await);
+}
+
+#[tokio::test]
+async fn login_only_articles_follow_reader_clearance() {
+    let app = TestApp::new().await;
+    let author = app
+        .register("frank", "frank@example.com", "secret-five")
+        .await;
+    let reader = app
+        .register("grace", "grace@example.com", "secret-six")
+        .await;
+    let id = post_article(&app, &author, "Members", "2").await;
+
+    assert!(!can_read(&app, id, None).await);
+    assert!(!can_read(&app, id, Some("expired-or-forged")).await);
+    assert!(can_read(&app, id, Some(&reader)).await);
+
+    app.execute("UPDATE user_tb SET privilege = 1 WHERE name = 'grace'")
+        .await;
+    assert!(!can_read(&app, id, Some(&reader)).await);
+}
+
+#[tokio::test]
+async fn private_articles_are_only_readable_by_their_author() {
+    let app = TestApp::new().await;
+    let author = app
+        .register("heidi", "heidi@example.com", "secret-seven")
+        .await;
+    let other = app
+        .register("ivan", "ivan@example.com", "secret-eight")
+        .await;
+    let id = post_article(&app, &author, "Diary", "999").await;
+
+    assert!(can_read(&app, id, Some(&author)).await);
+    assert!(!can_read(&app, id, Some(&other)).await);
+    assert!(!can_read(&app, id, None).await);
+
+    app.execute("UPDATE user_tb SET clearance = 999 WHERE name = 'ivan'")
+        .await;
+    assert!(
+        !can_read(&app, id, Some(&other)).await,
+        "a high clearance does not unlock someone else's private article"
+    );
+}
+
+#[tokio::test]
+async fn stored_clearances_reach_past_the_roles() {
+    let app = TestApp::new().await;
+    let author = app
+        .register("olga", "olga@example.com", "secret-nine")
+        .await;
+    let reader = app.register("pete", "pete@example.com", "secret-ten").await;
+    let id = post_article(&app, &author, "Inner circle", "2").await;
+    app.execute(&format!("UPDATE article_tb SET level = 7 WHERE id = {id}"))
+        .await;
+
+    assert!(!can_read(&app, id, Some(&reader)).await);
+    app.execute("UPDATE user_tb SET clearance = 7 WHERE name = 'pete'")
+        .await;
+    assert!(can_read(&app, id, Some(&reader)).await);
+    assert!(
+        can_read(&app, id, Some(&author)).await,
+        "authors read their own articles whatever the level"
+    );
+}
+
+#[tokio::test]
+async fn only_the_author_can_edit_or_shadow() {
+    let app = TestApp::new().await;
+    let author = app
+        .register("judy", "judy@example.com", "secret-nine")
+        .await;
+    let other = app
+        .register("mallory", "mallory@example.com", "secret-ten")
+        .await;
+    let id = post_article(&app, &author, "Mine", "1").await;
+
+    let form = [
+        ("tag", "1"),
+        ("title", "Stolen"),
+        ("content", "x"),
+        ("level", "1"),
+    ];
+    let r = app.post(&format!("/edit/{id}"), &form, Some(&other)).await;
+    assert_ne!(r["code"], 200, "{r}");
+    let r = app.post(&format!("/delete/{id}"), &[], Some(&other)).await;
+    assert_ne!(r["code"], 200, "{r}");
+
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("Mine") && !page.contains("Stolen"));
+    let hidden = app
+        .scalar(&format!(
+            "SELECT COUNT(*) FROM article_tb WHERE id = {id} AND article_state = 0"
+        ))
+        .await;
+    assert_eq!(hidden, 0);
+}
@@ -0,0 +1,57 @@
+use super::TestApp;
+
+#[tokio::test]
+async fn login_rejects_wrong_password_and_unknown_user() {
+    let app = TestApp::new().await;
+    app.register("carol", "carol@example.com", "secret-three")
+        .await;
+
+    let r = app.login("carol", "not-the-password").await;
+    assert_eq!(r["code"], 400, "{r}");
+    let r = app.login("nobody", "secret-three").await;
+    assert_eq!(r["code"], 400, "{r}");
+    let r = app.login("carol", "secret-three").await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn legacy_md5_password_is_upgraded_on_login() {
+    let app = TestApp::new().await;
+    let legacy = format!("{:?}", md5::compute("old-secret"));
+    app.execute(&format!(
+        "INSERT INTO user_tb (name, email, password, privilege) \
+         VALUES ('dave', 'dave@example.com', '{legacy}', 2)"
+    ))
+    .await;
+
+    let r = app.login("dave", "old-secret").await;
+    assert_eq!(r["code"], 200, "{r}");
+    let upgraded = app
+        .scalar("SELECT COUNT(*) FROM user_tb WHERE name = 'dave' AND password LIKE '$argon2id$%'")
+        .await;
+    assert_eq!(upgraded, 1);
+
+    let r = app.login("dave", "old-secret").await;
+    assert_eq!(r["code"], 200, "the upgraded hash still verifies: {r}");
+}
+
+#[tokio::test]
+async fn guarded_routes_require_a_valid_token() {
+    let app = TestApp::new().await;
+    let form = [
+        ("tag", "1"),
+        ("title", "t"),
+        ("content", "c"),
+        ("level", "1"),
+    ];
+
+    let r = app.post("/add", &form, None).await;
+    assert_eq!(r["code"], 400, "{r}");
+    assert_eq!(r["msg"], "没有权限执行此操作");
+    let r = app.post("/add", &form, Some("garbage")).await;
+    assert_eq!(r["code"], 400, "{r}");
+
+    let (_, page) = app.get("/add", None).await;
+    assert!(page.contains("没有权限执行此操作"));
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM article_tb").await, 0);
+}
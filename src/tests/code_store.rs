@@ -0,0 +1,157 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::TestApp;
+use crate::home::code_store::{CodeStore, MemoryCodeStore, Take};
+
+async fn register(app: &TestApp, name: &str, email: &str, code: &str) -> Value {
+    app.post(
+        "/register",
+        &[
+            ("nickName", name),
+            ("password", "secret-pass"),
+            ("password2", "secret-pass"),
+            ("email", email),
+            ("code", code),
+        ],
+        None,
+    )
+    .await
+}
+
+/// Register `name` with a fresh code, then try to reuse that code for a second account.
+async fn assert_code_is_single_use(app: &TestApp, name: &str, email: &str) {
+    let r = app.post("/sendcode", &[("email", email)], None).await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to(email);
+    let r = register(app, name, email, &code).await;
+    assert_eq!(r["code"], 200, "{r}");
+    let r = register(app, "second-account", email, &code).await;
+    assert_eq!(r["code"], 400, "a used code must not register again: {r}");
+}
+
+#[tokio::test]
+async fn codes_in_memory_are_single_use() {
+    let app = TestApp::new().await;
+    assert_code_is_single_use(&app, "lena", "lena@example.com").await;
+}
+
+#[tokio::test]
+async fn codes_in_redis_are_single_use() {
+    let app = TestApp::with_redis().await;
+    assert_code_is_single_use(&app, "mike", "mike@example.com").await;
+}
+
+#[tokio::test]
+async fn password_reset_consumes_the_code() {
+    let app = TestApp::new().await;
+    app.register("nina", "nina@example.com", "secret-old").await;
+    let r = app
+        .post(
+            "/sendcode",
+            &[("email", "nina@example.com"), ("purpose", "reset")],
+            None,
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("nina@example.com");
+    let form = [
+        ("nickName", "nina"),
+        ("password", "secret-new"),
+        ("password2", "secret-new"),
+        ("email", "nina@example.com"),
+        ("code", code.as_str()),
+    ];
+
+    let r = app.post("/forget", &form, None).await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert_eq!(app.login("nina", "secret-new").await["code"], 200);
+    let r = app.post("/forget", &form, None).await;
+    assert_eq!(r["code"], 400, "{r}");
+}
+
+#[tokio::test]
+async fn memory_store_expires_codes() {
+    let store = MemoryCodeStore::default();
+    store
+        .put("a@example.com", "1234", Duration::from_millis(50))
+        .await
+        .unwrap();
+    store
+        .put("b@example.com", "5678", Duration::from_secs(60))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.get("a@example.com").await.unwrap().as_deref(),
+        Some("1234")
+    );
+
+    tokio::time::sleep(Duration::from_millis(80)).await;
+    assert_eq!(store.get("a@example.com").await.unwrap(), None);
+    assert_eq!(
+        store.get("b@example.com").await.unwrap().as_deref(),
+        Some("5678")
+    );
+    store.remove("b@example.com").await.unwrap();
+    assert_eq!(store.get("b@example.com").await.unwrap(), None);
+}
+
+#[tokio::test]
+async fn concurrent_checks_take_a_code_once() {
+    let store = Arc::new(MemoryCodeStore::default());
+    store
+        .put("c@example.com", "2468", Duration::from_secs(60))
+        .await
+        .unwrap();
+    assert_eq!(
+        store.take("c@example.com", "1357").await.unwrap(),
+        Take::Mismatch
+    );
+
+    let attempts: Vec<_> = (0..8)
+        .map(|_| {
+            let store = store.clone();
+            tokio::spawn(async move { store.take("c@example.com", "2468").await.unwrap() })
+        })
+        .collect();
+    let mut taken = 0;
+    for attempt in attempts {
+        if attempt.await.unwrap() == Take::Taken {
+            taken += 1;
+        }
+    }
+    assert_eq!(taken, 1);
+    assert_eq!(
+        store.take("c@example.com", "2468").await.unwrap(),
+        Take::Missing
+    );
+}
+
+#[tokio::test]
+async fn a_refused_registration_keeps_the_code() {
+    let app = TestApp::new().await;
+    app.register("olga", "olga@example.com", "secret-pass")
+        .await;
+    let r = app
+        .post("/sendcode", &[("email", "other@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("other@example.com");
+    let r = register(&app, "olga", "other@example.com", &code).await;
+    assert_eq!(r["msg"], "用户名已存在", "{r}");
+    let r = register(&app, "oscar", "other@example.com", &code).await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn sendcode_reports_an_unreachable_redis() {
+    let app = TestApp::with_redis().await;
+    app.stop_redis();
+    let r = app
+        .post("/sendcode", &[("email", "kim@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 400, "{r}");
+    assert_eq!(r["msg"], "验证码服务暂时不可用, 请稍后再试");
+}
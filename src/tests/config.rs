@@ -0,0 +1,105 @@
+//! Environment overrides of the config file.
+
+use std::collections::HashMap;
+
+use crate::config::{AppConfig, Cli};
+
+fn apply(vars: &[(&str, &str)]) -> anyhow::Result<AppConfig> {
+    let vars: HashMap<String, String> = vars
+        .iter()
+        .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+        .collect();
+    let mut config = AppConfig::default();
+    config.apply_env(|key| vars.get(key).cloned())?;
+    Ok(config)
+}
+
+#[test]
+fn numbers_and_rate_limits_come_from_the_environment() {
+    let config = apply(&[
+        ("BLOG_CODE_LENGTH", "8"),
+        ("BLOG_CODE_TTL_SECS", " 600 "),
+        ("BLOG_CODE_MAX_ATTEMPTS", "3"),
+        ("BLOG_REDIS_CONNECT_TIMEOUT_MS", "500"),
+        ("BLOG_REDIS_RESPONSE_TIMEOUT_MS", "250"),
+        ("BLOG_RATE_LIMIT_LOGIN_PER_ACCOUNT", "3/60"),
+    ])
+    .expect("valid overrides");
+    assert_eq!(config.code_length, 8);
+    assert_eq!(config.code_ttl_secs, 600);
+    assert_eq!(config.code_max_attempts, 3);
+    assert_eq!(config.redis_connect_timeout_ms, 500);
+    assert_eq!(config.redis_response_timeout_ms, 250);
+    assert_eq!(config.rate_limits.login_per_account.max, 3);
+    assert_eq!(config.rate_limits.login_per_account.window_secs, 60);
+    // The others keep their defaults
+    assert_eq!(config.rate_limits.login_per_ip.max, 30);
+}
+
+#[test]
+fn malformed_overrides_name_the_variable() {
+    let e = apply(&[("BLOG_CODE_TTL_SECS", "soon")])
+        .err()
+        .expect("error");
+    assert!(format!("{e:#}").contains("BLOG_CODE_TTL_SECS"), "{e:#}");
+    let e = apply(&[("BLOG_RATE_LIMIT_REGISTER_PER_IP", "10")])
+        .err()
+        .expect("error");
+    assert!(
+        format!("{e:#}").contains("BLOG_RATE_LIMIT_REGISTER_PER_IP"),
+        "{e:#}"
+    );
+}
+
+#[test]
+fn only_the_server_needs_the_secret_key_and_mail_credentials() {
+    let dir = tempfile::tempdir().expect("temp dir");
+    let path = dir.path().join("config.json");
+    std::fs::write(&path, r#"{"database_url": "sqlite::memory:"}"#).expect("write config");
+    let path = path.display().to_string();
+    for command in ["migrate", "sanitize"] {
+        let cli = Cli::parse_from(["--config".to_owned(), path.clone(), command.to_owned()])
+            .expect("arguments");
+        AppConfig::load(&cli).unwrap_or_else(|e| panic!("{command}: {e:#}"));
+    }
+    let cli = Cli::parse_from(["--config".to_owned(), path]).expect("arguments");
+    let e = AppConfig::load(&cli).err().expect("error");
+    assert!(format!("{e:#}").contains("secret_key"), "{e:#}");
+}
+
+#[test]
+fn secrets_in_the_environment_beat_secret_files_in_the_config() {
+    const FROM_FILE: &str = "secret-key-read-from-a-file-0123456789";
+    const FROM_ENV: &str = "secret-key-set-in-the-environment-0123";
+    let dir = tempfile::tempdir().expect("temp dir");
+    let secret = dir.path().join("secret_key");
+    std::fs::write(&secret, format!("{FROM_FILE}\n")).expect("write secret");
+    let path = dir.path().join("config.json");
+    let config = serde_json::json!({
+        "database_url": "sqlite::memory:",
+        "mail_transport": "file",
+        "secret_key_file": secret,
+    });
+    std::fs::write(&path, config.to_string()).expect("write config");
+    let cli =
+        Cli::parse_from(["--config".to_owned(), path.display().to_string()]).expect("arguments");
+    let load = |vars: &[(&str, &str)]| {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| ((*k).to_owned(), (*v).to_owned()))
+            .collect();
+        AppConfig::load_with(&cli, |key| vars.get(key).cloned())
+    };
+
+    let config = load(&[]).expect("valid config");
+    assert_eq!(config.secret_key, FROM_FILE);
+    let config = load(&[("BLOG_SECRET_KEY", FROM_ENV)]).expect("valid config");
+    assert_eq!(config.secret_key, FROM_ENV);
+    let e = load(&[
+        ("BLOG_SECRET_KEY", FROM_ENV),
+        ("BLOG_SECRET_KEY_FILE", "/nonexistent"),
+    ])
+    .err()
+    .expect("error");
+    assert!(format!("{e:#}").contains("BLOG_SECRET_KEY_FILE"), "{e:#}");
+}
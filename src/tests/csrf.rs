@@ -0,0 +1,85 @@
+//! Cross-site request forgery protection.
+
+use salvo::test::ResponseExt;
+use salvo::test::TestClient;
+use serde_json::Value;
+
+use crate::home::csrf;
+
+use super::TestApp;
+
+async fn post_raw(app: &TestApp, cookie: Option<&str>, header: Option<&str>) -> Value {
+    let mut builder = TestClient::post(TestApp::url("/login"));
+    if let Some(cookie) = cookie {
+        builder = builder.add_header("cookie", format!("{}={cookie}", csrf::COOKIE), true);
+    }
+    if let Some(header) = header {
+        builder = builder.add_header(csrf::HEADER, header, true);
+    }
+    let mut res = builder
+        .form(&[
+            ("nickName", "uma"),
+            ("password", "secret-uma"),
+            ("rememberMe", "false"),
+        ])
+        .send(&app.service)
+        .await;
+    serde_json::from_str(&res.take_string().await.unwrap_or_default()).expect("JSON reply")
+}
+
+#[tokio::test]
+async fn posts_without_a_matching_token_are_turned_away() {
+    let app = TestApp::new().await;
+    app.register("uma", "uma@example.com", "secret-uma").await;
+    let token = csrf::issue(app.state.secret_key());
+    let other = csrf::issue(app.state.secret_key());
+    let forged = format!("{}.{}", "0".repeat(32), "0".repeat(64));
+
+    for (cookie, header) in [
+        (None, None),
+        (Some(token.as_str()), None),
+        (None, Some(token.as_str())),
+        (Some(token.as_str()), Some(other.as_str())),
+        (Some(forged.as_str()), Some(forged.as_str())),
+    ] {
+        let r = post_raw(&app, cookie, header).await;
+        assert_eq!(
+            r["msg"], "页面已过期, 请刷新后重试",
+            "{cookie:?} {header:?}"
+        );
+    }
+    let r = post_raw(&app, Some(&token), Some(&token)).await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn pages_hand_out_the_token_they_embed() {
+    let app = TestApp::new().await;
+    let mut res = TestClient::get(TestApp::url("/login"))
+        .send(&app.service)
+        .await;
+    let cookie = res
+        .cookie(csrf::COOKIE)
+        .map(|c| c.value().to_owned())
+        .expect("csrf cookie");
+    assert!(csrf::is_valid(app.state.secret_key(), &cookie));
+    let page = res.take_string().await.unwrap_or_default();
+    assert!(page.contains(&format!(r#"<meta name="csrf-token" content="{cookie}" />"#)));
+}
+
+#[tokio::test]
+async fn the_token_is_also_taken_from_the_query() {
+    let app = TestApp::new().await;
+    let token = app.register("vic", "vic@example.com", "secret-vic").await;
+    let issued = csrf::issue(app.state.secret_key());
+    let mut res = TestClient::post(TestApp::url(&format!("/upload?{}={issued}", csrf::FIELD)))
+        .add_header(
+            "cookie",
+            format!("{}={issued}; token={token}", csrf::COOKIE),
+            true,
+        )
+        .send(&app.service)
+        .await;
+    let body = res.take_string().await.unwrap_or_default();
+    assert!(!body.contains("页面已过期"), "{body}");
+}
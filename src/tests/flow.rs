@@ -0,0 +1,130 @@
+use super::TestApp;
+
+#[tokio::test]
+async fn register_write_comment_edit_shadow_and_search() {
+    let app = TestApp::new().await;
+    app.register("alice", "alice@example.com", "secret-one")
+        .await;
+
+    let r = app.login("alice", "secret-one").await;
+    assert_eq!(r["code"], 200, "{r}");
+    let token = r["token"].as_str().unwrap().to_owned();
+
+    let r = app
+        .post(
+            "/add",
+            &[
+                ("tag", "1"),
+                ("title", "Borrow checker notes"),
+                ("content", "lifetimes are *regions*"),
+                ("level", "1"),
+            ],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let id = app.scalar("SELECT MAX(id) FROM article_tb").await;
+
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("Borrow checker notes"));
+    assert!(page.contains("lifetimes are <em>regions</em>"));
+
+    let r = app
+        .post(
+            &format!("/comment/{id}"),
+            &[("comment", "<p>nice post</p>"), ("md_content", "nice post")],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let (_, page) = app.get(&format!("/article/{id}"), Some(&token)).await;
+    assert!(page.contains("nice post"));
+
+    let r = app
+        .post(
+            &format!("/edit/{id}"),
+            &[
+                ("tag", "1"),
+                ("title", "Ownership notes"),
+                ("content", "moves and borrows"),
+                ("level", "1"),
+            ],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let (_, page) = app.get("/home/1", None).await;
+    assert!(page.contains("Ownership notes"));
+    assert!(!page.contains("Borrow checker notes"));
+
+    let (_, page) = app.get("/search/1/?query=Ownership", None).await;
+    assert!(page.contains("Ownership notes"));
+    let (_, page) = app.get("/search/1/?query=borrows", None).await;
+    assert!(page.contains("Ownership notes"), "content is searched too");
+
+    let r = app.post(&format!("/delete/{id}"), &[], Some(&token)).await;
+    assert_eq!(r["code"], 200, "{r}");
+    let (_, page) = app.get("/search/1/?query=Ownership", None).await;
+    assert!(!page.contains("Ownership notes"));
+    let (_, page) = app.get("/home/1", None).await;
+    assert!(!page.contains("Ownership notes"));
+    let (_, page) = app.get("/list/1", Some(&token)).await;
+    assert!(
+        page.contains("Ownership notes"),
+        "the author still lists it"
+    );
+}
+
+#[tokio::test]
+async fn register_rejects_wrong_code_and_taken_name() {
+    let app = TestApp::new().await;
+    let r = app
+        .post("/sendcode", &[("email", "bob@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("bob@example.com");
+    let wrong = if code == "0000" { "1111" } else { "0000" };
+
+    let form = |code: &'static str| {
+        [
+            ("nickName", "bob"),
+            ("password", "secret-two"),
+            ("password2", "secret-two"),
+            ("email", "bob@example.com"),
+            ("code", code),
+        ]
+    };
+    let r = app.post("/register", &form(wrong), None).await;
+    assert_eq!(r["code"], 400, "{r}");
+
+    app.register("bob", "bob@example.com", "secret-two").await;
+    let r = app
+        .post("/sendcode", &[("email", "bob@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("bob@example.com");
+    let r = app
+        .post(
+            "/register",
+            &[
+                ("nickName", "bob"),
+                ("password", "secret-two"),
+                ("password2", "secret-two"),
+                ("email", "bob@example.com"),
+                ("code", &code),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(r["code"], 400, "{r}");
+    assert_eq!(r["msg"], "用户名已存在");
+}
+
+#[tokio::test]
+async fn invalid_email_is_rejected_before_sending() {
+    let app = TestApp::new().await;
+    let r = app
+        .post("/sendcode", &[("email", "not-an-email")], None)
+        .await;
+    assert_eq!(r["code"], 400, "{r}");
+}
@@ -0,0 +1,61 @@
+use super::{FakeResend, TestApp};
+use crate::config::{AppConfig, MailTransport};
+
+#[tokio::test]
+async fn verification_email_uses_template_and_sender() {
+    let app = TestApp::build(None, |config| {
+        config.mail_from = "Notes <notes@example.org>".to_owned();
+    })
+    .await;
+    let r = app
+        .post("/sendcode", &[("email", "olga@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+
+    let sent = app.sent_mail();
+    assert_eq!(sent.len(), 1);
+    let message = &sent[0];
+    assert!(message.starts_with("From: Notes <notes@example.org>\nTo: olga@example.com\n"));
+    assert!(message.contains("验证码5分钟内有效"), "{message}");
+    let code = app.last_code_sent_to("olga@example.com");
+    assert!(message.contains(&format!(">{code}</strong>")), "{message}");
+}
+
+#[tokio::test]
+async fn resend_transport_posts_to_the_api() {
+    let app = TestApp::with_resend().await;
+    app.register("pete", "pete@example.com", "secret-pass")
+        .await;
+
+    let email = FakeResend::global()
+        .last_sent_to("pete@example.com")
+        .unwrap();
+    assert_eq!(email["from"], "Blog <blog@resend.dev>");
+    assert_eq!(email["subject"], "你好，邮箱验证码");
+    assert!(app.sent_mail().is_empty());
+}
+
+#[tokio::test]
+async fn delivery_failure_is_reported_to_the_user() {
+    let app = TestApp::build(None, |config| {
+        config.mail_transport = MailTransport::Smtp;
+        // Nothing listens on the discard port, so the connection is refused
+        config.smtp_url = "smtp://127.0.0.1:9".to_owned();
+    })
+    .await;
+    let r = app
+        .post("/sendcode", &[("email", "quinn@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 400, "{r}");
+    assert_eq!(r["msg"], "验证码邮件发送失败, 请稍后再试");
+}
+
+#[test]
+fn malformed_sender_is_rejected_at_startup() {
+    let config = AppConfig {
+        mail_transport: MailTransport::File,
+        mail_from: "not an address".to_owned(),
+        ..AppConfig::default()
+    };
+    assert!(crate::home::mailer::from_config(&config).is_err());
+}
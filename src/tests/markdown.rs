@@ -0,0 +1,186 @@
+//! Server-side Markdown rendering of articles and comments.
+
+use super::TestApp;
+
+async fn post_article(app: &TestApp, token: &str, content: &str) -> i64 {
+    let r = app
+        .post(
+            "/add",
+            &[
+                ("tag", "1"),
+                ("title", "Rendered"),
+                ("content", content),
+                ("level", "1"),
+            ],
+            Some(token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    app.scalar("SELECT MAX(id) FROM article_tb").await
+}
+
+#[tokio::test]
+async fn articles_and_comments_are_rendered_on_the_server() {
+    let app = TestApp::new().await;
+    let token = app.register("zoe", "zoe@example.com", "secret-zoe").await;
+    let id = post_article(
+        &app,
+        &token,
+        "## Getting Started\n\n| a | b |\n|---|---|\n| 1 | 2 |\n\n- [x] done\n- [ ] todo\n\nSee the note[^note] and ~~old~~.\n\n## Getting started\n\n[^note]: The note.\n",
+    )
+    .await;
+    let r = app
+        .post(
+            &format!("/comment/{id}"),
+            &[("comment", ""), ("md_content", "# Reply\n\n*nice*")],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let comment = app.scalar("SELECT MAX(id) FROM comment_tb").await;
+
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    let anchor = format!(
+        r##"<h2 id="getting-started">Getting Started<a class="heading-anchor" href="article/{id}#getting-started" rel="noopener noreferrer">#</a></h2>"##
+    );
+    assert!(page.contains(&anchor), "{page}");
+    assert!(page.contains(r#"<h2 id="getting-started-1">"#), "{page}");
+    assert!(page.contains("<td>1</td>"), "{page}");
+    assert!(
+        page.contains(r#"<input disabled="" checked="" type="checkbox">"#),
+        "{page}"
+    );
+    assert!(page.contains("<del>old</del>"), "{page}");
+    assert!(
+        page.contains(&format!(
+            r##"<a href="article/{id}#fn-note" rel="noopener noreferrer">1</a>"##
+        )),
+        "{page}"
+    );
+    assert!(
+        page.contains(r#"<div class="footnote-definition" id="fn-note">"#),
+        "{page}"
+    );
+    assert!(
+        page.contains(&format!(r#"<h1 id="comment-{comment}-reply">"#)),
+        "{page}"
+    );
+    assert!(page.contains("<em>nice</em>"), "{page}");
+}
+
+#[tokio::test]
+async fn rendered_html_is_reused_until_the_article_changes() {
+    let app = TestApp::new().await;
+    let token = app.register("abe", "abe@example.com", "secret-abe").await;
+    let id = post_article(&app, &token, "first *draft*").await;
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("first <em>draft</em>"));
+
+    // Written behind the server's back, the stale rendering is still served
+    app.execute(&format!(
+        "UPDATE article_tb SET content = 'second *draft*' WHERE id = {id}"
+    ))
+    .await;
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("first <em>draft</em>"));
+    app.execute(&format!(
+        "UPDATE article_tb SET update_time = '2030-01-01 00:00:00' WHERE id = {id}"
+    ))
+    .await;
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("second <em>draft</em>"));
+
+    let r = app
+        .post(
+            &format!("/edit/{id}"),
+            &[
+                ("tag", "1"),
+                ("title", "Rendered"),
+                ("content", "third *draft*"),
+                ("level", "1"),
+            ],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("third <em>draft</em>"));
+}
+
+#[tokio::test]
+async fn code_blocks_are_highlighted_with_marked_lines() {
+    let app = TestApp::new().await;
+    let token = app.register("bea", "bea@example.com", "secret-bea").await;
+    let id = post_article(
+        &app,
+        &token,
+        "```rust {2}\nfn main() {\n    let s = \"a<b\";\n}\n```\n\n```nosuchlang\nplain <text>\n```\n",
+    )
+    .await;
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("public/css/highlight/inspired-github.css"));
+    assert!(page.contains(r#"<pre class="hl-code"><code class="language-rust"><span class="line"><span class="hl-source hl-rust">"#), "{page}");
+    assert!(
+        page.contains(r#"<span class="hl-storage hl-type hl-function hl-rust">fn</span>"#),
+        "{page}"
+    );
+    // The second line is marked, and opens the spans left open by the first again
+    assert!(page.contains(r#"<span class="line hl-marked"><span class="hl-source hl-rust"><span class="hl-meta hl-function hl-rust">"#), "{page}");
+    assert!(page.contains("a&lt;b"), "{page}");
+    assert!(
+        page.contains(r#"<code class="language-nosuchlang"><span class="line">plain &lt;text&gt;"#),
+        "{page}"
+    );
+}
+
+#[tokio::test]
+async fn math_is_rendered_as_mathml() {
+    let app = TestApp::new().await;
+    let token = app.register("cyd", "cyd@example.com", "secret-cyd").await;
+    let id = post_article(
+        &app,
+        &token,
+        "Euler: $e^{i\\pi} + 1 = 0$ and a sum\n\n$$\\sum_{k=1}^{n} k = \\frac{n(n+1)}{2}$$\n\nBroken $\\frac{1}$ and $\\nosuch x$, then the rest.\n",
+    )
+    .await;
+    let r = app
+        .post(
+            &format!("/comment/{id}"),
+            &[
+                ("comment", ""),
+                ("md_content", "Also $\\sqrt{x} < \\alpha$"),
+            ],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(
+        page.contains("<math><semantics><mrow><msup><mi>e</mi><mrow><mi>i</mi><mi>π</mi></mrow></msup><mo>+</mo><mn>1</mn><mo>=</mo><mn>0</mn></mrow><annotation encoding=\"application/x-tex\">e^{i\\pi} + 1 = 0</annotation></semantics></math>"),
+        "{page}"
+    );
+    assert!(
+        page.contains(r#"<math display="block"><semantics><mrow><munderover><mo largeop="true" movablelimits="true">∑</mo>"#),
+        "{page}"
+    );
+    assert!(
+        page.contains("<mfrac><mrow><mi>n</mi><mo>(</mo><mi>n</mi><mo>+</mo><mn>1</mn><mo>)</mo></mrow><mn>2</mn></mfrac>"),
+        "{page}"
+    );
+    // Mistakes are marked where they are and the page carries on
+    assert!(
+        page.contains(
+            r#"<span class="math-error" title="公式有误: \frac 缺少参数">$\frac{1}$</span>"#
+        ),
+        "{page}"
+    );
+    assert!(
+        page.contains(r#"<span class="math-error" title="公式有误: 未知的命令 \nosuch">$\nosuch x$</span>, then the rest."#),
+        "{page}"
+    );
+    assert!(
+        page.contains("<msqrt><mi>x</mi></msqrt><mo>&lt;</mo><mi>α</mi>"),
+        "{page}"
+    );
+}
@@ -0,0 +1,509 @@
+//! TeX math to MathML.
+
+use crate::home::math::{render, to_mathml};
+
+/// How deep the converter lets formulas nest.
+const MAX_DEPTH: usize = 64;
+
+/// The MathML between `<semantics>` and the annotation.
+fn body(tex: &str) -> String {
+    let mathml = to_mathml(tex, false).unwrap_or_else(|e| panic!("{tex}: {e}"));
+    let start = mathml.find("<semantics>").expect("semantics") + "<semantics>".len();
+    let end = mathml.find("<annotation").expect("annotation");
+    mathml[start..end].to_owned()
+}
+
+fn error(tex: &str) -> String {
+    to_mathml(tex, false).expect_err(tex)
+}
+
+#[test]
+fn every_symbol_command_renders() {
+    let symbols = [
+        ("alpha", "α"),
+        ("beta", "β"),
+        ("gamma", "γ"),
+        ("delta", "δ"),
+        ("epsilon", "ϵ"),
+        ("varepsilon", "ε"),
+        ("zeta", "ζ"),
+        ("eta", "η"),
+        ("theta", "θ"),
+        ("vartheta", "ϑ"),
+        ("iota", "ι"),
+        ("kappa", "κ"),
+        ("lambda", "λ"),
+        ("mu", "μ"),
+        ("nu", "ν"),
+        ("xi", "ξ"),
+        ("omicron", "ο"),
+        ("pi", "π"),
+        ("varpi", "ϖ"),
+        ("rho", "ρ"),
+        ("varrho", "ϱ"),
+        ("sigma", "σ"),
+        ("varsigma", "ς"),
+        ("tau", "τ"),
+        ("upsilon", "υ"),
+        ("phi", "ϕ"),
+        ("varphi", "φ"),
+        ("chi", "χ"),
+        ("psi", "ψ"),
+        ("omega", "ω"),
+        ("Gamma", "Γ"),
+        ("Delta", "Δ"),
+        ("Theta", "Θ"),
+        ("Lambda", "Λ"),
+        ("Xi", "Ξ"),
+        ("Pi", "Π"),
+        ("Sigma", "Σ"),
+        ("Upsilon", "Υ"),
+        ("Phi", "Φ"),
+        ("Psi", "Ψ"),
+        ("Omega", "Ω"),
+        ("infty", "∞"),
+        ("partial", "∂"),
+        ("nabla", "∇"),
+        ("emptyset", "∅"),
+        ("varnothing", "∅"),
+        ("hbar", "ℏ"),
+        ("ell", "ℓ"),
+        ("Re", "ℜ"),
+        ("Im", "ℑ"),
+        ("aleph", "ℵ"),
+        ("wp", "℘"),
+        ("imath", "ı"),
+        ("jmath", "ȷ"),
+        ("pm", "±"),
+        ("mp", "∓"),
+        ("times", "×"),
+        ("div", "÷"),
+        ("cdot", "⋅"),
+        ("ast", "∗"),
+        ("star", "⋆"),
+        ("circ", "∘"),
+        ("bullet", "∙"),
+        ("oplus", "⊕"),
+        ("ominus", "⊖"),
+        ("otimes", "⊗"),
+        ("odot", "⊙"),
+        ("cup", "∪"),
+        ("cap", "∩"),
+        ("setminus", "∖"),
+        ("wedge", "∧"),
+        ("land", "∧"),
+        ("vee", "∨"),
+        ("lor", "∨"),
+        ("neg", "¬"),
+        ("lnot", "¬"),
+        ("le", "≤"),
+        ("leq", "≤"),
+        ("ge", "≥"),
+        ("geq", "≥"),
+        ("ne", "≠"),
+        ("neq", "≠"),
+        ("ll", "≪"),
+        ("gg", "≫"),
+        ("approx", "≈"),
+        ("equiv", "≡"),
+        ("sim", "∼"),
+        ("simeq", "≃"),
+        ("cong", "≅"),
+        ("propto", "∝"),
+        ("in", "∈"),
+        ("notin", "∉"),
+        ("ni", "∋"),
+        ("subset", "⊂"),
+        ("supset", "⊃"),
+        ("subseteq", "⊆"),
+        ("supseteq", "⊇"),
+        ("forall", "∀"),
+        ("exists", "∃"),
+        ("nexists", "∄"),
+        ("to", "→"),
+        ("rightarrow", "→"),
+        ("leftarrow", "←"),
+        ("gets", "←"),
+        ("leftrightarrow", "↔"),
+        ("Rightarrow", "⇒"),
+        ("Leftarrow", "⇐"),
+        ("Leftrightarrow", "⇔"),
+        ("implies", "⟹"),
+        ("impliedby", "⟸"),
+        ("iff", "⟺"),
+        ("mapsto", "↦"),
+        ("uparrow", "↑"),
+        ("downarrow", "↓"),
+        ("longrightarrow", "⟶"),
+        ("longleftarrow", "⟵"),
+        ("ldots", "…"),
+        ("dots", "…"),
+        ("cdots", "⋯"),
+        ("vdots", "⋮"),
+        ("ddots", "⋱"),
+        ("prime", "′"),
+        ("angle", "∠"),
+        ("perp", "⊥"),
+        ("parallel", "∥"),
+        ("mid", "∣"),
+        ("vdash", "⊢"),
+        ("models", "⊨"),
+        ("top", "⊤"),
+        ("bot", "⊥"),
+        ("triangle", "△"),
+        ("colon", ":"),
+        ("%", "%"),
+        ("$", "$"),
+        ("#", "#"),
+        ("&", "&amp;"),
+        ("_", "_"),
+        (",", "\u{2009}"),
+        ("{", "{"),
+        ("}", "}"),
+        ("lbrace", "{"),
+        ("rbrace", "}"),
+        ("langle", "⟨"),
+        ("rangle", "⟩"),
+        ("lfloor", "⌊"),
+        ("rfloor", "⌋"),
+        ("lceil", "⌈"),
+        ("rceil", "⌉"),
+        ("vert", "|"),
+        ("lvert", "|"),
+        ("rvert", "|"),
+        ("|", "‖"),
+        ("Vert", "‖"),
+        ("lVert", "‖"),
+        ("rVert", "‖"),
+        ("backslash", "\\"),
+        ("sum", "∑"),
+        ("prod", "∏"),
+        ("coprod", "∐"),
+        ("bigcup", "⋃"),
+        ("bigcap", "⋂"),
+        ("bigoplus", "⨁"),
+        ("bigotimes", "⨂"),
+        ("bigvee", "⋁"),
+        ("bigwedge", "⋀"),
+        ("int", "∫"),
+        ("iint", "∬"),
+        ("iiint", "∭"),
+        ("oint", "∮"),
+    ];
+    for (name, symbol) in symbols {
+        let mathml = body(&format!("\\{name}"));
+        assert!(mathml.contains(symbol), "\\{name}: {mathml}");
+    }
+    let functions = [
+        "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh",
+        "tanh", "coth", "log", "ln", "lg", "exp", "arg", "deg", "dim", "hom", "ker", "mod", "lim",
+        "liminf", "limsup", "max", "min", "sup", "inf", "det", "gcd", "Pr", "argmax", "argmin",
+    ];
+    for name in functions {
+        let mathml = body(&format!("\\{name}"));
+        assert!(mathml.contains(&format!(">{name}<")), "\\{name}: {mathml}");
+    }
+    let spaces = [
+        (":", "0.2222em"),
+        (";", "0.2778em"),
+        ("!", "-0.1667em"),
+        (" ", "0.3333em"),
+        ("quad", "1em"),
+        ("qquad", "2em"),
+        ("enspace", "0.5em"),
+        ("thinspace", "0.1667em"),
+    ];
+    for (name, width) in spaces {
+        let mathml = body(&format!("\\{name}"));
+        assert_eq!(mathml, format!("<mspace width=\"{width}\"></mspace>"));
+    }
+}
+
+#[test]
+fn symbols_carry_their_attributes() {
+    assert_eq!(body("\\alpha"), "<mi>α</mi>");
+    assert_eq!(body("\\Gamma"), "<mi mathvariant=\"normal\">Γ</mi>");
+    assert_eq!(body("\\langle"), "<mo stretchy=\"false\">⟨</mo>");
+    assert_eq!(
+        body("\\sum"),
+        "<mo largeop=\"true\" movablelimits=\"true\">∑</mo>"
+    );
+    assert_eq!(body("\\int"), "<mo largeop=\"true\">∫</mo>");
+    assert_eq!(body("\\sin"), "<mi>sin</mi>");
+    assert_eq!(body("\\lim"), "<mo movablelimits=\"true\">lim</mo>");
+    assert_eq!(body("\\&"), "<mo>&amp;</mo>");
+}
+
+#[test]
+fn accents_and_fonts_take_an_argument() {
+    let accents = [
+        ("hat", "^", false),
+        ("widehat", "^", false),
+        ("check", "ˇ", false),
+        ("tilde", "~", false),
+        ("widetilde", "~", false),
+        ("bar", "¯", false),
+        ("overline", "¯", false),
+        ("vec", "→", false),
+        ("overrightarrow", "→", false),
+        ("overleftarrow", "←", false),
+        ("dot", "˙", false),
+        ("ddot", "¨", false),
+        ("acute", "´", false),
+        ("grave", "`", false),
+        ("breve", "˘", false),
+        ("overbrace", "⏞", false),
+        ("underline", "_", true),
+        ("underbrace", "⏟", true),
+    ];
+    for (name, mark, under) in accents {
+        let mathml = body(&format!("\\{name}{{x}}"));
+        let expected = if under {
+            format!(
+                "<munder accentunder=\"true\"><mi>x</mi><mo stretchy=\"true\">{mark}</mo></munder>"
+            )
+        } else {
+            format!("<mover accent=\"true\"><mi>x</mi><mo stretchy=\"true\">{mark}</mo></mover>")
+        };
+        assert_eq!(mathml, expected, "\\{name}");
+        assert_eq!(error(&format!("\\{name}")), format!("\\{name} 缺少参数"));
+    }
+    let fonts = [
+        ("mathrm", "normal"),
+        ("mathit", "italic"),
+        ("mathbf", "bold"),
+        ("boldsymbol", "bold-italic"),
+        ("bm", "bold-italic"),
+        ("mathbb", "double-struck"),
+        ("mathcal", "script"),
+        ("mathscr", "script"),
+        ("mathfrak", "fraktur"),
+        ("mathsf", "sans-serif"),
+        ("mathtt", "monospace"),
+    ];
+    for (name, variant) in fonts {
+        let mathml = body(&format!("\\{name}{{x1}}"));
+        assert_eq!(
+            mathml,
+            format!(
+                "<mrow><mi mathvariant=\"{variant}\">x</mi><mn mathvariant=\"{variant}\">1</mn></mrow>"
+            ),
+            "\\{name}"
+        );
+    }
+    // The font replaces the upright style of capital Greek letters
+    assert_eq!(body("\\mathbf{\\Gamma}"), "<mi mathvariant=\"bold\">Γ</mi>");
+}
+
+#[test]
+fn scripts() {
+    assert_eq!(body("x^2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+    assert_eq!(body("x_i"), "<msub><mi>x</mi><mi>i</mi></msub>");
+    assert_eq!(
+        body("x_i^{2}"),
+        "<msubsup><mi>x</mi><mi>i</mi><mn>2</mn></msubsup>"
+    );
+    assert_eq!(body("f''"), "<msup><mi>f</mi><mo>′′</mo></msup>");
+    assert_eq!(
+        body("\\sum_{i=1}^n"),
+        "<munderover><mo largeop=\"true\" movablelimits=\"true\">∑</mo><mrow><mi>i</mi><mo>=</mo><mn>1</mn></mrow><mi>n</mi></munderover>"
+    );
+    assert_eq!(
+        body("\\int_0^1"),
+        "<msubsup><mo largeop=\"true\">∫</mo><mn>0</mn><mn>1</mn></msubsup>"
+    );
+    assert_eq!(body("3.14"), "<mn>3.14</mn>");
+    assert_eq!(
+        body("x^12"),
+        "<mrow><msup><mi>x</mi><mn>1</mn></msup><mn>2</mn></mrow>",
+        "one digit, as in TeX"
+    );
+    assert_eq!(error("x^2^3"), "重复的上标");
+    assert_eq!(error("x_1_2"), "重复的下标");
+    assert_eq!(error("x^"), "^ 缺少参数");
+}
+
+#[test]
+fn fractions_roots_and_text() {
+    assert_eq!(body("\\frac{a}{b}"), "<mfrac><mi>a</mi><mi>b</mi></mfrac>");
+    assert_eq!(body("\\frac12"), "<mfrac><mn>1</mn><mn>2</mn></mfrac>");
+    for name in ["dfrac", "tfrac", "cfrac"] {
+        assert_eq!(body(&format!("\\{name}12")), body("\\frac12"));
+    }
+    assert_eq!(
+        body("\\binom nk"),
+        "<mrow><mo>(</mo><mfrac linethickness=\"0\"><mi>n</mi><mi>k</mi></mfrac><mo>)</mo></mrow>"
+    );
+    assert_eq!(body("\\sqrt x"), "<msqrt><mi>x</mi></msqrt>");
+    assert_eq!(body("\\sqrt[3]{x}"), "<mroot><mi>x</mi><mn>3</mn></mroot>");
+    assert_eq!(body("\\text{a {b} \\}}"), "<mtext>a {b} }</mtext>");
+    for name in ["textrm", "textnormal", "mbox"] {
+        assert_eq!(body(&format!("\\{name}{{if}}")), "<mtext>if</mtext>");
+    }
+    assert_eq!(
+        body("\\textit{if}"),
+        "<mtext mathvariant=\"italic\">if</mtext>"
+    );
+    assert_eq!(
+        body("\\textbf{if}"),
+        "<mtext mathvariant=\"bold\">if</mtext>"
+    );
+    assert_eq!(
+        body("\\operatorname{ rank }"),
+        "<mi mathvariant=\"normal\">rank</mi>"
+    );
+    assert_eq!(error("\\frac{1}"), "\\frac 缺少参数");
+    assert_eq!(error("\\text x"), "\\text 后面需要 {…}");
+}
+
+#[test]
+fn delimiters_and_styles() {
+    assert_eq!(
+        body("\\left( x \\right."),
+        "<mrow><mo stretchy=\"true\">(</mo><mi>x</mi><mo stretchy=\"true\"></mo></mrow>"
+    );
+    assert_eq!(
+        body("\\left\\langle x \\right>"),
+        "<mrow><mo stretchy=\"true\">⟨</mo><mi>x</mi><mo stretchy=\"true\">⟩</mo></mrow>"
+    );
+    for name in [
+        "big", "Big", "bigg", "Bigg", "bigl", "bigr", "Bigl", "Bigr", "biggl", "biggr", "Biggl",
+        "Biggr", "bigm", "Bigm",
+    ] {
+        assert_eq!(
+            body(&format!("\\{name}|")),
+            "<mo stretchy=\"false\">|</mo>",
+            "\\{name}"
+        );
+    }
+    assert_eq!(body("\\not="), "<mo>=\u{338}</mo>");
+    assert_eq!(error("\\not x"), "\\not 后面需要关系符号");
+    for name in [
+        "displaystyle",
+        "textstyle",
+        "scriptstyle",
+        "limits",
+        "nolimits",
+    ] {
+        assert_eq!(
+            body(&format!("\\{name} x")),
+            "<mrow><mrow></mrow><mi>x</mi></mrow>"
+        );
+    }
+    assert_eq!(error("\\left x \\right)"), "\\left 后面需要括号");
+    assert_eq!(error("\\bigl\\alpha"), "\\bigl 后面不能是 \\alpha");
+}
+
+#[test]
+fn environments() {
+    let table = "<mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr><mtr><mtd><mi>c</mi></mtd><mtd><mi>d</mi></mtd></mtr></mtable>";
+    for (name, open, close) in [
+        ("matrix", "", ""),
+        ("smallmatrix", "", ""),
+        ("pmatrix", "(", ")"),
+        ("bmatrix", "[", "]"),
+        ("Bmatrix", "{", "}"),
+        ("vmatrix", "|", "|"),
+        ("Vmatrix", "‖", "‖"),
+        ("gathered", "", ""),
+        ("gather", "", ""),
+        ("gather*", "", ""),
+    ] {
+        let mathml = body(&format!(
+            "\\begin{{{name}}} a & b \\\\ c & d \\end{{{name}}}"
+        ));
+        let fence = |d: &str| {
+            if d.is_empty() {
+                String::new()
+            } else {
+                format!("<mo stretchy=\"true\">{d}</mo>")
+            }
+        };
+        assert_eq!(
+            mathml,
+            format!("<mrow>{}{table}{}</mrow>", fence(open), fence(close)),
+            "{name}"
+        );
+    }
+    for name in ["aligned", "align", "align*", "split"] {
+        let mathml = body(&format!("\\begin{{{name}}} a & b \\\\ \\end{{{name}}}"));
+        assert_eq!(
+            mathml,
+            "<mrow><mtable columnalign=\"right left\"><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr></mtable></mrow>",
+            "{name}: a trailing \\\\ adds no row"
+        );
+    }
+    assert_eq!(
+        body("\\begin{cases} 1 & x > 0 \\end{cases}"),
+        "<mrow><mo stretchy=\"true\">{</mo><mtable columnalign=\"left left\"><mtr><mtd><mn>1</mn></mtd><mtd><mrow><mi>x</mi><mo>&gt;</mo><mn>0</mn></mrow></mtd></mtr></mtable></mrow>"
+    );
+    assert_eq!(
+        body("\\begin{array}{cc} a & b \\end{array}"),
+        "<mrow><mtable><mtr><mtd><mi>a</mi></mtd><mtd><mi>b</mi></mtd></mtr></mtable></mrow>"
+    );
+    assert_eq!(
+        error("\\begin{pmatrix} a \\end{bmatrix}"),
+        "\\begin{pmatrix} 和 \\end{bmatrix} 不匹配"
+    );
+    assert_eq!(
+        error("\\begin{tabular} a \\end{tabular}"),
+        "不支持的环境 tabular"
+    );
+}
+
+#[test]
+fn nesting_is_limited() {
+    let nested = |n: usize| format!("{}x{}", "{".repeat(n), "}".repeat(n));
+    // Each brace is one level and the `x` inside another
+    assert_eq!(body(&nested(MAX_DEPTH - 1)), "<mi>x</mi>");
+    assert_eq!(error(&nested(MAX_DEPTH)), "嵌套过深");
+    assert_eq!(error(&format!("{}x", "\\sqrt".repeat(100_000))), "嵌套过深");
+    assert_eq!(
+        error(&format!("{}2", "\\frac1".repeat(100_000))),
+        "嵌套过深"
+    );
+    assert_eq!(error(&"\\left(".repeat(100_000)), "嵌套过深");
+}
+
+#[test]
+fn unbalanced_braces() {
+    assert_eq!(error("{x"), "缺少 }");
+    assert_eq!(error("x}"), "多余的 }");
+    assert_eq!(error("\\frac{1}{2"), "缺少 }");
+    assert_eq!(error("\\text{open"), "缺少 }");
+    assert_eq!(error("\\sqrt[3 x"), "缺少 ]");
+    assert_eq!(error("\\left( x"), "缺少 \\right");
+    assert_eq!(error("\\right)"), "\\right 缺少对应的 \\left");
+    assert_eq!(error("\\begin{matrix} a"), "缺少 \\end{matrix}");
+    assert_eq!(error("\\end{matrix}"), "\\end 缺少对应的 \\begin");
+}
+
+#[test]
+fn unknown_and_misplaced_commands() {
+    assert_eq!(error("\\nosuch x"), "未知的命令 \\nosuch");
+    assert_eq!(error("\\@"), "未知的命令 \\@");
+    assert_eq!(error("x \\"), "多余的 \\");
+    assert_eq!(error("a \\\\ b"), "\\\\ 只能用在矩阵等环境中");
+    assert_eq!(error("a & b"), "& 只能用在矩阵等环境中");
+    assert_eq!(
+        error("\\left\\alpha x \\right)"),
+        "\\left 后面不能是 \\alpha"
+    );
+}
+
+#[test]
+fn errors_fall_back_to_the_source() {
+    assert_eq!(
+        render("\\nosuch <b>", false),
+        "<span class=\"math-error\" title=\"公式有误: 未知的命令 \\nosuch\">$\\nosuch &lt;b&gt;$</span>"
+    );
+    assert_eq!(
+        render("{\"x\"", true),
+        "<span class=\"math-error\" title=\"公式有误: 缺少 }\">$${&quot;x&quot;$$</span>"
+    );
+    assert_eq!(
+        render("x<y", true),
+        "<math display=\"block\"><semantics><mrow><mi>x</mi><mo>&lt;</mo><mi>y</mi></mrow><annotation encoding=\"application/x-tex\">x&lt;y</annotation></semantics></math>"
+    );
+    assert_eq!(render("x", false), to_mathml("x", false).unwrap());
+}
@@ -0,0 +1,398 @@
+use std::collections::HashMap;
+
+use salvo::prelude::*;
+use salvo::test::{ResponseExt, TestClient};
+use sea_orm::ConnectionTrait;
+use serde_json::{Value, json};
+
+use super::TestApp;
+
+/// Where a callback sent the browser, and the token cookie it set if any.
+struct Landing {
+    location: String,
+    token: Option<String>,
+    body: String,
+}
+
+/// Walk through the provider's consent screen as the browser would: follow
+/// `/oauth/mock` to the provider, grant `claims`, and come back to the callback.
+async fn sign_in_as(app: &TestApp, claims: Value, token: Option<&str>) -> Landing {
+    round_trip(app, "/oauth/mock", claims, token).await
+}
+
+/// Like [`sign_in_as`], but confirming who the signed in user is.
+async fn reauth_as(app: &TestApp, claims: Value, token: &str) -> Landing {
+    round_trip(app, "/oauth/mock?reauth=1", claims, Some(token)).await
+}
+
+async fn round_trip(app: &TestApp, path: &str, claims: Value, token: Option<&str>) -> Landing {
+    let (query, binding) = start(app, path, token).await;
+    let code = format!("code-{}", query["state"]);
+    app.oidc
+        .as_ref()
+        .expect("oidc provider")
+        .grant(&code, &query["code_challenge"], claims);
+    callback(app, &code, &query["state"], Some(&binding)).await
+}
+
+/// Start signing in and return the query of the redirect to the provider,
+/// and the cookie binding the round trip to this browser.
+async fn start(
+    app: &TestApp,
+    path: &str,
+    token: Option<&str>,
+) -> (HashMap<String, String>, String) {
+    let mut req = TestClient::get(TestApp::url(path));
+    if let Some(token) = token {
+        req = req.add_header("cookie", format!("token={token}"), true);
+    }
+    let res = req.send(&app.service).await;
+    assert_eq!(res.status_code, Some(StatusCode::FOUND));
+    let binding = res
+        .cookie("oauth_binding")
+        .map(|c| c.value().to_owned())
+        .expect("binding cookie");
+    let location = location(&res);
+    let oidc = app.oidc.as_ref().expect("oidc provider");
+    assert!(
+        location.starts_with(&format!("{}/authorize?", oidc.url())),
+        "{location}"
+    );
+    let url = reqwest::Url::parse(&location).expect("redirect url");
+    let query: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    assert_eq!(query["client_id"], "blog");
+    assert_eq!(query["code_challenge_method"], "S256");
+    assert_eq!(query["redirect_uri"], TestApp::url("/oauth/mock/callback"));
+    (query, binding)
+}
+
+async fn callback(app: &TestApp, code: &str, state: &str, binding: Option<&str>) -> Landing {
+    let mut req = TestClient::get(TestApp::url("/oauth/mock/callback"))
+        .query("code", code)
+        .query("state", state);
+    if let Some(binding) = binding {
+        req = req.add_header("cookie", format!("oauth_binding={binding}"), true);
+    }
+    let mut res = req.send(&app.service).await;
+    Landing {
+        location: location(&res),
+        token: res.cookie("token").map(|c| c.value().to_owned()),
+        body: res.take_string().await.unwrap_or_default(),
+    }
+}
+
+fn location(res: &Response) -> String {
+    res.headers()
+        .get("location")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// The name of the user `token` signs in as.
+async fn user_of(app: &TestApp, token: &str) -> String {
+    use base64::Engine;
+
+    let (_, page) = app.get("/profile", Some(token)).await;
+    assert!(!page.contains("没有权限执行此操作"), "not signed in");
+    let payload = token.split('.').nth(1).expect("jwt payload");
+    let claims: Value = serde_json::from_slice(
+        &base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(payload)
+            .expect("base64 payload"),
+    )
+    .expect("json claims");
+    let user_id = claims["user_id"].as_str().expect("user_id");
+    let db = app.state.db();
+    let row = db
+        .query_one(sea_orm::Statement::from_sql_and_values(
+            db.get_database_backend(),
+            "SELECT name FROM user_tb WHERE id = ?",
+            [user_id.parse::<i32>().expect("numeric id").into()],
+        ))
+        .await
+        .expect("query failed")
+        .expect("no such user");
+    row.try_get_by_index(0).expect("name")
+}
+
+#[tokio::test]
+async fn first_sign_in_creates_a_user_and_later_ones_reuse_it() {
+    let app = TestApp::with_oidc().await;
+    let claims = json!({
+        "sub": "subject-1",
+        "preferred_username": "octo",
+        "email": "octo@example.com",
+        "email_verified": true,
+    });
+    let landing = sign_in_as(&app, claims.clone(), None).await;
+    assert!(
+        landing.location.ends_with("/home/1"),
+        "{}",
+        landing.location
+    );
+    let token = landing.token.expect("token cookie");
+    assert_eq!(user_of(&app, &token).await, "octo");
+
+    let again = sign_in_as(&app, claims, None).await;
+    let token = again.token.expect("token cookie");
+    assert_eq!(user_of(&app, &token).await, "octo");
+    let users = app.scalar("SELECT COUNT(*) FROM user_tb").await;
+    assert_eq!(users, 1, "the second sign in reuses the account");
+}
+
+#[tokio::test]
+async fn taken_usernames_get_a_suffix() {
+    let app = TestApp::with_oidc().await;
+    app.register("octo", "octo@example.com", "secret-one").await;
+    let landing = sign_in_as(
+        &app,
+        json!({"sub": "subject-2", "preferred_username": "octo"}),
+        None,
+    )
+    .await;
+    let token = landing.token.expect("token cookie");
+    assert_eq!(user_of(&app, &token).await, "octo_2");
+}
+
+async fn has_email(app: &TestApp, name: &str) -> bool {
+    let sql = format!("SELECT COUNT(*) FROM user_tb WHERE name = '{name}' AND email IS NOT NULL");
+    app.scalar(&sql).await == 1
+}
+
+#[tokio::test]
+async fn addresses_are_kept_only_when_verified_and_free() {
+    let app = TestApp::with_oidc().await;
+    app.register("vera", "vera@example.com", "secret-one").await;
+    let claims = json!({
+        "sub": "subject-10",
+        "preferred_username": "imposter",
+        "email": "Vera@Example.com",
+        "email_verified": true,
+    });
+    sign_in_as(&app, claims, None).await.token.expect("token");
+    assert!(!has_email(&app, "imposter").await, "the address is taken");
+    let owners = app
+        .scalar("SELECT COUNT(*) FROM user_tb WHERE lower(email) = 'vera@example.com'")
+        .await;
+    assert_eq!(owners, 1);
+
+    // Like GitHub, which doesn't say whether it checked the address
+    let claims =
+        json!({"sub": "subject-11", "preferred_username": "unsure", "email": "unsure@example.com"});
+    sign_in_as(&app, claims, None).await.token.expect("token");
+    assert!(
+        !has_email(&app, "unsure").await,
+        "the address is unverified"
+    );
+
+    let claims = json!({
+        "sub": "subject-12",
+        "preferred_username": "fresh",
+        "email": "fresh@example.com",
+        "email_verified": true,
+    });
+    sign_in_as(&app, claims, None).await.token.expect("token");
+    assert!(has_email(&app, "fresh").await);
+}
+
+#[tokio::test]
+async fn state_is_single_use() {
+    let app = TestApp::with_oidc().await;
+    let (query, binding) = start(&app, "/oauth/mock", None).await;
+    let oidc = app.oidc.as_ref().unwrap();
+    oidc.grant(
+        "code-a",
+        &query["code_challenge"],
+        json!({"sub": "subject-3"}),
+    );
+    let first = callback(&app, "code-a", &query["state"], Some(&binding)).await;
+    assert!(first.token.is_some());
+
+    let replay = callback(&app, "code-a", &query["state"], Some(&binding)).await;
+    assert!(replay.token.is_none());
+    assert!(replay.body.contains("登录请求已失效"), "{}", replay.body);
+
+    let forged = callback(&app, "code-a", "not-a-state", Some(&binding)).await;
+    assert!(forged.token.is_none());
+    assert!(forged.body.contains("登录请求已失效"), "{}", forged.body);
+}
+
+#[tokio::test]
+async fn callbacks_only_work_in_the_browser_that_started_them() {
+    let app = TestApp::with_oidc().await;
+    // The attacker starts a sign in with their own account...
+    let (query, _) = start(&app, "/oauth/mock", None).await;
+    app.oidc.as_ref().unwrap().grant(
+        "code-c",
+        &query["code_challenge"],
+        json!({"sub": "attacker"}),
+    );
+    // ...and gets the victim's browser, which never started one, to finish it
+    let landing = callback(&app, "code-c", &query["state"], None).await;
+    assert!(landing.token.is_none());
+    assert!(landing.body.contains("登录请求已失效"), "{}", landing.body);
+
+    // Nor does a binding cookie from some other round trip help
+    let (other, _) = start(&app, "/oauth/mock", None).await;
+    let (_, victim) = start(&app, "/oauth/mock", None).await;
+    app.oidc.as_ref().unwrap().grant(
+        "code-d",
+        &other["code_challenge"],
+        json!({"sub": "attacker"}),
+    );
+    let landing = callback(&app, "code-d", &other["state"], Some(&victim)).await;
+    assert!(landing.token.is_none());
+    assert!(landing.body.contains("登录请求已失效"), "{}", landing.body);
+}
+
+#[tokio::test]
+async fn a_wrong_verifier_is_rejected_by_the_provider() {
+    let app = TestApp::with_oidc().await;
+    let (query, binding) = start(&app, "/oauth/mock", None).await;
+    app.oidc.as_ref().unwrap().grant(
+        "code-b",
+        "some-other-challenge",
+        json!({"sub": "subject-4"}),
+    );
+    let landing = callback(&app, "code-b", &query["state"], Some(&binding)).await;
+    assert!(landing.token.is_none());
+    assert!(landing.body.contains("第三方登录失败"), "{}", landing.body);
+}
+
+#[tokio::test]
+async fn signed_in_users_link_accounts() {
+    let app = TestApp::with_oidc().await;
+    let token = app.register("hank", "hank@example.com", "secret-two").await;
+    let claims = json!({"sub": "subject-5", "preferred_username": "hank-gh"});
+    let landing = sign_in_as(&app, claims.clone(), Some(&token)).await;
+    assert!(
+        landing.location.ends_with("/home/1"),
+        "{}",
+        landing.location
+    );
+    assert!(landing.token.is_none(), "linking keeps the current session");
+
+    // Signing in through the provider now lands on the linked account
+    let landing = sign_in_as(&app, claims.clone(), None).await;
+    let linked = landing.token.expect("token cookie");
+    assert_eq!(user_of(&app, &linked).await, "hank");
+
+    // It can't be linked to somebody else as well
+    let other = app.register("ivy", "ivy@example.com", "secret-three").await;
+    let landing = sign_in_as(&app, claims, Some(&other)).await;
+    assert!(
+        landing.body.contains("该第三方账号已绑定其他用户"),
+        "{}",
+        landing.body
+    );
+}
+
+#[tokio::test]
+async fn users_with_two_factor_are_sent_to_the_second_step() {
+    let app = TestApp::with_oidc().await;
+    let token = app
+        .register("jill", "jill@example.com", "secret-four")
+        .await;
+    sign_in_as(&app, json!({"sub": "subject-6"}), Some(&token)).await;
+    app.execute("UPDATE user_tb SET totp_enabled = 1 WHERE name = 'jill'")
+        .await;
+
+    let landing = sign_in_as(&app, json!({"sub": "subject-6"}), None).await;
+    assert!(landing.token.is_none(), "no session before the second step");
+    assert!(
+        landing.location.contains("/login?ticket="),
+        "{}",
+        landing.location
+    );
+}
+
+#[tokio::test]
+async fn users_without_a_password_confirm_changes_through_the_provider() {
+    let app = TestApp::with_oidc().await;
+    let claims = json!({"sub": "subject-7", "preferred_username": "kim"});
+    let landing = sign_in_as(&app, claims.clone(), None).await;
+    let token = landing.token.expect("token cookie");
+    let set_password = [
+        ("oldPassword", ""),
+        ("password", "secret-five"),
+        ("password2", "secret-five"),
+    ];
+    let r = app
+        .post("/profile/password", &set_password, Some(&token))
+        .await;
+    assert_eq!(r["code"], 400, "{r}");
+    assert_eq!(r["msg"], "请先通过已绑定的第三方账号重新验证身份");
+
+    let (_, page) = app.get("/profile", Some(&token)).await;
+    assert!(page.contains("oauth/mock?reauth=1"), "{page}");
+
+    let landing = reauth_as(&app, claims.clone(), &token).await;
+    assert!(
+        landing.location.ends_with("/profile"),
+        "{}",
+        landing.location
+    );
+    assert!(landing.token.is_none(), "reauth keeps the current session");
+    let r = app
+        .post("/profile/password", &set_password, Some(&token))
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let r = app.login("kim", "secret-five").await;
+    assert_eq!(r["code"], 200, "{r}");
+
+    // Each trip through the provider is good for one change only
+    app.execute("UPDATE user_tb SET password = NULL WHERE name = 'kim'")
+        .await;
+    let r = app
+        .post("/profile/password", &set_password, Some(&token))
+        .await;
+    assert_eq!(r["code"], 400, "{r}");
+}
+
+#[tokio::test]
+async fn reauth_only_accepts_an_account_already_linked() {
+    let app = TestApp::with_oidc().await;
+    let landing = sign_in_as(&app, json!({"sub": "subject-8"}), None).await;
+    let token = landing.token.expect("token cookie");
+
+    let landing = reauth_as(&app, json!({"sub": "subject-9"}), &token).await;
+    assert!(
+        landing.body.contains("请使用已绑定的第三方账号验证身份"),
+        "{}",
+        landing.body
+    );
+    let set_password = [
+        ("oldPassword", ""),
+        ("password", "secret-six"),
+        ("password2", "secret-six"),
+    ];
+    let r = app
+        .post("/profile/password", &set_password, Some(&token))
+        .await;
+    assert_eq!(r["code"], 400, "{r}");
+    // Nor did the attempt link the new account
+    let landing = sign_in_as(&app, json!({"sub": "subject-9"}), None).await;
+    let other = landing.token.expect("token cookie");
+    assert_ne!(user_of(&app, &other).await, user_of(&app, &token).await);
+
+    let (status, page) = app.get("/oauth/mock?reauth=1", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(page.contains("请先登录"), "{page}");
+}
+
+#[tokio::test]
+async fn login_page_lists_providers() {
+    let app = TestApp::with_oidc().await;
+    let (_, page) = app.get("/login", None).await;
+    assert!(page.contains("其他登录方式"));
+    assert!(page.contains("Mock"));
+
+    let plain = TestApp::new().await;
+    let (_, page) = plain.get("/login", None).await;
+    assert!(!page.contains("其他登录方式"));
+
+    let (status, page) = plain.get("/oauth/mock", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(page.contains("不支持的登录方式"));
+}
@@ -0,0 +1,309 @@
+//! `/profile`: password, email and public profile fields, and the author
+//! pages at `/user/{id}`.
+
+use serde_json::Value;
+
+use super::TestApp;
+
+async fn signed_in(app: &TestApp, token: &str) -> bool {
+    let (_, page) = app.get("/profile", Some(token)).await;
+    !page.contains("没有权限执行此操作")
+}
+
+/// The most recent message sent to `to`.
+fn last_mail_to(app: &TestApp, to: &str) -> String {
+    let header = format!("To: {to}\n");
+    app.sent_mail()
+        .into_iter()
+        .rev()
+        .find(|message| message.contains(&header))
+        .unwrap_or_else(|| panic!("nothing was sent to {to}"))
+}
+
+#[tokio::test]
+async fn password_change_needs_the_current_one_and_signs_out_elsewhere() {
+    let app = TestApp::new().await;
+    let here = app.register("xena", "xena@example.com", "secret-one").await;
+    let r = app.login("xena", "secret-one").await;
+    let elsewhere = r["token"].as_str().expect("token").to_owned();
+
+    let r = app
+        .post(
+            "/profile/password",
+            &[
+                ("oldPassword", "wrong-guess"),
+                ("password", "secret-two"),
+                ("password2", "secret-two"),
+            ],
+            Some(&here),
+        )
+        .await;
+    assert_eq!(r["msg"], "当前密码错误", "{r}");
+    let r = app
+        .post(
+            "/profile/password",
+            &[
+                ("oldPassword", "secret-one"),
+                ("password", "secret-two"),
+                ("password2", "secret-three"),
+            ],
+            Some(&here),
+        )
+        .await;
+    assert_eq!(r["msg"], "密码不一致", "{r}");
+
+    let r = app
+        .post(
+            "/profile/password",
+            &[
+                ("oldPassword", "secret-one"),
+                ("password", "secret-two"),
+                ("password2", "secret-two"),
+            ],
+            Some(&here),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert!(signed_in(&app, &here).await, "this device stays signed in");
+    assert!(!signed_in(&app, &elsewhere).await);
+    assert_ne!(app.login("xena", "secret-one").await["code"], 200);
+    assert_eq!(app.login("xena", "secret-two").await["code"], 200);
+
+    let notice = last_mail_to(&app, "xena@example.com");
+    assert!(notice.contains("修改了密码"), "{notice}");
+}
+
+#[tokio::test]
+async fn email_change_is_confirmed_from_the_new_address() {
+    let app = TestApp::new().await;
+    let here = app.register("yuri", "yuri@example.com", "secret-one").await;
+    app.register("zoe", "zoe@example.com", "secret-two").await;
+    let r = app.login("yuri", "secret-one").await;
+    let elsewhere = r["token"].as_str().expect("token").to_owned();
+
+    let r = app
+        .post(
+            "/profile/email/code",
+            &[("email", "zoe@example.com")],
+            Some(&here),
+        )
+        .await;
+    assert_eq!(r["msg"], "该邮箱已被其他账号使用", "{r}");
+    let r = app
+        .post(
+            "/profile/email/code",
+            &[("email", "yuri@example.org")],
+            Some(&here),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("yuri@example.org");
+
+    // A registration code for the same address doesn't count
+    let r = app
+        .post("/sendcode", &[("email", "yuri@example.org")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let other = app.last_code_sent_to("yuri@example.org");
+    let r = app
+        .post(
+            "/profile/email",
+            &[("email", "yuri@example.org"), ("code", &other)],
+            Some(&here),
+        )
+        .await;
+    assert_eq!(r["msg"], "邮箱验证码错误", "{r}");
+
+    let r = app
+        .post(
+            "/profile/email",
+            &[("email", "yuri@example.org"), ("code", &code)],
+            Some(&here),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let changed = app
+        .scalar("SELECT COUNT(*) FROM user_tb WHERE name = 'yuri' AND email = 'yuri@example.org'")
+        .await;
+    assert_eq!(changed, 1);
+    assert!(signed_in(&app, &here).await);
+    assert!(!signed_in(&app, &elsewhere).await);
+
+    let notice = last_mail_to(&app, "yuri@example.com");
+    assert!(notice.contains("将邮箱修改为 yuri@example.org"), "{notice}");
+
+    // Nothing left to change
+    let r = app
+        .post(
+            "/profile/email",
+            &[("email", "yuri@example.org"), ("code", &code)],
+            Some(&here),
+        )
+        .await;
+    assert_eq!(r["msg"], "新邮箱与当前邮箱相同", "{r}");
+}
+
+async fn post_article(app: &TestApp, token: &str, title: &str, level: &str) -> i64 {
+    let r = app
+        .post(
+            "/add",
+            &[
+                ("tag", "1"),
+                ("title", title),
+                ("content", "body"),
+                ("level", level),
+            ],
+            Some(token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    app.scalar("SELECT MAX(id) FROM article_tb").await
+}
+
+#[tokio::test]
+async fn profile_fields_are_validated_and_shown_publicly() {
+    let app = TestApp::new().await;
+    let token = app.register("abel", "abel@example.com", "secret-one").await;
+    let id = app
+        .scalar("SELECT id FROM user_tb WHERE name = 'abel'")
+        .await;
+
+    let r = app
+        .post(
+            "/profile/info",
+            &[("website", "javascript:alert(1)")],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["msg"], "个人网站必须是 http 或 https 链接", "{r}");
+    let links = "https://a.example\nhttps://b.example\nhttps://c.example\nhttps://d.example\nhttps://e.example\nhttps://f.example";
+    let r = app
+        .post("/profile/info", &[("socialLinks", links)], Some(&token))
+        .await;
+    assert_eq!(r["msg"], "社交链接最多5个", "{r}");
+
+    let r = app
+        .post(
+            "/profile/info",
+            &[
+                ("displayName", "  Abel the Writer "),
+                ("bio", "Writes about <b>Rust</b>"),
+                ("website", "https://abel.example"),
+                ("location", "Lisbon"),
+                (
+                    "socialLinks",
+                    "https://github.com/abel\n\nhttps://mastodon.example/@abel",
+                ),
+            ],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+
+    let (_, page) = app.get("/profile", Some(&token)).await;
+    assert!(page.contains("value=\"Abel the Writer\""), "{page}");
+
+    let (_, page) = app.get(&format!("/user/{id}/1"), None).await;
+    assert!(page.contains("Abel the Writer") && page.contains("@abel"));
+    assert!(page.contains("Lisbon"));
+    assert!(
+        page.contains("Writes about &lt;b&gt;Rust&lt;&#x2F;b&gt;"),
+        "bio is escaped"
+    );
+    assert!(page.contains("github.com") && page.contains("mastodon.example"));
+
+    // Clearing a field stores nothing
+    let r = app.post("/profile/info", &[], Some(&token)).await;
+    assert_eq!(r["code"], 200, "{r}");
+    let empty = app
+        .scalar(&format!(
+            "SELECT COUNT(*) FROM user_tb WHERE id = {id} AND display_name IS NULL AND social_links IS NULL"
+        ))
+        .await;
+    assert_eq!(empty, 1);
+}
+
+async fn set_display_name(app: &TestApp, token: &str, name: &str) -> Value {
+    app.post("/profile/info", &[("displayName", name)], Some(token))
+        .await
+}
+
+#[tokio::test]
+async fn display_names_cannot_pass_for_someone_else() {
+    let app = TestApp::new().await;
+    app.register("admin", "admin@example.com", "secret-one")
+        .await;
+    let bea = app.register("bea", "bea@example.com", "secret-two").await;
+    let cid = app.register("cid", "cid@example.com", "secret-three").await;
+
+    let r = set_display_name(&app, &bea, "Admin").await;
+    assert_eq!(r["msg"], "显示名称已被使用", "{r}");
+    let r = set_display_name(&app, &bea, "Bea").await;
+    assert_eq!(r["code"], 200, "your own user name is fine: {r}");
+    let r = set_display_name(&app, &cid, "BEA").await;
+    assert_eq!(r["msg"], "显示名称已被使用", "{r}");
+    let r = set_display_name(&app, &bea, "The Editor").await;
+    assert_eq!(r["code"], 200, "{r}");
+    let r = set_display_name(&app, &cid, "the editor").await;
+    assert_eq!(r["msg"], "显示名称已被使用", "{r}");
+
+    // Nor can a new account take a display name as its user name
+    let r = app
+        .post("/sendcode", &[("email", "dan@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("dan@example.com");
+    let r = app
+        .post(
+            "/register",
+            &[
+                ("nickName", "the editor"),
+                ("password", "secret-four"),
+                ("password2", "secret-four"),
+                ("email", "dan@example.com"),
+                ("code", &code),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(r["msg"], "用户名已存在", "{r}");
+}
+
+#[tokio::test]
+async fn author_pages_list_only_visible_articles() {
+    let app = TestApp::new().await;
+    let author = app
+        .register("bella", "bella@example.com", "secret-one")
+        .await;
+    let other = app.register("cole", "cole@example.com", "secret-two").await;
+    let id = app
+        .scalar("SELECT id FROM user_tb WHERE name = 'bella'")
+        .await;
+    post_article(&app, &author, "Open Letter", "1").await;
+    post_article(&app, &author, "Secret Diary", "999").await;
+    let withdrawn = post_article(&app, &author, "Old Draft", "1").await;
+    let r = app
+        .post(&format!("/delete/{withdrawn}"), &[], Some(&author))
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    post_article(&app, &other, "Someone Else", "1").await;
+
+    // The sidebar lists hot articles from everyone, so look at the cards only
+    let listed = |page: &str, title: &str| {
+        page.contains(&format!("<h3 class=\"article-title\">{title}</h3>"))
+    };
+
+    let (status, _) = app.get(&format!("/user/{id}"), None).await;
+    assert_eq!(status, salvo::http::StatusCode::SEE_OTHER);
+    for token in [None, Some(other.as_str())] {
+        let (_, page) = app.get(&format!("/user/{id}/1"), token).await;
+        assert!(listed(&page, "Open Letter"));
+        assert!(!listed(&page, "Secret Diary") && !listed(&page, "Old Draft"));
+        assert!(!listed(&page, "Someone Else"));
+    }
+    let (_, page) = app.get(&format!("/user/{id}/1"), Some(&author)).await;
+    assert!(listed(&page, "Open Letter") && listed(&page, "Secret Diary"));
+
+    let (_, page) = app.get("/user/9999/1", None).await;
+    assert!(page.contains("用户不存在"));
+}
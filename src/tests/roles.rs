@@ -0,0 +1,162 @@
+//! Roles stored in `user_tb.privilege` and the permissions they grant.
+
+use sea_orm_migration::MigratorTrait;
+
+use crate::home::access::{self, Permission, Role};
+use crate::migration::Migrator;
+
+use super::TestApp;
+
+async fn set_role(app: &TestApp, name: &str, role: Role) {
+    access::set_role_by_name(app.state.db(), name, role)
+        .await
+        .expect("failed to set role");
+}
+
+async fn post_article(app: &TestApp, token: &str, level: &str) -> serde_json::Value {
+    app.post(
+        "/add",
+        &[
+            ("tag", "1"),
+            ("title", "Roles"),
+            ("content", "body"),
+            ("level", level),
+        ],
+        Some(token),
+    )
+    .await
+}
+
+#[test]
+fn privileges_map_onto_roles() {
+    for role in Role::ALL {
+        assert_eq!(Role::from_privilege(Some(role.privilege())), role);
+        assert_eq!(Role::from_name(role.as_str()), Some(role));
+    }
+    // Unknown values never grant anything
+    assert_eq!(Role::from_privilege(None), Role::Reader);
+    assert_eq!(Role::from_privilege(Some(999)), Role::Reader);
+    assert!(!Role::Reader.can(Permission::ArticlePublish));
+    assert!(Role::Author.can(Permission::ArticlePublish));
+    assert!(!Role::Author.can(Permission::CommentModerate));
+    assert!(Role::Editor.can(Permission::CommentModerate));
+    assert!(!Role::Editor.can(Permission::UserManage));
+    assert!(Role::Admin.can(Permission::UserManage));
+}
+
+#[tokio::test]
+async fn privileges_from_before_roles_become_clearances() {
+    let app = TestApp::new().await;
+    let db = app.state.db();
+    // Back to just before the clearance column, the eighth migration
+    let later = Migrator::migrations().len() as u32 - 7;
+    Migrator::down(db, Some(later))
+        .await
+        .expect("failed to roll back");
+    app.execute(
+        "INSERT INTO user_tb (name, email, privilege) VALUES \
+         ('old-vip', 'vip@example.com', 7), ('old-four', 'four@example.com', 4)",
+    )
+    .await;
+    Migrator::up(db, None).await.expect("failed to migrate");
+
+    for (name, clearance) in [("old-vip", 7), ("old-four", 4)] {
+        let sql = format!("SELECT privilege FROM user_tb WHERE name = '{name}'");
+        assert_eq!(app.scalar(&sql).await, 2, "{name} is an author");
+        let sql = format!("SELECT clearance FROM user_tb WHERE name = '{name}'");
+        assert_eq!(app.scalar(&sql).await, clearance, "{name} reads as before");
+    }
+    assert_eq!(access::clearance(Role::Author, Some(7)), 7);
+    assert_eq!(access::clearance(Role::Admin, Some(1)), 4);
+    assert_eq!(access::clearance(Role::Reader, None), 1);
+}
+
+#[tokio::test]
+async fn readers_cannot_publish() {
+    let app = TestApp::new().await;
+    let token = app.register("kate", "kate@example.com", "secret-one").await;
+    let r = post_article(&app, &token, "1").await;
+    assert_eq!(r["code"], 200, "authors publish: {r}");
+    let id = app.scalar("SELECT MAX(id) FROM article_tb").await;
+
+    set_role(&app, "kate", Role::Reader).await;
+    let r = post_article(&app, &token, "1").await;
+    assert_eq!(r["msg"], "没有权限执行此操作", "{r}");
+    let (_, page) = app.get("/add", Some(&token)).await;
+    assert!(page.contains("没有权限执行此操作"));
+    let r = app.post(&format!("/delete/{id}"), &[], Some(&token)).await;
+    assert_eq!(r["msg"], "没有权限执行此操作", "{r}");
+
+    // Reading is unaffected
+    let (_, page) = app.get(&format!("/article/{id}"), Some(&token)).await;
+    assert!(page.contains("Roles"));
+}
+
+#[tokio::test]
+async fn reading_clearance_follows_the_role() {
+    let app = TestApp::new().await;
+    let author = app.register("liam", "liam@example.com", "secret-two").await;
+    let editor = app
+        .register("mona", "mona@example.com", "secret-three")
+        .await;
+    let r = post_article(&app, &author, "3").await;
+    assert_eq!(r["msg"], "阅读等级不存在", "{r}");
+    app.execute("INSERT INTO level_tb (level, name) VALUES (3, '编辑可见')")
+        .await;
+    let r = post_article(&app, &author, "3").await;
+    assert_eq!(r["code"], 200, "{r}");
+    let id = app.scalar("SELECT MAX(id) FROM article_tb").await;
+
+    let (_, page) = app.get(&format!("/article/{id}"), Some(&editor)).await;
+    assert!(
+        page.contains("没有该文章的阅读权限"),
+        "authors can't read level 3"
+    );
+    set_role(&app, "mona", Role::Editor).await;
+    let (_, page) = app.get(&format!("/article/{id}"), Some(&editor)).await;
+    assert!(!page.contains("没有该文章的阅读权限"), "editors can");
+}
+
+#[tokio::test]
+async fn editors_moderate_comments() {
+    let app = TestApp::new().await;
+    let author = app
+        .register("nora", "nora@example.com", "secret-four")
+        .await;
+    let editor = app
+        .register("otto", "otto@example.com", "secret-five")
+        .await;
+    let r = post_article(&app, &author, "1").await;
+    assert_eq!(r["code"], 200, "{r}");
+    let article = app.scalar("SELECT MAX(id) FROM article_tb").await;
+    let r = app
+        .post(
+            &format!("/comment/{article}"),
+            &[("comment", "<p>hi</p>"), ("md_content", "hi")],
+            Some(&author),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let comment = app.scalar("SELECT MAX(id) FROM comment_tb").await;
+
+    let r = app
+        .post(&format!("/delcomment/{comment}"), &[], Some(&editor))
+        .await;
+    assert_ne!(r["code"], 200, "authors can't delete others' comments");
+
+    set_role(&app, "otto", Role::Editor).await;
+    let r = app
+        .post(&format!("/delcomment/{comment}"), &[], Some(&editor))
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM comment_tb").await, 0);
+}
+
+#[tokio::test]
+async fn unknown_users_cannot_be_given_a_role() {
+    let app = TestApp::new().await;
+    let err = access::set_role_by_name(app.state.db(), "nobody", Role::Admin)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("nobody"));
+}
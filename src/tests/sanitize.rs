@@ -0,0 +1,127 @@
+//! Sanitizing of comment and article HTML.
+
+use crate::config::HtmlPolicy;
+use crate::home::sanitize::{self, Sanitizer};
+
+use super::TestApp;
+
+async fn post_article(app: &TestApp, token: &str, content: &str) -> i64 {
+    let r = app
+        .post(
+            "/add",
+            &[
+                ("tag", "1"),
+                ("title", "Markup"),
+                ("content", content),
+                ("level", "1"),
+            ],
+            Some(token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    app.scalar("SELECT MAX(id) FROM article_tb").await
+}
+
+async fn articles_like(app: &TestApp, pattern: &str) -> i64 {
+    app.scalar(&format!(
+        "SELECT COUNT(*) FROM article_tb WHERE content LIKE '%{pattern}%'"
+    ))
+    .await
+}
+
+#[tokio::test]
+async fn comments_keep_only_allowed_html() {
+    let app = TestApp::new().await;
+    let token = app.register("wes", "wes@example.com", "secret-wes").await;
+    let id = post_article(&app, &token, "body").await;
+
+    let r = app
+        .post(
+            &format!("/comment/{id}"),
+            &[
+                (
+                    "comment",
+                    r#"<p class="lead">hi <b>there</b><script>alert(1)</script><img src="x" onerror="alert(2)"><a href="javascript:alert(3)">x</a></p>"#,
+                ),
+                ("md_content", "hi **there**<script>alert(1)</script>"),
+            ],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let kept = app
+        .scalar(r#"SELECT COUNT(*) FROM comment_tb WHERE comment LIKE '<p class="lead">hi <b>there</b><img src="x"><a rel="noopener noreferrer">x</a></p>'"#)
+        .await;
+    assert_eq!(kept, 1);
+    let script = app
+        .scalar("SELECT COUNT(*) FROM comment_tb WHERE md_content LIKE '%<script>%'")
+        .await;
+    assert_eq!(script, 0);
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("<p>hi <strong>there</strong>"), "{page}");
+    assert!(!page.contains("<script>alert"), "{page}");
+}
+
+#[tokio::test]
+async fn article_markdown_keeps_its_text_but_not_its_scripts() {
+    let app = TestApp::new().await;
+    let token = app.register("xia", "xia@example.com", "secret-xia").await;
+    post_article(
+        &app,
+        &token,
+        "# Title\n\n<div onclick=\"steal()\">\n\nkept **bold** <span onmouseover=\"steal()\">span</span> `<script>` in code\n\n</div>\n\n[click](javascript:steal()) and [fine](https://example.com)\n",
+    )
+    .await;
+    assert_eq!(articles_like(&app, "steal").await, 0);
+    assert_eq!(
+        articles_like(
+            &app,
+            "<div>%kept **bold** <span>span</span> `<script>` in code"
+        )
+        .await,
+        1
+    );
+    assert_eq!(
+        articles_like(&app, "click and [fine](https://example.com)").await,
+        1
+    );
+
+    // Code stays code on the rendered page
+    post_article(&app, &token, "`</textarea>` <b>after</b>").await;
+    let id = app.scalar("SELECT MAX(id) FROM article_tb").await;
+    let (_, page) = app.get(&format!("/article/{id}"), None).await;
+    assert!(page.contains("<code>&lt;/textarea&gt;</code> <b>after</b>"));
+}
+
+#[tokio::test]
+async fn stored_rows_are_sanitized_again_on_demand() {
+    let app = TestApp::new().await;
+    let token = app.register("yan", "yan@example.com", "secret-yan").await;
+    let id = post_article(&app, &token, "<img src=\"a.png\"> ok").await;
+    app.execute(&format!(
+        "INSERT INTO comment_tb (article_id, user_id, comment, md_content) VALUES ({id}, 1, '<i onclick=\"x()\">old</i>', 'old')"
+    ))
+    .await;
+
+    let policy = HtmlPolicy {
+        removed_tags: vec!["img".to_owned()],
+        ..HtmlPolicy::default()
+    };
+    let changed = sanitize::resanitize(app.state.db(), &Sanitizer::new(&policy))
+        .await
+        .expect("resanitize");
+    assert_eq!(changed, (1, 1));
+    let images = app
+        .scalar("SELECT COUNT(*) FROM article_tb WHERE content LIKE '%<img%'")
+        .await;
+    assert_eq!(images, 0);
+    let handlers = app
+        .scalar("SELECT COUNT(*) FROM comment_tb WHERE comment LIKE '%onclick%'")
+        .await;
+    assert_eq!(handlers, 0);
+
+    let again = sanitize::resanitize(app.state.db(), &Sanitizer::new(&policy))
+        .await
+        .expect("resanitize");
+    assert_eq!(again, (0, 0));
+}
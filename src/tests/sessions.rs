@@ -0,0 +1,188 @@
+use salvo::prelude::*;
+use salvo::test::{ResponseExt, TestClient};
+
+use super::TestApp;
+
+/// Whether `token` still gets past the guard on `/profile`.
+async fn signed_in(app: &TestApp, token: &str) -> bool {
+    let (_, page) = app.get("/profile", Some(token)).await;
+    !page.contains("没有权限执行此操作")
+}
+
+#[tokio::test]
+async fn logout_revokes_only_the_current_session() {
+    let app = TestApp::new().await;
+    let first = app
+        .register("erin", "erin@example.com", "secret-five")
+        .await;
+    let r = app.login("erin", "secret-five").await;
+    let second = r["token"].as_str().expect("token").to_owned();
+    assert!(signed_in(&app, &first).await);
+    assert!(signed_in(&app, &second).await);
+
+    let r = app.post("/logout", &[], Some(&first)).await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert!(
+        !signed_in(&app, &first).await,
+        "logged out token still works"
+    );
+    assert!(
+        signed_in(&app, &second).await,
+        "other sessions stay signed in"
+    );
+
+    // Logging out twice, or without a token, is harmless
+    let r = app.post("/logout", &[], Some(&first)).await;
+    assert_eq!(r["code"], 200, "{r}");
+    let r = app.post("/logout", &[], None).await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn sign_out_everywhere_revokes_every_session() {
+    let app = TestApp::new().await;
+    let first = app
+        .register("frank", "frank@example.com", "secret-six")
+        .await;
+    let r = app.login("frank", "secret-six").await;
+    let second = r["token"].as_str().expect("token").to_owned();
+
+    let r = app.post("/logout/all", &[], None).await;
+    assert_eq!(r["msg"], "没有权限执行此操作", "{r}");
+    let r = app.post("/logout/all", &[], Some(&second)).await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert!(!signed_in(&app, &first).await);
+    assert!(!signed_in(&app, &second).await);
+
+    let r = app.login("frank", "secret-six").await;
+    let fresh = r["token"].as_str().expect("token").to_owned();
+    assert!(signed_in(&app, &fresh).await, "signing in again works");
+}
+
+#[tokio::test]
+async fn password_reset_revokes_existing_sessions() {
+    let app = TestApp::new().await;
+    let token = app
+        .register("gina", "gina@example.com", "secret-seven")
+        .await;
+
+    let r = app
+        .post(
+            "/sendcode",
+            &[("email", "gina@example.com"), ("purpose", "reset")],
+            None,
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("gina@example.com");
+    let r = app
+        .post(
+            "/forget",
+            &[
+                ("nickName", "gina"),
+                ("password", "secret-eight"),
+                ("password2", "secret-eight"),
+                ("email", "gina@example.com"),
+                ("code", &code),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert!(!signed_in(&app, &token).await);
+}
+
+#[tokio::test]
+async fn tokens_without_a_session_are_rejected() {
+    let app = TestApp::new().await;
+    app.register("hank", "hank@example.com", "secret-nine")
+        .await;
+    let user_id = app
+        .scalar("SELECT id FROM user_tb WHERE name = 'hank'")
+        .await;
+    // Shaped like the tokens handed out before sessions were tracked
+    let legacy = jsonwebtoken::encode(
+        &jsonwebtoken::Header::default(),
+        &serde_json::json!({
+            "user_id": user_id.to_string(),
+            "exp": chrono::Utc::now().timestamp() + 3600,
+        }),
+        &jsonwebtoken::EncodingKey::from_secret(app.state.secret_key().as_bytes()),
+    )
+    .expect("encode token");
+    assert!(!signed_in(&app, &legacy).await);
+}
+
+#[tokio::test]
+async fn sessions_page_lists_and_revokes_logins() {
+    let app = TestApp::new().await;
+    let first = app.register("iris", "iris@example.com", "secret-ten").await;
+    let mut res = app
+        .with_token(TestClient::post(TestApp::url("/login")), None)
+        .add_header("user-agent", "Firefox/140.0 on the laptop", true)
+        .form(&[
+            ("nickName", "iris"),
+            ("password", "secret-ten"),
+            ("rememberMe", "false"),
+        ])
+        .send(&app.service)
+        .await;
+    let r: serde_json::Value =
+        serde_json::from_str(&res.take_string().await.unwrap_or_default()).expect("JSON reply");
+    let laptop = r["token"].as_str().expect("token").to_owned();
+
+    let (status, page) = app.get("/profile/sessions", Some(&first)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(page.contains("on the laptop"), "{page}");
+    assert!(page.contains("当前设备"));
+
+    let laptop_id = app
+        .scalar("SELECT id FROM session_tb WHERE user_agent LIKE 'Firefox%'")
+        .await;
+    let r = app
+        .post(
+            &format!("/profile/sessions/{laptop_id}/revoke"),
+            &[],
+            Some(&first),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert_eq!(r["current"], false);
+    assert!(
+        !signed_in(&app, &laptop).await,
+        "revoked session still works"
+    );
+    assert!(signed_in(&app, &first).await);
+    let (_, page) = app.get("/profile/sessions", Some(&first)).await;
+    assert!(
+        !page.contains("Firefox/140.0"),
+        "revoked sessions are not listed"
+    );
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_revoked() {
+    let app = TestApp::new().await;
+    let jack = app
+        .register("jack", "jack@example.com", "secret-eleven")
+        .await;
+    let kate = app
+        .register("kate", "kate@example.com", "secret-twelve")
+        .await;
+    let kate_session = app
+        .scalar(
+            "SELECT s.id FROM session_tb s JOIN user_tb u ON u.id = s.user_id \
+             WHERE u.name = 'kate'",
+        )
+        .await;
+
+    let r = app
+        .post(
+            &format!("/profile/sessions/{kate_session}/revoke"),
+            &[],
+            Some(&jack),
+        )
+        .await;
+    assert_eq!(r["code"], 404, "{r}");
+    assert!(signed_in(&app, &kate).await);
+}
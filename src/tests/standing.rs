@@ -0,0 +1,164 @@
+//! Suspended and banned accounts.
+
+use chrono::{Duration, Local};
+
+use crate::home::access::{self, Standing};
+
+use super::TestApp;
+
+async fn user_id(app: &TestApp, name: &str) -> i32 {
+    app.scalar(&format!("SELECT id FROM user_tb WHERE name = '{name}'"))
+        .await as i32
+}
+
+async fn post_article(app: &TestApp, token: &str) -> serde_json::Value {
+    app.post(
+        "/add",
+        &[
+            ("tag", "1"),
+            ("title", "Standing"),
+            ("content", "body"),
+            ("level", "1"),
+        ],
+        Some(token),
+    )
+    .await
+}
+
+async fn comment(app: &TestApp, token: &str, article: i64) -> serde_json::Value {
+    app.post(
+        &format!("/comment/{article}"),
+        &[("comment", "<p>hi</p>"), ("md_content", "hi")],
+        Some(token),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn suspended_users_read_but_do_not_post() {
+    let app = TestApp::new().await;
+    let token = app.register("uma", "uma@example.com", "secret-one").await;
+    let r = post_article(&app, &token).await;
+    assert_eq!(r["code"], 200, "{r}");
+    let article = app.scalar("SELECT MAX(id) FROM article_tb").await;
+    let r = comment(&app, &token, article).await;
+    assert_eq!(r["code"], 200, "{r}");
+    let earlier = app.scalar("SELECT MAX(id) FROM comment_tb").await;
+
+    let until = (Local::now() + Duration::days(3)).naive_local();
+    let uma = user_id(&app, "uma").await;
+    access::set_standing(
+        app.state.db(),
+        uma,
+        &Standing::Suspended {
+            until,
+            reason: "flame war".to_owned(),
+        },
+    )
+    .await
+    .unwrap();
+    let notice = format!(
+        "账号已被暂停至 {}, 期间只能浏览, 原因: flame war",
+        until.format("%Y-%m-%d %H:%M")
+    );
+
+    let (_, page) = app.get(&format!("/article/{article}"), Some(&token)).await;
+    assert!(page.contains("Standing"), "reading is unaffected");
+    let r = comment(&app, &token, article).await;
+    assert_eq!(r["msg"], notice.as_str(), "{r}");
+    let r = post_article(&app, &token).await;
+    assert_eq!(r["msg"], notice.as_str(), "{r}");
+    let r = app
+        .post(&format!("/delcomment/{earlier}"), &[], Some(&token))
+        .await;
+    assert_eq!(r["msg"], notice.as_str(), "{r}");
+    let r = app
+        .post(
+            "/profile/info",
+            &[("displayName", "Uma"), ("bio", "spam")],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["msg"], notice.as_str(), "{r}");
+    let (_, page) = app.get("/home/1", Some(&token)).await;
+    assert!(page.contains(&notice), "the reason is shown");
+
+    // Suspended users can still sign in
+    let r = app.login("uma", "secret-one").await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn suspensions_run_out() {
+    let app = TestApp::new().await;
+    let token = app.register("vera", "vera@example.com", "secret-two").await;
+    let r = post_article(&app, &token).await;
+    assert_eq!(r["code"], 200, "{r}");
+    let article = app.scalar("SELECT MAX(id) FROM article_tb").await;
+
+    let until = (Local::now() - Duration::minutes(1)).naive_local();
+    let vera = user_id(&app, "vera").await;
+    access::set_standing(
+        app.state.db(),
+        vera,
+        &Standing::Suspended {
+            until,
+            reason: String::new(),
+        },
+    )
+    .await
+    .unwrap();
+    let r = comment(&app, &token, article).await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn admins_suspend_with_a_future_date() {
+    let app = TestApp::new().await;
+    let root = app
+        .register("root", "root@example.com", "secret-root")
+        .await;
+    access::set_role_by_name(app.state.db(), "root", access::Role::Admin)
+        .await
+        .unwrap();
+    app.register("walt", "walt@example.com", "secret-three")
+        .await;
+    let walt = user_id(&app, "walt").await;
+    let path = format!("/admin/users/{walt}/status");
+
+    let r = app
+        .post(
+            &path,
+            &[("status", "suspended"), ("until", "2000-01-01")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["msg"], "暂停截止时间必须晚于当前时间", "{r}");
+    let r = app
+        .post(
+            &path,
+            &[("status", "suspended"), ("until", "soon")],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["msg"], "请填写正确的暂停截止时间", "{r}");
+
+    let until = (Local::now() + Duration::days(1)).format("%Y-%m-%dT%H:%M");
+    let r = app
+        .post(
+            &path,
+            &[
+                ("status", "suspended"),
+                ("until", &until.to_string()),
+                ("reason", "cool off"),
+            ],
+            Some(&root),
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let (_, page) = app.get("/admin/users/1", Some(&root)).await;
+    assert!(page.contains("已暂停") && page.contains("cool off"));
+
+    let r = app.post(&path, &[("status", "frozen")], Some(&root)).await;
+    assert_eq!(r["msg"], "未知的账号状态", "{r}");
+}
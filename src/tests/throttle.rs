@@ -0,0 +1,175 @@
+use super::TestApp;
+use crate::config::Quota;
+
+fn is_throttled(r: &serde_json::Value) -> bool {
+    r["code"] == 429
+        && r["msg"]
+            .as_str()
+            .is_some_and(|m| m.starts_with("请求过于频繁"))
+}
+
+#[tokio::test]
+async fn failed_logins_lock_the_account_until_the_window_ends() {
+    let app = TestApp::new().await;
+    app.register("rita", "rita@example.com", "secret-pass")
+        .await;
+
+    for _ in 0..5 {
+        let r = app.login("rita", "wrong-pass").await;
+        assert_eq!(r["code"], 400, "{r}");
+    }
+    let r = app.login("rita", "secret-pass").await;
+    assert!(is_throttled(&r), "{r}");
+    let r = app.login("someone-else", "whatever").await;
+    assert_eq!(r["code"], 400, "other accounts are unaffected: {r}");
+}
+
+#[tokio::test]
+async fn successful_login_clears_the_failure_count() {
+    let app = TestApp::new().await;
+    app.register("sam", "sam@example.com", "secret-pass").await;
+
+    for _ in 0..3 {
+        for _ in 0..4 {
+            assert_eq!(app.login("sam", "wrong-pass").await["code"], 400);
+        }
+        assert_eq!(app.login("sam", "secret-pass").await["code"], 200);
+    }
+}
+
+#[tokio::test]
+async fn login_is_limited_per_client_address() {
+    let app = TestApp::build(None, |config| {
+        config.behind_proxy = true;
+        config.rate_limits.login_per_ip = Quota {
+            max: 3,
+            window_secs: 60,
+        };
+    })
+    .await;
+    let form = |name| {
+        [
+            ("nickName", name),
+            ("password", "wrong-pass"),
+            ("rememberMe", "false"),
+        ]
+    };
+
+    for name in ["a", "b", "c"] {
+        let r = app.post_from("203.0.113.7", "/login", &form(name)).await;
+        assert_eq!(r["code"], 400, "{r}");
+    }
+    let r = app.post_from("203.0.113.7", "/login", &form("d")).await;
+    assert!(is_throttled(&r), "{r}");
+    let r = app.post_from("198.51.100.2", "/login", &form("d")).await;
+    assert_eq!(r["code"], 400, "another address has its own quota: {r}");
+}
+
+#[tokio::test]
+async fn spoofed_forwarded_addresses_do_not_escape_the_limit() {
+    let app = TestApp::build(None, |config| {
+        config.behind_proxy = true;
+        config.rate_limits.login_per_ip = Quota {
+            max: 3,
+            window_secs: 60,
+        };
+    })
+    .await;
+    let form = [
+        ("nickName", "nobody"),
+        ("password", "wrong-pass"),
+        ("rememberMe", "false"),
+    ];
+
+    // The client makes up the first entries, the proxy appends the real address
+    for fake in ["10.0.0.1", "10.0.0.2", "10.0.0.3"] {
+        let forwarded = format!("{fake}, 203.0.113.9");
+        let r = app.post_from(&forwarded, "/login", &form).await;
+        assert_eq!(r["code"], 400, "{r}");
+    }
+    let r = app
+        .post_from("10.0.0.4, 203.0.113.9", "/login", &form)
+        .await;
+    assert!(is_throttled(&r), "{r}");
+}
+
+#[tokio::test]
+async fn sendcode_is_limited_per_email() {
+    let app = TestApp::new().await;
+    for _ in 0..5 {
+        let r = app
+            .post("/sendcode", &[("email", "tom@example.com")], None)
+            .await;
+        assert_eq!(r["code"], 200, "{r}");
+    }
+    let r = app
+        .post("/sendcode", &[("email", "TOM@example.com")], None)
+        .await;
+    assert!(is_throttled(&r), "{r}");
+    assert_eq!(app.sent_mail().len(), 5);
+}
+
+#[tokio::test]
+async fn code_is_discarded_after_too_many_wrong_guesses() {
+    let app = TestApp::new().await;
+    let r = app
+        .post("/sendcode", &[("email", "uma@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("uma@example.com");
+    let wrong = if code == "000000" { "111111" } else { "000000" };
+    let register = |code| {
+        [
+            ("nickName", "uma"),
+            ("password", "secret-pass"),
+            ("password2", "secret-pass"),
+            ("email", "uma@example.com"),
+            ("code", code),
+        ]
+    };
+
+    for _ in 0..4 {
+        let r = app.post("/register", &register(wrong), None).await;
+        assert_eq!(r["msg"], "邮箱验证码错误", "{r}");
+    }
+    let r = app.post("/register", &register(wrong), None).await;
+    assert_eq!(r["msg"], "验证码错误次数过多, 请重新获取", "{r}");
+    let r = app.post("/register", &register(&code), None).await;
+    assert_eq!(r["code"], 400, "the right code no longer works: {r}");
+}
+
+#[tokio::test]
+async fn register_is_limited_per_email() {
+    let app = TestApp::build(None, |config| {
+        config.behind_proxy = true;
+        config.rate_limits.register_per_email = Quota {
+            max: 2,
+            window_secs: 60,
+        };
+    })
+    .await;
+    let form = |email| {
+        [
+            ("nickName", "vic"),
+            ("password", "secret-pass"),
+            ("password2", "secret-pass"),
+            ("email", email),
+            ("code", "000000"),
+        ]
+    };
+
+    for ip in ["203.0.113.1", "203.0.113.2"] {
+        let r = app
+            .post_from(ip, "/register", &form("vic@example.com"))
+            .await;
+        assert!(!is_throttled(&r), "{r}");
+    }
+    let r = app
+        .post_from("203.0.113.3", "/register", &form("VIC@example.com"))
+        .await;
+    assert!(is_throttled(&r), "{r}");
+    let r = app
+        .post_from("203.0.113.3", "/register", &form("wes@example.com"))
+        .await;
+    assert!(!is_throttled(&r), "another email has its own quota: {r}");
+}
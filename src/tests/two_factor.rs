@@ -0,0 +1,201 @@
+use serde_json::Value;
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use super::TestApp;
+
+/// The code an authenticator with `secret` shows during time step `step`.
+fn code_for_step(secret: &str, step: i64) -> String {
+    let bytes = Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .expect("base32 secret");
+    TOTP::new_unchecked(Algorithm::SHA1, 6, 1, 30, bytes, None, String::new())
+        .generate(step as u64 * 30)
+}
+
+fn current_step() -> i64 {
+    chrono::Utc::now().timestamp() / 30
+}
+
+/// Register `name` and switch on two-factor authentication, returning the
+/// session token, the secret and the recovery codes.
+async fn enrolled(app: &TestApp, name: &str, password: &str) -> (String, String, Vec<String>) {
+    let token = app
+        .register(name, &format!("{name}@example.com"), password)
+        .await;
+    let r = app.post("/profile/totp/setup", &[], Some(&token)).await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert!(
+        r["qr"].as_str().is_some_and(|qr| qr.contains("<svg")),
+        "{r}"
+    );
+    assert!(
+        r["url"]
+            .as_str()
+            .is_some_and(|url| url.starts_with("otpauth://totp/"))
+    );
+    let secret = r["secret"].as_str().expect("secret").to_owned();
+
+    let code = code_for_step(&secret, current_step());
+    let r = app
+        .post("/profile/totp/confirm", &[("code", &code)], Some(&token))
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let recovery: Vec<String> = r["recovery_codes"]
+        .as_array()
+        .expect("recovery codes")
+        .iter()
+        .map(|c| c.as_str().expect("code").to_owned())
+        .collect();
+    (token, secret, recovery)
+}
+
+async fn password_step(app: &TestApp, name: &str, password: &str) -> String {
+    let r = app.login(name, password).await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert_eq!(r["totp"], true, "{r}");
+    assert!(r["token"].is_null(), "no token before the second step: {r}");
+    r["ticket"].as_str().expect("ticket").to_owned()
+}
+
+async fn second_step(app: &TestApp, ticket: &str, code: &str) -> Value {
+    app.post("/login/totp", &[("ticket", ticket), ("code", code)], None)
+        .await
+}
+
+#[tokio::test]
+async fn enrollment_needs_a_valid_code() {
+    let app = TestApp::new().await;
+    let token = app.register("lena", "lena@example.com", "secret-1").await;
+    let (_, page) = app.get("/profile/totp", Some(&token)).await;
+    assert!(page.contains("开启两步验证"), "{page}");
+    app.post("/profile/totp/setup", &[], Some(&token)).await;
+
+    let r = app
+        .post("/profile/totp/confirm", &[("code", "000000")], Some(&token))
+        .await;
+    assert_eq!(r["code"], 400, "{r}");
+    let r = app.login("lena", "secret-1").await;
+    assert!(r["token"].is_string(), "not enabled until confirmed: {r}");
+}
+
+#[tokio::test]
+async fn enrolled_users_log_in_with_a_fresh_code() {
+    let app = TestApp::new().await;
+    let (_, secret, _) = enrolled(&app, "mike", "secret-2").await;
+
+    let ticket = password_step(&app, "mike", "secret-2").await;
+    let r = second_step(&app, &ticket, "123456").await;
+    assert_eq!(r["code"], 400, "{r}");
+
+    // The code used to confirm enrollment can't be replayed
+    let last = app
+        .scalar("SELECT totp_last_step FROM user_tb WHERE name = 'mike'")
+        .await;
+    let r = second_step(&app, &ticket, &code_for_step(&secret, last)).await;
+    assert_eq!(r["code"], 400, "{r}");
+
+    let r = second_step(&app, &ticket, &code_for_step(&secret, last + 1)).await;
+    assert_eq!(r["code"], 200, "{r}");
+    assert!(r["token"].is_string());
+
+    // Tickets are single use
+    let r = second_step(&app, &ticket, &code_for_step(&secret, last + 1)).await;
+    assert_eq!(r["msg"], "两步验证已过期, 请重新登录", "{r}");
+}
+
+#[tokio::test]
+async fn recovery_codes_work_once() {
+    let app = TestApp::new().await;
+    let (_, _, recovery) = enrolled(&app, "nina", "secret-3").await;
+    assert_eq!(recovery.len(), 10);
+
+    let ticket = password_step(&app, "nina", "secret-3").await;
+    let r = second_step(&app, &ticket, &recovery[0].to_uppercase()).await;
+    assert_eq!(r["code"], 200, "{r}");
+
+    let ticket = password_step(&app, "nina", "secret-3").await;
+    let r = second_step(&app, &ticket, &recovery[0]).await;
+    assert_eq!(r["code"], 400, "used recovery code accepted again: {r}");
+}
+
+#[tokio::test]
+async fn second_step_shares_the_password_throttle() {
+    let app = TestApp::new().await;
+    enrolled(&app, "omar", "secret-4").await;
+    let limit = app.state.rate_limits().login_per_account.max;
+
+    // The password step counts as one attempt and doesn't clear the count
+    let ticket = password_step(&app, "omar", "secret-4").await;
+    for _ in 1..limit {
+        let r = second_step(&app, &ticket, "000000").await;
+        assert_eq!(r["code"], 400, "{r}");
+    }
+    let r = second_step(&app, &ticket, "000000").await;
+    assert_eq!(r["code"], 429, "{r}");
+    let r = app.login("omar", "secret-4").await;
+    assert_eq!(r["code"], 429, "{r}");
+}
+
+async fn disable(app: &TestApp, token: &str, password: &str, code: &str) -> Value {
+    app.post(
+        "/profile/totp/disable",
+        &[("password", password), ("code", code)],
+        Some(token),
+    )
+    .await
+}
+
+#[tokio::test]
+async fn disabling_requires_the_password_and_a_code() {
+    let app = TestApp::new().await;
+    let (token, secret, recovery) = enrolled(&app, "pia", "secret-5").await;
+
+    let last = app
+        .scalar("SELECT totp_last_step FROM user_tb WHERE name = 'pia'")
+        .await;
+    let fresh = code_for_step(&secret, last + 1);
+    let r = disable(&app, &token, "wrong", &fresh).await;
+    assert_eq!(r["msg"], "密码错误", "{r}");
+    // The password alone doesn't do it
+    let r = disable(&app, &token, "secret-5", "").await;
+    assert_eq!(r["msg"], "两步验证码错误", "{r}");
+    let r = disable(&app, &token, "secret-5", "000000").await;
+    assert_eq!(r["msg"], "两步验证码错误", "{r}");
+    let r = app.login("pia", "secret-5").await;
+    assert_eq!(r["totp"], true, "still enabled: {r}");
+
+    let r = disable(&app, &token, "secret-5", &recovery[3]).await;
+    assert_eq!(r["code"], 200, "{r}");
+    let r = app.login("pia", "secret-5").await;
+    assert!(r["token"].is_string(), "{r}");
+    assert_eq!(app.scalar("SELECT COUNT(*) FROM recovery_code_tb").await, 0);
+}
+
+#[tokio::test]
+async fn password_guesses_on_the_settings_page_are_throttled() {
+    let app = TestApp::new().await;
+    let (token, _, _) = enrolled(&app, "quin", "secret-6").await;
+    let limit = app.state.rate_limits().login_per_account.max;
+
+    for _ in 0..limit {
+        let r = app
+            .post(
+                "/profile/totp/recovery",
+                &[("password", "wrong")],
+                Some(&token),
+            )
+            .await;
+        assert_eq!(r["msg"], "密码错误", "{r}");
+    }
+    // Not even the right password gets through now, here or when disabling
+    let r = app
+        .post(
+            "/profile/totp/recovery",
+            &[("password", "secret-6")],
+            Some(&token),
+        )
+        .await;
+    assert_eq!(r["code"], 429, "{r}");
+    let r = disable(&app, &token, "secret-6", "").await;
+    assert_eq!(r["code"], 429, "{r}");
+}
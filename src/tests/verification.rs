@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use super::TestApp;
+use crate::home::verification;
+
+fn reset_form<'a>(name: &'a str, email: &'a str, code: &'a str) -> [(&'a str, &'a str); 5] {
+    [
+        ("nickName", name),
+        ("password", "secret-new"),
+        ("password2", "secret-new"),
+        ("email", email),
+        ("code", code),
+    ]
+}
+
+#[test]
+fn generated_codes_use_every_digit() {
+    let mut seen = [false; 10];
+    for _ in 0..200 {
+        let code = verification::generate(6);
+        assert_eq!(code.len(), 6);
+        for digit in code.bytes() {
+            seen[usize::from(digit - b'0')] = true;
+        }
+    }
+    assert!(seen.iter().all(|&s| s), "{seen:?}");
+}
+
+#[tokio::test]
+async fn codes_have_the_configured_length() {
+    let app = TestApp::build(None, |config| config.code_length = 8).await;
+    let r = app
+        .post("/sendcode", &[("email", "vera@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("vera@example.com");
+    assert_eq!(code.len(), 8, "{code}");
+}
+
+#[tokio::test]
+async fn codes_only_work_for_their_purpose() {
+    let app = TestApp::new().await;
+    app.register("walt", "walt@example.com", "secret-old").await;
+
+    let r = app
+        .post("/sendcode", &[("email", "walt@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let register_code = app.last_code_sent_to("walt@example.com");
+    let r = app
+        .post(
+            "/forget",
+            &reset_form("walt", "walt@example.com", &register_code),
+            None,
+        )
+        .await;
+    assert_eq!(
+        r["code"], 400,
+        "a registration code must not reset a password: {r}"
+    );
+
+    let r = app
+        .post(
+            "/sendcode",
+            &[("email", "walt@example.com"), ("purpose", "reset")],
+            None,
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let reset_code = app.last_code_sent_to("walt@example.com");
+    let mail = app.sent_mail();
+    assert!(mail.last().unwrap().contains("Subject: 重置密码验证码"));
+    let r = app
+        .post(
+            "/forget",
+            &reset_form("walt", "walt@example.com", &reset_code),
+            None,
+        )
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+}
+
+#[tokio::test]
+async fn unknown_purpose_is_rejected() {
+    let app = TestApp::new().await;
+    let r = app
+        .post(
+            "/sendcode",
+            &[("email", "xena@example.com"), ("purpose", "login")],
+            None,
+        )
+        .await;
+    assert_eq!(r["code"], 400, "{r}");
+    assert!(app.sent_mail().is_empty());
+}
+
+#[tokio::test]
+async fn codes_expire_after_the_configured_ttl() {
+    let app = TestApp::build(None, |config| config.code_ttl_secs = 1).await;
+    let r = app
+        .post("/sendcode", &[("email", "yuri@example.com")], None)
+        .await;
+    assert_eq!(r["code"], 200, "{r}");
+    let code = app.last_code_sent_to("yuri@example.com");
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+
+    let r = app
+        .post(
+            "/register",
+            &[
+                ("nickName", "yuri"),
+                ("password", "secret-pass"),
+                ("password2", "secret-pass"),
+                ("email", "yuri@example.com"),
+                ("code", &code),
+            ],
+            None,
+        )
+        .await;
+    assert_eq!(r["msg"], "未通过邮箱验证, 未找到邮箱的验证码", "{r}");
+}